@@ -4,106 +4,612 @@
 #![recursion_limit = "128"]
 
 extern crate proc_macro;
+extern crate proc_macro2;
 #[macro_use]
 extern crate quote;
 #[macro_use]
 extern crate syn;
 
 use proc_macro::TokenStream;
-use quote::Tokens;
-use syn::punctuated::Punctuated;
-use syn::synom::Synom;
-use syn::token::Comma;
-use syn::{Data, DataEnum, DeriveInput, Fields, Ident, LitStr, Path, Variant};
+use proc_macro2::TokenStream as Tokens;
+use quote::ToTokens;
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, Data, DataEnum, DataStruct, DeriveInput, Error, Fields, Ident, LitStr, Meta, Path,
+    Type, Variant,
+};
 
-/// Derives the boilerplate code for a Dispatcher
+/// Derives the boilerplate code for a Dispatcher. Events dispatched for commands that
+/// implement `Command` take the command's effective time unless they set their own.
 #[proc_macro_derive(Dispatcher, attributes(aggregate))]
 pub fn component(input: TokenStream) -> TokenStream {
-    let ast = syn::parse(input).unwrap();
-    let gen = impl_component(&ast);
+    let ast = parse_macro_input!(input as DeriveInput);
+    let gen = impl_component(&ast).unwrap_or_else(Error::into_compile_error);
     gen.into()
 }
 
 /// Derives the boilerplate code for an Event
-#[proc_macro_derive(Event, attributes(event_type_version, event_source))]
+///
+/// The enum-level `#[event_type_version(...)]` and `#[event_source("...")]` attributes
+/// apply to every variant. The version can be a string literal or the path of a `&str`
+/// constant. Event types are named `enum.variant`, in lower case by default;
+/// `#[event_naming("kebab-case")]` or `#[event_naming("snake_case")]` change the casing, and
+/// `#[event_type_prefix("com.acme.bank")]` replaces the enum name with a fixed prefix.
+///
+/// Individual variants can override their type with `#[event_type("...")]`, as well as
+/// their `#[event_type_version(...)]` and `#[event_source("...")]`. Types a variant was
+/// previously persisted under can be listed with `#[event_alias("...")]`; events with those
+/// types are accepted when converting cloud events back into the enum.
+///
+/// Structs (with named or unnamed fields) can be events on their own. Their event type is
+/// the struct name, cased and prefixed in the same way, unless set with `#[event_type("...")]`.
+/// To group such structs under a single `Aggregate::Event` type, derive `Event` on an enum
+/// marked `#[event_delegate]` whose variants each wrap one event struct; the type, version
+/// and source of each event then come from the wrapped struct.
+///
+/// A field holding the time at which the event took effect in the business, as opposed to
+/// when it was recorded, can be marked `#[effective_time]`. The field must be a
+/// `DateTime<Utc>` or an `Option<DateTime<Utc>>`.
+///
+/// A field naming the subject the event is about, such as the person whose personal data it
+/// holds, can be marked `#[subject]`. The field must be a `String` or an `Option<String>`.
+///
+/// Fields holding personal data can be marked `#[encrypted]`, so that stores encrypting
+/// events encrypt only those fields rather than the whole data. The fields are located the
+/// way serde serializes them, honoring `#[serde(rename, rename_all, tag, content,
+/// untagged)]`; marked fields can't be skipped or flattened. Once a field's key is deleted
+/// it reads as `null`, so marked fields should deserialize from `null`, as `Option`s do.
+///
+/// Events also get an `EventCatalog`, listing the event types and deserializing event data
+/// by type, so they must implement `Deserialize`. Events that are only ever appended can be
+/// marked `#[event_no_catalog]` to go without one, and then need only implement `Serialize`;
+/// aggregates can't load such events, and a delegating enum needs the catalogs of the events
+/// it wraps. With the `orgeventstore` feature events with a catalog get `TryFrom<CloudEvent>`
+/// too, which deserializes through the catalog as `CloudEvent::to_event` does.
+#[proc_macro_derive(
+    Event,
+    attributes(
+        event_type_version,
+        event_source,
+        event_type,
+        event_alias,
+        event_naming,
+        event_type_prefix,
+        event_delegate,
+        event_no_catalog,
+        effective_time,
+        encrypted,
+        subject
+    )
+)]
 pub fn component_event(input: TokenStream) -> TokenStream {
-    let ast: DeriveInput = syn::parse(input).unwrap();
+    let ast = parse_macro_input!(input as DeriveInput);
     let gen = match ast.data {
+        Data::Enum(ref data_enum) if has_attr(&ast.attrs, "event_delegate") => {
+            impl_delegate_event(&ast, data_enum)
+        }
         Data::Enum(ref data_enum) => impl_component_event(&ast, data_enum),
-        Data::Struct(_) => quote! {
-            panic!("#[derive(Event)] is only defined for enums, not structs")
-        },
-        Data::Union(_) => quote! {
-            panic!("#[derive(Event)] is only defined for enums, not unions")
-        },
+        Data::Struct(_) => impl_struct_event(&ast),
+        Data::Union(ref data_union) => Err(Error::new(
+            data_union.union_token.span,
+            "#[derive(Event)] is only defined for enums and structs, not unions",
+        )),
     };
 
-    gen.into()
+    gen.unwrap_or_else(Error::into_compile_error).into()
 }
 
-struct EventSourceAttribute {
-    event_source: LitStr,
+/// Derives `EventSchema`, describing the data of every event type as a JSON Schema. The
+/// derive reads the same attributes as `#[derive(Event)]`, and every field type must
+/// implement `eventsourcing::schema::JsonSchema`.
+///
+/// The schemas follow serde's `rename`, `rename_all`, `rename_all_fields` and `skip`
+/// attributes. Fields marked `#[serde(default)]` or `#[serde(skip_serializing_if)]` are
+/// optional. Data serde doesn't lay out itself fails the derive, which covers flattened
+/// fields, fields with custom serializers and enums that aren't externally tagged.
+#[proc_macro_derive(
+    EventSchema,
+    attributes(
+        event_type_version,
+        event_source,
+        event_type,
+        event_alias,
+        event_naming,
+        event_type_prefix,
+        event_delegate,
+        event_no_catalog,
+        effective_time,
+        encrypted,
+        subject
+    )
+)]
+pub fn component_event_schema(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let gen = match ast.data {
+        Data::Enum(ref data_enum) if has_attr(&ast.attrs, "event_delegate") => {
+            impl_delegate_event_schema(&ast, data_enum)
+        }
+        Data::Enum(ref data_enum) => impl_component_event_schema(&ast, data_enum),
+        Data::Struct(ref data_struct) => impl_struct_event_schema(&ast, data_struct),
+        Data::Union(ref data_union) => Err(Error::new(
+            data_union.union_token.span,
+            "#[derive(EventSchema)] is only defined for enums and structs, not unions",
+        )),
+    };
+
+    gen.unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Derives the boilerplate code for a Command
+///
+/// Command types are named `enum.variant` (or after the struct), cased with
+/// `#[command_naming("...")]` like event types; variants can set their type with
+/// `#[command_type("...")]`. Every variant must mark the field holding the ID of the
+/// aggregate instance it targets with `#[aggregate_id]`, and can mark the field holding the
+/// effective time of the events it produces with `#[effective_time]`.
+#[proc_macro_derive(
+    Command,
+    attributes(command_type, command_naming, aggregate_id, effective_time)
+)]
+pub fn component_command(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let gen = match ast.data {
+        Data::Enum(ref data_enum) => impl_component_command(&ast, data_enum),
+        Data::Struct(ref data_struct) => impl_struct_command(&ast, data_struct),
+        Data::Union(ref data_union) => Err(Error::new(
+            data_union.union_token.span,
+            "#[derive(Command)] is only defined for enums and structs, not unions",
+        )),
+    };
+
+    gen.unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Derives the boilerplate code for AggregateState, using the field marked `#[generation]`
+/// (or, if no field is marked, the field named `generation`) as the state generation
+#[proc_macro_derive(AggregateState, attributes(generation))]
+pub fn component_aggregate_state(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let gen = match ast.data {
+        Data::Struct(ref data_struct) => impl_component_aggregate_state(&ast, data_struct),
+        Data::Enum(ref data_enum) => Err(Error::new(
+            data_enum.enum_token.span,
+            "#[derive(AggregateState)] is only defined for structs, not enums",
+        )),
+        Data::Union(ref data_union) => Err(Error::new(
+            data_union.union_token.span,
+            "#[derive(AggregateState)] is only defined for structs, not unions",
+        )),
+    };
+
+    gen.unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// The value of an `event_type_version` attribute: a string literal or the path of a constant
+enum VersionValue {
+    Literal(LitStr),
+    Const(Path),
+}
+
+impl Parse for VersionValue {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitStr) {
+            input.parse().map(VersionValue::Literal)
+        } else if input.peek(Ident) || input.peek(Token![::]) {
+            input.parse().map(VersionValue::Const)
+        } else {
+            Err(input.error("expected a string literal or the path of a &str constant"))
+        }
+    }
 }
 
-struct EventTypeVersionAttribute {
-    event_type_version: Ident,
+impl ToTokens for VersionValue {
+    fn to_tokens(&self, tokens: &mut Tokens) {
+        match *self {
+            VersionValue::Literal(ref lit) => lit.to_tokens(tokens),
+            VersionValue::Const(ref path) => path.to_tokens(tokens),
+        }
+    }
 }
 
-struct AggregateAttribute {
-    aggregate: Path,
+/// Parses the value of `#[name(value)]` or `#[name = value]`
+fn attr_value<T: Parse>(attr: &Attribute) -> syn::Result<T> {
+    match attr.meta {
+        Meta::List(ref list) => list.parse_args(),
+        Meta::NameValue(ref name_value) => syn::parse2(name_value.value.to_token_stream()),
+        Meta::Path(ref path) => Err(Error::new_spanned(
+            path,
+            format!(
+                "expected a value, as in #[{}(...)]",
+                path.to_token_stream().to_string().replace(' ', "")
+            ),
+        )),
+    }
 }
 
-impl Synom for EventSourceAttribute {
-    named!(parse -> Self, map!(
-        parens!(syn!(LitStr)),
-        |(_, event_source)| EventSourceAttribute { event_source }
-    ));
+/// Parses every attribute with the given name
+fn attr_values<T: Parse>(attrs: &[Attribute], name: &str) -> syn::Result<Vec<T>> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident(name))
+        .map(attr_value)
+        .collect()
 }
 
-impl Synom for AggregateAttribute {
-    named!(parse -> Self, map!(
-        parens!(syn!(Path)),
-        |(_, aggregate)| AggregateAttribute { aggregate }
-    ));
+/// Parses the attribute with the given name, rejecting duplicates
+fn single_attr<T: Parse>(attrs: &[Attribute], name: &str) -> syn::Result<Option<T>> {
+    let mut found = attrs.iter().filter(|attr| attr.path().is_ident(name));
+    let value = match found.next() {
+        Some(attr) => Some(attr_value(attr)?),
+        None => None,
+    };
+    match found.next() {
+        Some(duplicate) => Err(Error::new_spanned(
+            duplicate,
+            format!("duplicate #[{}] attribute", name),
+        )),
+        None => Ok(value),
+    }
 }
 
-impl Synom for EventTypeVersionAttribute {
-    named!(parse -> Self, map!(
-        parens!(syn!(Ident)),
-        |(_, event_type_version) | EventTypeVersionAttribute { event_type_version }
-    ));
+/// Whether a bare marker attribute such as `#[event_delegate]` is present
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().any(|attr| attr.path().is_ident(name))
 }
 
-fn impl_component_event(ast: &DeriveInput, data_enum: &DataEnum) -> Tokens {
+/// The where clause of an event's catalog: the type's own, requiring that the event
+/// deserializes. The bound is spanned to the type name, so an event that doesn't implement
+/// `Deserialize` (and isn't marked `#[event_no_catalog]`) fails to compile there.
+fn catalog_where_clause(ast: &DeriveInput) -> Tokens {
+    let name = &ast.ident;
+    let (_, ty_generics, _) = ast.generics.split_for_impl();
+    let predicates = ast
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|w| w.predicates.iter());
+    let bound = quote_spanned! {name.span()=>
+        #name #ty_generics: ::eventsourcing::__private::DeserializeOwned
+    };
+    quote! { where #(#predicates,)* #bound }
+}
+
+/// `TryFrom<CloudEvent>`, deserializing through the event's catalog
+fn try_from_cloud_event(ast: &DeriveInput, where_clause: &Tokens) -> Tokens {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, _) = ast.generics.split_for_impl();
+    quote! {
+        #[cfg(feature = "orgeventstore")]
+        impl #impl_generics ::std::convert::TryFrom<::eventsourcing::cloudevents::CloudEvent>
+            for #name #ty_generics #where_clause
+        {
+            type Error = ::eventsourcing::Error;
+
+            fn try_from(
+                __source: ::eventsourcing::cloudevents::CloudEvent,
+            ) -> ::eventsourcing::Result<Self> {
+                __source.to_event()
+            }
+        }
+    }
+}
+
+fn event_naming(attrs: &[Attribute]) -> syn::Result<Naming> {
+    match single_attr::<LitStr>(attrs, "event_naming")? {
+        Some(naming) => Naming::parse(&naming),
+        None => Ok(Naming::Lowercase),
+    }
+}
+
+fn impl_component_event(ast: &DeriveInput, data_enum: &DataEnum) -> syn::Result<Tokens> {
     let name = &ast.ident;
     let variants = &data_enum.variants;
-    let (impl_generics, _ty_generics, where_clause) = ast.generics.split_for_impl();
-    let event_type_version = ast
-        .attrs
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let mut event_matches = Vec::new();
+    let mut version_matches = Vec::new();
+    let mut source_matches = Vec::new();
+    let mut type_matches = Vec::new();
+    let mut time_matches = Vec::new();
+    let mut subject_matches = Vec::new();
+    let mut encrypted_matches = Vec::new();
+    let mut event_types = Vec::new();
+    let container = serde_attrs(&ast.attrs)?;
+    for (variant, meta) in variants.iter().zip(enum_metadata(ast, data_enum)?) {
+        let id = &variant.ident;
+        let EventMetadata {
+            event_type,
+            event_type_version,
+            event_source,
+            aliases,
+        } = meta;
+        event_matches.push(quote! { #name::#id { .. } => #event_type, });
+        version_matches.push(quote! { #name::#id { .. } => #event_type_version, });
+        source_matches.push(quote! { #name::#id { .. } => #event_source, });
+        time_matches.push(effective_time_arm(name, variant)?);
+        subject_matches.push(subject_arm(name, variant)?);
+        let pointers = if marks_encrypted(&variant.fields) {
+            encrypted_pointers(
+                &variant.fields,
+                variant_field_rule(variant, &container)?,
+                &encrypted_variant_prefix(variant, &container)?,
+            )?
+        } else {
+            Vec::new()
+        };
+        encrypted_matches.push(quote! {
+            #name::#id { .. } => Some(vec![#(#pointers.to_owned()),*]),
+        });
+
+        let variant_s = id.to_string();
+        let mut types = vec![event_type.clone()];
+        types.extend(aliases);
+        type_matches.push(quote! { #(#types)|* => Some(#variant_s), });
+        event_types.push(event_type);
+    }
+    let encrypted_fields = if variants
         .iter()
-        .find(|attr| attr.path.segments[0].ident == "event_type_version")
-        .map(|attr| {
-            syn::parse2::<EventTypeVersionAttribute>(attr.tts.clone())
-                .unwrap()
-                .event_type_version
+        .any(|variant| marks_encrypted(&variant.fields))
+    {
+        Some(quote! {
+            fn encrypted_fields(&self) -> Option<Vec<String>> {
+                match *self {
+                    #(#encrypted_matches)*
+                }
+            }
         })
-        .unwrap_or_else(|| parse_quote!(NoSchemaVersion));
+    } else {
+        None
+    };
 
-    let event_source = ast
-        .attrs
-        .iter()
-        .find(|attr| attr.path.segments[0].ident == "event_source")
-        .map(|attr| {
-            syn::parse2::<EventSourceAttribute>(attr.tts.clone())
-                .unwrap()
-                .event_source
+    let catalog = if has_attr(&ast.attrs, "event_no_catalog") {
+        None
+    } else {
+        let catalog_where = catalog_where_clause(ast);
+        let try_from = try_from_cloud_event(ast, &catalog_where);
+        Some(quote! {
+            impl #impl_generics ::eventsourcing::EventCatalog for #name #ty_generics #catalog_where {
+                const EVENT_TYPES: &'static [&'static str] = &[#(#event_types),*];
+
+                fn is_known_type(event_type: &str) -> bool {
+                    let __variant: Option<&str> = match event_type {
+                        #(#type_matches)*
+                        _ => None,
+                    };
+                    __variant.is_some()
+                }
+
+                fn deserialize_by_type(
+                    event_type: &str,
+                    data: &::eventsourcing::__private::Value,
+                ) -> ::eventsourcing::Result<Self> {
+                    let __variant: Option<&str> = match event_type {
+                        #(#type_matches)*
+                        _ => None,
+                    };
+                    match __variant {
+                        Some(variant) => ::eventsourcing::__private::deserialize_event(
+                            event_type,
+                            data,
+                            Some(variant),
+                        ),
+                        None => Err(::eventsourcing::__private::unknown_event_type(event_type)),
+                    }
+                }
+            }
+            #try_from
         })
-        .unwrap_or_else(|| parse_quote!(NoEventSource));
+    };
+    Ok(quote! {
+        impl #impl_generics ::eventsourcing::Event for #name #ty_generics #where_clause {
+            fn event_type_version(&self) -> &str {
+                match self {
+                    #(#version_matches)*
+                }
+            }
 
-    let event_matches = generate_event_matches(&name, &variants);
+            fn event_source(&self) -> &str {
+                match self {
+                    #(#source_matches)*
+                }
+            }
 
-    quote! {
-        impl #impl_generics ::eventsourcing::Event for #name #where_clause {
+            fn event_type(&self) -> &str {
+                match self {
+                    #(#event_matches)*
+                }
+            }
+
+            fn effective_time(&self) -> Option<::eventsourcing::__private::DateTime> {
+                match *self {
+                    #(#time_matches)*
+                }
+            }
+
+            fn subject(&self) -> Option<String> {
+                match *self {
+                    #(#subject_matches)*
+                }
+            }
+
+            #encrypted_fields
+        }
+        #catalog
+    })
+}
+
+fn missing_attr(name: &Ident, variant: Option<&Variant>, attr: &str, example: &str) -> Error {
+    let msg = match variant {
+        Some(variant) => format!(
+            "variant {} has no #[{}]; add {} to the enum or to the variant",
+            variant.ident, attr, example
+        ),
+        None => format!("event {} has no #[{}]; add {}", name, attr, example),
+    };
+    Error::new(name.span(), msg)
+}
+
+/// The event type, version and source of a struct event or an enum variant
+struct EventMetadata {
+    event_type: String,
+    event_type_version: Tokens,
+    event_source: LitStr,
+    aliases: Vec<String>,
+}
+
+fn enum_metadata(ast: &DeriveInput, data_enum: &DataEnum) -> syn::Result<Vec<EventMetadata>> {
+    let name = &ast.ident;
+    let event_type_version: Option<VersionValue> = single_attr(&ast.attrs, "event_type_version")?;
+    let event_source: Option<LitStr> = single_attr(&ast.attrs, "event_source")?;
+    let naming = event_naming(&ast.attrs)?;
+    let prefix = single_attr::<LitStr>(&ast.attrs, "event_type_prefix")?.map(|p| p.value());
+
+    let mut result = Vec::new();
+    for variant in data_enum.variants.iter() {
+        let event_type = event_type_name(name, variant, naming, prefix.as_ref())?;
+
+        let version = match single_attr::<VersionValue>(&variant.attrs, "event_type_version")? {
+            Some(version) => version.into_token_stream(),
+            None => match event_type_version {
+                Some(ref version) => version.into_token_stream(),
+                None => {
+                    return Err(missing_attr(
+                        name,
+                        Some(variant),
+                        "event_type_version",
+                        "#[event_type_version(DOMAIN_VERSION)]",
+                    ))
+                }
+            },
+        };
+
+        let source = match single_attr::<LitStr>(&variant.attrs, "event_source")? {
+            Some(source) => source,
+            None => match event_source {
+                Some(ref source) => source.clone(),
+                None => {
+                    return Err(missing_attr(
+                        name,
+                        Some(variant),
+                        "event_source",
+                        "#[event_source(\"events://...\")]",
+                    ))
+                }
+            },
+        };
+
+        let aliases = attr_values::<LitStr>(&variant.attrs, "event_alias")?
+            .iter()
+            .map(LitStr::value)
+            .collect();
+        result.push(EventMetadata {
+            event_type,
+            event_type_version: version,
+            event_source: source,
+            aliases,
+        });
+    }
+    Ok(result)
+}
+
+fn struct_metadata(ast: &DeriveInput) -> syn::Result<EventMetadata> {
+    let name = &ast.ident;
+    let event_type_version: VersionValue = single_attr(&ast.attrs, "event_type_version")?
+        .ok_or_else(|| {
+            missing_attr(
+                name,
+                None,
+                "event_type_version",
+                "#[event_type_version(DOMAIN_VERSION)]",
+            )
+        })?;
+    let event_source: LitStr = single_attr(&ast.attrs, "event_source")?.ok_or_else(|| {
+        missing_attr(
+            name,
+            None,
+            "event_source",
+            "#[event_source(\"events://...\")]",
+        )
+    })?;
+
+    let naming = event_naming(&ast.attrs)?;
+    let prefix = single_attr::<LitStr>(&ast.attrs, "event_type_prefix")?.map(|p| p.value());
+    let event_type = match single_attr::<LitStr>(&ast.attrs, "event_type")? {
+        Some(event_type) => event_type.value(),
+        None => match prefix {
+            Some(prefix) => format!("{}.{}", prefix, naming.apply(&name.to_string())),
+            None => naming.apply(&name.to_string()),
+        },
+    };
+    let aliases = attr_values::<LitStr>(&ast.attrs, "event_alias")?
+        .iter()
+        .map(LitStr::value)
+        .collect();
+    Ok(EventMetadata {
+        event_type,
+        event_type_version: event_type_version.into_token_stream(),
+        event_source,
+        aliases,
+    })
+}
+
+fn impl_struct_event(ast: &DeriveInput) -> syn::Result<Tokens> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let EventMetadata {
+        event_type: et_name,
+        event_type_version,
+        event_source,
+        aliases,
+    } = struct_metadata(ast)?;
+    let mut types = vec![et_name.clone()];
+    types.extend(aliases);
+    let effective_time = struct_effective_time(ast)?;
+    let subject = struct_subject(ast)?;
+    let encrypted_fields = match ast.data {
+        Data::Struct(ref data_struct) if marks_encrypted(&data_struct.fields) => {
+            let pointers = struct_encrypted_pointers(ast, &data_struct.fields)?;
+            Some(quote! {
+                fn encrypted_fields(&self) -> Option<Vec<String>> {
+                    Some(vec![#(#pointers.to_owned()),*])
+                }
+            })
+        }
+        _ => None,
+    };
+
+    let catalog = if has_attr(&ast.attrs, "event_no_catalog") {
+        None
+    } else {
+        let catalog_where = catalog_where_clause(ast);
+        let try_from = try_from_cloud_event(ast, &catalog_where);
+        Some(quote! {
+            impl #impl_generics ::eventsourcing::EventCatalog for #name #ty_generics #catalog_where {
+                const EVENT_TYPES: &'static [&'static str] = &[#et_name];
+
+                fn is_known_type(event_type: &str) -> bool {
+                    match event_type {
+                        #(#types)|* => true,
+                        _ => false,
+                    }
+                }
+
+                fn deserialize_by_type(
+                    event_type: &str,
+                    data: &::eventsourcing::__private::Value,
+                ) -> ::eventsourcing::Result<Self> {
+                    if <Self as ::eventsourcing::EventCatalog>::is_known_type(event_type) {
+                        ::eventsourcing::__private::deserialize_event(event_type, data, None)
+                    } else {
+                        Err(::eventsourcing::__private::unknown_event_type(event_type))
+                    }
+                }
+            }
+            #try_from
+        })
+    };
+    Ok(quote! {
+        impl #impl_generics ::eventsourcing::Event for #name #ty_generics #where_clause {
             fn event_type_version(&self) -> &str {
                 #event_type_version
             }
@@ -113,89 +619,1035 @@ fn impl_component_event(ast: &DeriveInput, data_enum: &DataEnum) -> Tokens {
             }
 
             fn event_type(&self) -> &str {
-                match self {
-                    #(#event_matches)*
+                #et_name
+            }
+
+            #effective_time
+
+            #subject
+
+            #encrypted_fields
+        }
+        #catalog
+    })
+}
+
+/// The variants of an `#[event_delegate]` enum along with the event type each one wraps
+fn delegate_variants(data_enum: &DataEnum) -> syn::Result<Vec<(&Ident, &Type)>> {
+    data_enum
+        .variants
+        .iter()
+        .map(|variant| match variant.fields {
+            Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => {
+                Ok((&variant.ident, &fields.unnamed[0].ty))
+            }
+            _ => Err(Error::new_spanned(
+                variant,
+                "#[event_delegate] variants must wrap exactly one event, as in Variant(EventStruct)",
+            )),
+        })
+        .collect()
+}
+
+fn impl_delegate_event(ast: &DeriveInput, data_enum: &DataEnum) -> syn::Result<Tokens> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let mut version_arms = Vec::new();
+    let mut source_arms = Vec::new();
+    let mut type_arms = Vec::new();
+    let mut time_arms = Vec::new();
+    let mut subject_arms = Vec::new();
+    let mut encrypted_arms = Vec::new();
+    let mut inner_types = Vec::new();
+    let mut constructors = Vec::new();
+    let container = serde_attrs(&ast.attrs)?;
+    for (variant, (id, inner)) in data_enum.variants.iter().zip(delegate_variants(data_enum)?) {
+        inner_types.push(quote! { <#inner as ::eventsourcing::EventCatalog> });
+        let variant_s = id.to_string();
+        constructors.push(quote! { (#name::#id, #variant_s) });
+        version_arms.push(quote! {
+            #name::#id(ref __evt) => ::eventsourcing::Event::event_type_version(__evt),
+        });
+        source_arms.push(quote! {
+            #name::#id(ref __evt) => ::eventsourcing::Event::event_source(__evt),
+        });
+        type_arms.push(quote! {
+            #name::#id(ref __evt) => ::eventsourcing::Event::event_type(__evt),
+        });
+        time_arms.push(quote! {
+            #name::#id(ref __evt) => ::eventsourcing::Event::effective_time(__evt),
+        });
+        subject_arms.push(quote! {
+            #name::#id(ref __evt) => ::eventsourcing::Event::subject(__evt),
+        });
+        let prefix = match container.tagging() {
+            Tagging::External => format!(
+                "/{}",
+                pointer_token(&serde_variant_name(variant, &container)?)
+            ),
+            Tagging::Adjacent(content) => format!("/{}", pointer_token(content)),
+            Tagging::Internal | Tagging::Untagged => String::new(),
+        };
+        encrypted_arms.push(quote! {
+            #name::#id(ref __evt) => ::eventsourcing::Event::encrypted_fields(__evt).map(|__fields| {
+                __fields.into_iter().map(|__field| format!("{}{}", #prefix, __field)).collect()
+            }),
+        });
+    }
+
+    let catalog = if has_attr(&ast.attrs, "event_no_catalog") {
+        None
+    } else {
+        let try_from = try_from_cloud_event(ast, &quote!(#where_clause));
+        Some(quote! {
+            impl #impl_generics ::eventsourcing::EventCatalog for #name #ty_generics #where_clause {
+                const EVENT_TYPES: &'static [&'static str] = {
+                    const LEN: usize = 0 #(+ #inner_types::EVENT_TYPES.len())*;
+                    const TYPES: [&str; LEN] = {
+                        let mut types = [""; LEN];
+                        let mut i = 0;
+                        #(
+                            let mut j = 0;
+                            while j < #inner_types::EVENT_TYPES.len() {
+                                types[i] = #inner_types::EVENT_TYPES[j];
+                                i += 1;
+                                j += 1;
+                            }
+                        )*
+                        let _ = i;
+                        types
+                    };
+                    &TYPES
+                };
+
+                fn is_known_type(event_type: &str) -> bool {
+                    false #(|| #inner_types::is_known_type(event_type))*
+                }
+
+                fn deserialize_by_type(
+                    event_type: &str,
+                    data: &::eventsourcing::__private::Value,
+                ) -> ::eventsourcing::Result<Self> {
+                    #(
+                        if #inner_types::is_known_type(event_type) {
+                            let (__variant, __name) = #constructors;
+                            let __data = ::eventsourcing::__private::untag(data, __name);
+                            return #inner_types::deserialize_by_type(event_type, __data).map(__variant);
+                        }
+                    )*
+                    Err(::eventsourcing::__private::unknown_event_type(event_type))
+                }
+            }
+            #try_from
+        })
+    };
+    Ok(quote! {
+        impl #impl_generics ::eventsourcing::Event for #name #ty_generics #where_clause {
+            fn event_type_version(&self) -> &str {
+                match *self {
+                    #(#version_arms)*
+                }
+            }
+
+            fn event_source(&self) -> &str {
+                match *self {
+                    #(#source_arms)*
+                }
+            }
+
+            fn event_type(&self) -> &str {
+                match *self {
+                    #(#type_arms)*
+                }
+            }
+
+            fn effective_time(&self) -> Option<::eventsourcing::__private::DateTime> {
+                match *self {
+                    #(#time_arms)*
+                }
+            }
+
+            fn subject(&self) -> Option<String> {
+                match *self {
+                    #(#subject_arms)*
+                }
+            }
+
+            fn encrypted_fields(&self) -> Option<Vec<String>> {
+                match *self {
+                    #(#encrypted_arms)*
                 }
             }
         }
-        #[cfg(feature = "orgeventstore")]
-        impl From<::eventsourcing::cloudevents::CloudEvent> for #name {
-            fn from(__source: ::eventsourcing::cloudevents::CloudEvent) -> Self {
-                ::serde_json::from_str(&::serde_json::to_string(&__source.data).unwrap()).unwrap()
+        #catalog
+    })
+}
+
+/// The schema of the given fields, as serde serializes them: named fields under their serde
+/// names (renamed by `rule` or their own `#[serde(rename)]`), leaving skipped fields out.
+/// Fields serde may leave out of the data, or fill in with a default when it is missing,
+/// are optional, as are all fields of a struct marked `#[serde(default)]`. Fields whose layout
+/// the schema can't follow fail the derive.
+fn fields_schema(
+    fields: &Fields,
+    rule: Option<RenameRule>,
+    defaulted: bool,
+) -> syn::Result<Tokens> {
+    let newtype = fields.len() == 1 && matches!(fields, Fields::Unnamed(_));
+    let mut serialized = Vec::new();
+    for field in fields.iter() {
+        let attrs = serde_attrs(&field.attrs)?;
+        if attrs.flatten || attrs.custom {
+            return Err(Error::new_spanned(
+                field,
+                "#[derive(EventSchema)] can't describe fields serialized through #[serde(flatten)], #[serde(with)] or #[serde(serialize_with)]",
+            ));
+        }
+        if !attrs.skip {
+            serialized.push((field, attrs));
+        }
+    }
+    Ok(match *fields {
+        Fields::Unit => quote! { <() as ::eventsourcing::schema::JsonSchema>::json_schema() },
+        Fields::Unnamed(ref unnamed) if newtype => {
+            let ty = &unnamed.unnamed[0].ty;
+            quote! { <#ty as ::eventsourcing::schema::JsonSchema>::json_schema() }
+        }
+        Fields::Unnamed(_) => {
+            let types = serialized.iter().map(|(field, _)| &field.ty);
+            quote! {
+                ::eventsourcing::schema::tuple(vec![
+                    #(<#types as ::eventsourcing::schema::JsonSchema>::json_schema()),*
+                ])
+            }
+        }
+        Fields::Named(_) => {
+            let properties = serialized.iter().filter_map(|(field, attrs)| {
+                let ty = &field.ty;
+                let name = serde_field_name(field.ident.as_ref()?, attrs, rule);
+                let omittable = attrs.skip_serializing_if || attrs.default || defaulted;
+                Some(quote! {
+                    (
+                        #name,
+                        <#ty as ::eventsourcing::schema::JsonSchema>::json_schema(),
+                        #omittable || <#ty as ::eventsourcing::schema::JsonSchema>::is_optional(),
+                    )
+                })
+            });
+            quote! { ::eventsourcing::schema::object(vec![#(#properties),*]) }
+        }
+    })
+}
+
+/// The serde attributes of a type whose schema is derived, which must lay its data out as
+/// the schemas describe it: externally tagged, and serialized by serde itself
+fn schema_container(ast: &DeriveInput) -> syn::Result<SerdeAttrs> {
+    let container = serde_attrs(&ast.attrs)?;
+    if container.custom
+        || container.transparent
+        || !matches!(container.tagging(), Tagging::External)
+    {
+        return Err(Error::new(
+            ast.ident.span(),
+            "#[derive(EventSchema)] only describes data serde serializes itself, with enums externally tagged; remove #[serde(tag)], #[serde(untagged)], #[serde(transparent)] or #[serde(into)]",
+        ));
+    }
+    Ok(container)
+}
+
+fn impl_component_event_schema(ast: &DeriveInput, data_enum: &DataEnum) -> syn::Result<Tokens> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let container = schema_container(ast)?;
+    let mut schemas = Vec::new();
+    for (variant, meta) in data_enum
+        .variants
+        .iter()
+        .zip(enum_metadata(ast, data_enum)?)
+    {
+        let EventMetadata {
+            event_type,
+            event_type_version,
+            event_source,
+            ..
+        } = meta;
+        let variant_s = serde_variant_name(variant, &container)?;
+        let data = match variant.fields {
+            Fields::Unit => quote! { ::eventsourcing::schema::unit_variant(#variant_s) },
+            ref fields => {
+                let rule = variant_field_rule(variant, &container)?;
+                let payload = fields_schema(fields, rule, false)?;
+                quote! { ::eventsourcing::schema::tagged(#variant_s, #payload) }
+            }
+        };
+        schemas.push(quote! {
+            ::eventsourcing::schema::EventTypeSchema::new(
+                #event_type,
+                #event_type_version,
+                #event_source,
+                #data,
+            )
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::eventsourcing::schema::EventSchema for #name #ty_generics #where_clause {
+            fn event_schemas() -> Vec<::eventsourcing::schema::EventTypeSchema> {
+                vec![#(#schemas),*]
+            }
+        }
+    })
+}
+
+fn impl_struct_event_schema(ast: &DeriveInput, data_struct: &DataStruct) -> syn::Result<Tokens> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let EventMetadata {
+        event_type,
+        event_type_version,
+        event_source,
+        ..
+    } = struct_metadata(ast)?;
+    let container = schema_container(ast)?;
+    let data = fields_schema(&data_struct.fields, container.rename_all, container.default)?;
+
+    Ok(quote! {
+        impl #impl_generics ::eventsourcing::schema::EventSchema for #name #ty_generics #where_clause {
+            fn event_schemas() -> Vec<::eventsourcing::schema::EventTypeSchema> {
+                vec![::eventsourcing::schema::EventTypeSchema::new(
+                    #event_type,
+                    #event_type_version,
+                    #event_source,
+                    #data,
+                )]
+            }
+        }
+    })
+}
+
+fn impl_delegate_event_schema(ast: &DeriveInput, data_enum: &DataEnum) -> syn::Result<Tokens> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let container = schema_container(ast)?;
+    let mut inner_types = Vec::new();
+    let mut variant_names = Vec::new();
+    for variant in data_enum.variants.iter() {
+        variant_names.push(serde_variant_name(variant, &container)?);
+    }
+    for (_, inner) in delegate_variants(data_enum)? {
+        inner_types.push(inner);
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::eventsourcing::schema::EventSchema for #name #ty_generics #where_clause {
+            fn event_schemas() -> Vec<::eventsourcing::schema::EventTypeSchema> {
+                let mut schemas = Vec::new();
+                #(
+                    schemas.extend(
+                        <#inner_types as ::eventsourcing::schema::EventSchema>::event_schemas()
+                            .into_iter()
+                            .map(|schema| schema.tagged(#variant_names)),
+                    );
+                )*
+                schemas
             }
         }
+    })
+}
+
+/// How the enum and variant names are cased in generated event type names
+#[derive(Clone, Copy)]
+enum Naming {
+    Lowercase,
+    Kebab,
+    Snake,
+}
+
+impl Naming {
+    fn parse(naming: &LitStr) -> syn::Result<Naming> {
+        match naming.value().as_str() {
+            "lowercase" => Ok(Naming::Lowercase),
+            "kebab-case" => Ok(Naming::Kebab),
+            "snake_case" => Ok(Naming::Snake),
+            other => Err(Error::new(
+                naming.span(),
+                format!(
+                    "unknown event naming \"{}\", expected \"lowercase\", \"kebab-case\" or \"snake_case\"",
+                    other
+                ),
+            )),
+        }
+    }
+
+    fn apply(self, ident: &str) -> String {
+        match self {
+            Naming::Lowercase => ident.to_lowercase(),
+            Naming::Kebab => split_words(ident).join("-"),
+            Naming::Snake => split_words(ident).join("_"),
+        }
     }
 }
 
-fn generate_event_matches(
+/// Splits a CamelCase identifier into lower-case words, keeping acronyms together
+fn split_words(ident: &str) -> Vec<String> {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (idx, c) in chars.iter().enumerate() {
+        if *c == '_' {
+            if !word.is_empty() {
+                words.push(word.clone());
+                word.clear();
+            }
+            continue;
+        }
+        let boundary = c.is_uppercase()
+            && idx > 0
+            && (chars[idx - 1].is_lowercase()
+                || chars[idx - 1].is_numeric()
+                || (chars[idx - 1].is_uppercase()
+                    && chars.get(idx + 1).is_some_and(|next| next.is_lowercase())));
+        if boundary && !word.is_empty() {
+            words.push(word.clone());
+            word.clear();
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// A serde `rename_all` rule
+#[derive(Clone, Copy)]
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(rule: &LitStr) -> syn::Result<RenameRule> {
+        match rule.value().as_str() {
+            "lowercase" => Ok(RenameRule::Lower),
+            "UPPERCASE" => Ok(RenameRule::Upper),
+            "PascalCase" => Ok(RenameRule::Pascal),
+            "camelCase" => Ok(RenameRule::Camel),
+            "snake_case" => Ok(RenameRule::Snake),
+            "SCREAMING_SNAKE_CASE" => Ok(RenameRule::ScreamingSnake),
+            "kebab-case" => Ok(RenameRule::Kebab),
+            "SCREAMING-KEBAB-CASE" => Ok(RenameRule::ScreamingKebab),
+            other => Err(Error::new(
+                rule.span(),
+                format!("unknown serde rename rule \"{}\"", other),
+            )),
+        }
+    }
+
+    /// Renames a PascalCase variant the way serde does
+    fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            RenameRule::Pascal => variant.to_owned(),
+            RenameRule::Lower => variant.to_ascii_lowercase(),
+            RenameRule::Upper => variant.to_ascii_uppercase(),
+            RenameRule::Camel => lower_first(variant),
+            RenameRule::Snake => {
+                let mut snake = String::new();
+                for (idx, c) in variant.char_indices() {
+                    if idx > 0 && c.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(c.to_ascii_lowercase());
+                }
+                snake
+            }
+            RenameRule::ScreamingSnake => RenameRule::Snake
+                .apply_to_variant(variant)
+                .to_ascii_uppercase(),
+            RenameRule::Kebab => RenameRule::Snake
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+            RenameRule::ScreamingKebab => RenameRule::ScreamingSnake
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+        }
+    }
+
+    /// Renames a snake_case field the way serde does
+    fn apply_to_field(self, field: &str) -> String {
+        match self {
+            RenameRule::Lower | RenameRule::Snake => field.to_owned(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for c in field.chars() {
+                    if c == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(c.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(c);
+                    }
+                }
+                pascal
+            }
+            RenameRule::Camel => lower_first(&RenameRule::Pascal.apply_to_field(field)),
+            RenameRule::Kebab => field.replace('_', "-"),
+            RenameRule::ScreamingKebab => RenameRule::ScreamingSnake
+                .apply_to_field(field)
+                .replace('_', "-"),
+        }
+    }
+}
+
+fn lower_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// The `#[serde(...)]` attributes of a container, variant or field that change where and
+/// under which name serde serializes it
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    rename_all: Option<RenameRule>,
+    rename_all_fields: Option<RenameRule>,
+    tag: Option<String>,
+    content: Option<String>,
+    untagged: bool,
+    transparent: bool,
+    skip: bool,
+    skip_serializing_if: bool,
+    /// `default`, which lets the field be left out when deserializing
+    default: bool,
+    flatten: bool,
+    /// `into`, `with` or `serialize_with`, which replace the serialized value
+    custom: bool,
+}
+
+/// How serde lays out the variants of an enum
+enum Tagging<'a> {
+    External,
+    Internal,
+    Adjacent(&'a str),
+    Untagged,
+}
+
+impl SerdeAttrs {
+    fn tagging(&self) -> Tagging<'_> {
+        match (self.untagged, &self.tag, &self.content) {
+            (true, _, _) => Tagging::Untagged,
+            (false, Some(_), Some(content)) => Tagging::Adjacent(content),
+            (false, Some(_), None) => Tagging::Internal,
+            (false, None, _) => Tagging::External,
+        }
+    }
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> syn::Result<SerdeAttrs> {
+    let mut serde = SerdeAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            let key = meta
+                .path
+                .get_ident()
+                .map(Ident::to_string)
+                .unwrap_or_default();
+            match key.as_str() {
+                "rename" => serde.rename = serialized_value(&meta)?.map(|name| name.value()),
+                "rename_all" => {
+                    serde.rename_all = match serialized_value(&meta)? {
+                        Some(rule) => Some(RenameRule::parse(&rule)?),
+                        None => None,
+                    }
+                }
+                "rename_all_fields" => {
+                    serde.rename_all_fields = match serialized_value(&meta)? {
+                        Some(rule) => Some(RenameRule::parse(&rule)?),
+                        None => None,
+                    }
+                }
+                "tag" => serde.tag = Some(meta.value()?.parse::<LitStr>()?.value()),
+                "content" => serde.content = Some(meta.value()?.parse::<LitStr>()?.value()),
+                "untagged" => serde.untagged = true,
+                "transparent" => serde.transparent = true,
+                "skip" | "skip_serializing" => serde.skip = true,
+                "flatten" => serde.flatten = true,
+                "skip_serializing_if" => {
+                    serde.skip_serializing_if = true;
+                    skip_meta_value(&meta)?;
+                }
+                "default" => {
+                    serde.default = true;
+                    skip_meta_value(&meta)?;
+                }
+                "into" | "with" | "serialize_with" => {
+                    serde.custom = true;
+                    skip_meta_value(&meta)?;
+                }
+                _ => skip_meta_value(&meta)?,
+            }
+            Ok(())
+        })?;
+    }
+    Ok(serde)
+}
+
+/// The serialization side of `name = "..."` or `name(serialize = "...", ...)`
+fn serialized_value(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Option<LitStr>> {
+    if meta.input.peek(Token![=]) {
+        return meta.value()?.parse().map(Some);
+    }
+    let mut serialized = None;
+    meta.parse_nested_meta(|inner| {
+        if inner.path.is_ident("serialize") {
+            serialized = Some(inner.value()?.parse()?);
+        } else {
+            skip_meta_value(&inner)?;
+        }
+        Ok(())
+    })?;
+    Ok(serialized)
+}
+
+/// Consumes the value of a serde attribute this crate doesn't need
+fn skip_meta_value(meta: &syn::meta::ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        let content;
+        parenthesized!(content in meta.input);
+        content.parse::<Tokens>()?;
+    }
+    Ok(())
+}
+
+/// The name serde gives a variant
+fn serde_variant_name(variant: &Variant, container: &SerdeAttrs) -> syn::Result<String> {
+    let attrs = serde_attrs(&variant.attrs)?;
+    let ident = variant.ident.to_string();
+    Ok(match (attrs.rename, container.rename_all) {
+        (Some(rename), _) => rename,
+        (None, Some(rule)) => rule.apply_to_variant(&ident),
+        (None, None) => ident,
+    })
+}
+
+/// The name serde gives a named field, given the rename rule of its container
+fn serde_field_name(ident: &Ident, attrs: &SerdeAttrs, rule: Option<RenameRule>) -> String {
+    let ident = ident.to_string().trim_start_matches("r#").to_owned();
+    match (&attrs.rename, rule) {
+        (Some(rename), _) => rename.clone(),
+        (None, Some(rule)) => rule.apply_to_field(&ident),
+        (None, None) => ident,
+    }
+}
+
+/// The rename rule serde applies to the fields of a variant
+fn variant_field_rule(
+    variant: &Variant,
+    container: &SerdeAttrs,
+) -> syn::Result<Option<RenameRule>> {
+    Ok(serde_attrs(&variant.attrs)?
+        .rename_all
+        .or(container.rename_all_fields))
+}
+
+/// Escapes a name for use as a JSON pointer reference token
+fn pointer_token(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+fn event_type_name(
     name: &Ident,
-    variants: &Punctuated<Variant, Comma>,
-) -> Vec<quote::Tokens> {
-    let mut result = Vec::new();
-    for (_idx, variant) in variants.iter().enumerate() {
-        let id = &variant.ident;
-        let et_name = event_type_name(name, id);
-        let new = match variant.fields {
-            Fields::Unit => quote! {
-                #name::#id => #et_name,
+    variant: &Variant,
+    naming: Naming,
+    prefix: Option<&String>,
+) -> syn::Result<String> {
+    if let Some(event_type) = single_attr::<LitStr>(&variant.attrs, "event_type")? {
+        return Ok(event_type.value());
+    }
+    let variant_s = naming.apply(&variant.ident.to_string());
+    Ok(match prefix {
+        Some(prefix) => format!("{}.{}", prefix, variant_s),
+        None => format!("{}.{}", naming.apply(&name.to_string()), variant_s),
+    })
+}
+
+fn command_naming(attrs: &[Attribute]) -> syn::Result<Naming> {
+    match single_attr::<LitStr>(attrs, "command_naming")? {
+        Some(naming) => Naming::parse(&naming),
+        None => Ok(Naming::Lowercase),
+    }
+}
+
+/// A pattern binding the field marked with the given attribute to `binding`, if a field
+/// is marked
+fn marked_field_pattern(fields: &Fields, attr: &str, binding: &str) -> syn::Result<Option<Tokens>> {
+    let mut marked = fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| field.attrs.iter().any(|a| a.path().is_ident(attr)));
+    let (idx, field) = match (marked.next(), marked.next()) {
+        (_, Some((_, duplicate))) => {
+            return Err(Error::new_spanned(
+                duplicate,
+                format!("only one field can be marked #[{}]", attr),
+            ))
+        }
+        (Some(marked), None) => marked,
+        (None, None) => return Ok(None),
+    };
+    let binding = Ident::new(binding, proc_macro2::Span::call_site());
+    Ok(Some(match field.ident {
+        Some(ref id) => quote! { { #id: ref #binding, .. } },
+        None => {
+            let skipped = (0..idx).map(|_| quote! { _ });
+            quote! { ( #(#skipped,)* ref #binding, .. ) }
+        }
+    }))
+}
+
+/// A pattern binding the field marked `#[aggregate_id]` to `__id`
+fn aggregate_id_pattern(fields: &Fields, span_of: &dyn ToTokens) -> syn::Result<Tokens> {
+    marked_field_pattern(fields, "aggregate_id", "__id")?.ok_or_else(|| {
+        Error::new_spanned(
+            span_of,
+            "commands need a field marked #[aggregate_id] naming the aggregate they target",
+        )
+    })
+}
+
+/// A match arm returning the effective time held by the variant's `#[effective_time]`
+/// field, or `None` if it has none
+fn effective_time_arm(name: &Ident, variant: &Variant) -> syn::Result<Tokens> {
+    let id = &variant.ident;
+    Ok(
+        match marked_field_pattern(&variant.fields, "effective_time", "__time")? {
+            Some(pattern) => quote! {
+                #name::#id #pattern => ::eventsourcing::__private::effective_time(__time),
             },
-            Fields::Unnamed(ref fields) => {
-                let idents: Vec<_> = fields.unnamed.pairs().map(|p| p.value().ident).collect();
-                quote! {
-                    #name::#id( #(_#idents,)* ) => #et_name,
+            None => quote! { #name::#id { .. } => None, },
+        },
+    )
+}
+
+/// A match arm returning the subject held by the variant's `#[subject]` field, or `None` if
+/// it has none
+fn subject_arm(name: &Ident, variant: &Variant) -> syn::Result<Tokens> {
+    let id = &variant.ident;
+    Ok(
+        match marked_field_pattern(&variant.fields, "subject", "__subject")? {
+            Some(pattern) => quote! {
+                #name::#id #pattern => ::eventsourcing::__private::subject(__subject),
+            },
+            None => quote! { #name::#id { .. } => None, },
+        },
+    )
+}
+
+/// Whether any of the fields is marked `#[encrypted]`
+fn marks_encrypted(fields: &Fields) -> bool {
+    fields
+        .iter()
+        .any(|field| has_attr(&field.attrs, "encrypted"))
+}
+
+/// The JSON pointers, below `prefix`, of the fields marked `#[encrypted]` as serde
+/// serializes them: by name (renamed by `rule` or the field's own `#[serde(rename)]`), by
+/// position in a tuple, or as the value of a newtype
+fn encrypted_pointers(
+    fields: &Fields,
+    rule: Option<RenameRule>,
+    prefix: &str,
+) -> syn::Result<Vec<String>> {
+    let newtype = fields.len() == 1 && matches!(fields, Fields::Unnamed(_));
+    let mut pointers = Vec::new();
+    let mut position = 0;
+    for field in fields.iter() {
+        let attrs = serde_attrs(&field.attrs)?;
+        let encrypted = has_attr(&field.attrs, "encrypted");
+        if encrypted && (attrs.skip || attrs.skip_serializing_if || attrs.flatten) {
+            return Err(Error::new_spanned(
+                field,
+                "#[encrypted] fields must always be serialized in place; remove #[serde(skip)], #[serde(skip_serializing_if)] or #[serde(flatten)]",
+            ));
+        }
+        if attrs.skip {
+            continue;
+        }
+        if encrypted {
+            pointers.push(match field.ident {
+                Some(ref id) => format!(
+                    "{}/{}",
+                    prefix,
+                    pointer_token(&serde_field_name(id, &attrs, rule))
+                ),
+                None if newtype => prefix.to_owned(),
+                None => format!("{}/{}", prefix, position),
+            });
+        }
+        position += 1;
+    }
+    Ok(pointers)
+}
+
+/// The JSON pointer at which serde places the payload of a variant with `#[encrypted]`
+/// fields
+fn encrypted_variant_prefix(variant: &Variant, container: &SerdeAttrs) -> syn::Result<String> {
+    if container.custom {
+        return Err(Error::new_spanned(
+            variant,
+            "#[encrypted] fields can't be located in enums serialized through #[serde(into)]",
+        ));
+    }
+    match container.tagging() {
+        Tagging::External => Ok(format!(
+            "/{}",
+            pointer_token(&serde_variant_name(variant, container)?)
+        )),
+        Tagging::Adjacent(content) => Ok(format!("/{}", pointer_token(content))),
+        Tagging::Untagged => Ok(String::new()),
+        Tagging::Internal if matches!(variant.fields, Fields::Named(_)) => Ok(String::new()),
+        Tagging::Internal => Err(Error::new_spanned(
+            variant,
+            "#[encrypted] fields of internally tagged enums must be named fields",
+        )),
+    }
+}
+
+/// The JSON pointers of the fields of a struct marked `#[encrypted]`
+fn struct_encrypted_pointers(ast: &DeriveInput, fields: &Fields) -> syn::Result<Vec<String>> {
+    let container = serde_attrs(&ast.attrs)?;
+    if container.custom {
+        return Err(Error::new(
+            ast.ident.span(),
+            "#[encrypted] fields can't be located in structs serialized through #[serde(into)]",
+        ));
+    }
+    if container.transparent {
+        return Ok(vec![String::new()]);
+    }
+    encrypted_pointers(fields, container.rename_all, "")
+}
+
+/// The body of `effective_time` for a struct, or `None` if no field is marked
+fn struct_effective_time(ast: &DeriveInput) -> syn::Result<Option<Tokens>> {
+    let name = &ast.ident;
+    let fields = match ast.data {
+        Data::Struct(ref data_struct) => &data_struct.fields,
+        _ => return Ok(None),
+    };
+    Ok(
+        marked_field_pattern(fields, "effective_time", "__time")?.map(|pattern| {
+            quote! {
+                fn effective_time(&self) -> Option<::eventsourcing::__private::DateTime> {
+                    let #name #pattern = *self;
+                    ::eventsourcing::__private::effective_time(__time)
                 }
             }
-            Fields::Named(ref fields) => {
-                let idents: Vec<_> = fields.named.pairs().map(|p| p.value().ident).collect();
-                quote! {
-                    #name::#id { #(#idents: _,)* } => #et_name,
+        }),
+    )
+}
+
+/// The body of `subject` for a struct, or `None` if no field is marked
+fn struct_subject(ast: &DeriveInput) -> syn::Result<Option<Tokens>> {
+    let name = &ast.ident;
+    let fields = match ast.data {
+        Data::Struct(ref data_struct) => &data_struct.fields,
+        _ => return Ok(None),
+    };
+    Ok(
+        marked_field_pattern(fields, "subject", "__subject")?.map(|pattern| {
+            quote! {
+                fn subject(&self) -> Option<String> {
+                    let #name #pattern = *self;
+                    ::eventsourcing::__private::subject(__subject)
                 }
             }
+        }),
+    )
+}
+
+fn impl_component_command(ast: &DeriveInput, data_enum: &DataEnum) -> syn::Result<Tokens> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let naming = command_naming(&ast.attrs)?;
+
+    let mut type_matches = Vec::new();
+    let mut id_matches = Vec::new();
+    let mut time_matches = Vec::new();
+    for variant in data_enum.variants.iter() {
+        let id = &variant.ident;
+        let command_type = match single_attr::<LitStr>(&variant.attrs, "command_type")? {
+            Some(command_type) => command_type.value(),
+            None => format!(
+                "{}.{}",
+                naming.apply(&name.to_string()),
+                naming.apply(&id.to_string())
+            ),
         };
-        result.push(new);
+        type_matches.push(quote! { #name::#id { .. } => #command_type, });
+        let pattern = aggregate_id_pattern(&variant.fields, variant)?;
+        id_matches.push(quote! {
+            #name::#id #pattern => ::std::string::ToString::to_string(__id),
+        });
+        time_matches.push(effective_time_arm(name, variant)?);
     }
-    result
+
+    Ok(quote! {
+        impl #impl_generics ::eventsourcing::Command for #name #ty_generics #where_clause {
+            fn command_type(&self) -> &str {
+                match self {
+                    #(#type_matches)*
+                }
+            }
+
+            fn aggregate_id(&self) -> String {
+                match *self {
+                    #(#id_matches)*
+                }
+            }
+
+            fn effective_time(&self) -> Option<::eventsourcing::__private::DateTime> {
+                match *self {
+                    #(#time_matches)*
+                }
+            }
+        }
+    })
 }
 
-fn event_type_name(name: &Ident, variant_id: &Ident) -> String {
-    let name_s = name.to_string().to_lowercase();
-    let variant_s = variant_id.to_string().to_lowercase();
-    format!("{}.{}", name_s, variant_s)
+fn impl_struct_command(ast: &DeriveInput, data_struct: &DataStruct) -> syn::Result<Tokens> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let command_type = match single_attr::<LitStr>(&ast.attrs, "command_type")? {
+        Some(command_type) => command_type.value(),
+        None => command_naming(&ast.attrs)?.apply(&name.to_string()),
+    };
+    let pattern = aggregate_id_pattern(&data_struct.fields, name)?;
+    let effective_time = struct_effective_time(ast)?;
+
+    Ok(quote! {
+        impl #impl_generics ::eventsourcing::Command for #name #ty_generics #where_clause {
+            fn command_type(&self) -> &str {
+                #command_type
+            }
+
+            fn aggregate_id(&self) -> String {
+                let #name #pattern = *self;
+                ::std::string::ToString::to_string(__id)
+            }
+
+            #effective_time
+        }
+    })
 }
 
-fn impl_component(ast: &DeriveInput) -> Tokens {
+fn impl_component_aggregate_state(
+    ast: &DeriveInput,
+    data_struct: &DataStruct,
+) -> syn::Result<Tokens> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let fields = match data_struct.fields {
+        Fields::Named(ref fields) => &fields.named,
+        ref other => {
+            return Err(Error::new(
+                if let Fields::Unit = *other {
+                    name.span()
+                } else {
+                    other.span()
+                },
+                "#[derive(AggregateState)] requires a struct with named fields",
+            ))
+        }
+    };
+
+    let mut marked = fields.iter().filter(|field| {
+        field
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("generation"))
+    });
+    let generation = match (marked.next(), marked.next()) {
+        (_, Some(duplicate)) => {
+            return Err(Error::new_spanned(
+                duplicate,
+                "only one field can be marked #[generation]",
+            ))
+        }
+        (Some(field), None) => field.ident.clone(),
+        (None, None) => fields
+            .iter()
+            .find(|field| field.ident.as_ref().is_some_and(|id| id == "generation"))
+            .and_then(|field| field.ident.clone()),
+    };
+
+    match generation {
+        Some(generation) => Ok(quote! {
+            impl #impl_generics ::eventsourcing::AggregateState for #name #ty_generics #where_clause {
+                fn generation(&self) -> u64 {
+                    self.#generation
+                }
+
+                fn set_generation(&mut self, generation: u64) {
+                    self.#generation = generation;
+                }
+            }
+        }),
+        None => Err(Error::new(
+            name.span(),
+            "#[derive(AggregateState)] requires a u64 field marked #[generation] or named `generation`",
+        )),
+    }
+}
+
+fn impl_component(ast: &DeriveInput) -> syn::Result<Tokens> {
     let name = &ast.ident;
     let (impl_generics, _ty_generics, where_clause) = ast.generics.split_for_impl();
 
-    let aggregate = ast
-        .attrs
-        .iter()
-        .find(|attr| attr.path.segments[0].ident == "aggregate")
-        .map(|attr| {
-            syn::parse2::<AggregateAttribute>(attr.tts.clone())
-                .unwrap()
-                .aggregate
-        })
-        .unwrap_or_else(|| parse_quote!(NoAggregate));
+    let aggregate: Path = single_attr(&ast.attrs, "aggregate")?.ok_or_else(|| {
+        Error::new(
+            name.span(),
+            "#[derive(Dispatcher)] requires an #[aggregate(AggregateType)] attribute",
+        )
+    })?;
 
-    quote! {
+    Ok(quote! {
         impl #impl_generics ::eventsourcing::Dispatcher for #name #where_clause {
             type Aggregate = #aggregate;
-            type Event = <#aggregate as Aggregate>::Event;
-            type Command = <#aggregate as Aggregate>::Command;
-            type State = <#aggregate as Aggregate>::State;
+            type Event = <#aggregate as ::eventsourcing::Aggregate>::Event;
+            type Command = <#aggregate as ::eventsourcing::Aggregate>::Command;
+            type State = <#aggregate as ::eventsourcing::Aggregate>::State;
 
             fn dispatch(
                 state: &Self::State,
                 cmd: &Self::Command,
                 store: &impl ::eventsourcing::eventstore::EventStore,
                 stream: &str,
-            ) -> Vec<Result<::eventsourcing::cloudevents::CloudEvent>> {
-                match Self::Aggregate::handle_command(state, cmd) {
-                    Ok(evts) => evts.into_iter().map(|evt| store.append(evt, stream)).collect(),
-                    Err(e) => vec![Err(e)],
-                }
+            ) -> Vec<::eventsourcing::Result<::eventsourcing::cloudevents::CloudEvent>> {
+                <Self as ::eventsourcing::Dispatcher>::dispatch_with(state, cmd, &(), store, stream)
+            }
+
+            fn effective_time(
+                cmd: &Self::Command,
+            ) -> Option<::eventsourcing::__private::DateTime> {
+                #[allow(unused_imports)]
+                use ::eventsourcing::__private::{CommandEffectiveTime, NoEffectiveTime};
+                (&&::eventsourcing::__private::CommandTime(cmd)).effective_time()
             }
         }
-    }
+    })
 }
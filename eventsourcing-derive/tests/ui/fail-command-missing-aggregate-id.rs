@@ -0,0 +1,14 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+
+#[derive(Command)]
+enum AccountCommand {
+    Open {
+        #[aggregate_id]
+        account: String,
+    },
+    Deposit(String, u32),
+}
+
+fn main() {}
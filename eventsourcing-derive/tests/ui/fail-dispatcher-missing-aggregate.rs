@@ -0,0 +1,7 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+
+#[derive(Dispatcher)]
+struct AccountDispatcher;
+
+fn main() {}
@@ -0,0 +1,21 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_type_version("1.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+struct FundsDeposited {
+    amount: u32,
+}
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_delegate]
+enum AccountEvent {
+    Deposited(FundsDeposited),
+    Closed,
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_type_version("1.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+enum AccountEvent {
+    #[event_type("account.opened")]
+    #[event_type("account.created")]
+    Opened,
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_type_version("1.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+enum CustomerEvent {
+    CustomerRegistered {
+        #[encrypted]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        email: Option<String>,
+    },
+}
+
+fn main() {}
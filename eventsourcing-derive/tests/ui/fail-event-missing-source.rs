@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_type_version("1.0")]
+enum AccountEvent {
+    Opened,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+enum AccountEvent {
+    #[event_type_version("1.0")]
+    Opened,
+    Closed,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+
+#[derive(Event)]
+union Payload {
+    count: u32,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Event)]
+#[event_type_version("1.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+enum AuditEvent {
+    Exported { rows: u32 },
+}
+
+fn main() {}
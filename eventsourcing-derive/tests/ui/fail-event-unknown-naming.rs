@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_type_version("1.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+#[event_naming("SCREAMING_CASE")]
+enum AccountEvent {
+    Opened,
+}
+
+fn main() {}
@@ -0,0 +1,22 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize)]
+struct Address {
+    city: String,
+}
+
+#[derive(Serialize, Deserialize, EventSchema)]
+#[event_type_version("1.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+enum CustomerEvent {
+    CustomerMoved {
+        #[serde(flatten)]
+        address: Address,
+    },
+}
+
+fn main() {}
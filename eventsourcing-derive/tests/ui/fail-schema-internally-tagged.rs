@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize, EventSchema)]
+#[event_type_version("1.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+#[serde(tag = "type")]
+enum CustomerEvent {
+    CustomerRegistered { email: String },
+}
+
+fn main() {}
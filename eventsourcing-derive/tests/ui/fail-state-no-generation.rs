@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+
+#[derive(AggregateState)]
+struct AccountState {
+    balance: u32,
+    version: u64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+
+#[derive(AggregateState)]
+enum AccountState {
+    Open { generation: u64 },
+}
+
+fn main() {}
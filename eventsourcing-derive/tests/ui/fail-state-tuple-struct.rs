@@ -0,0 +1,7 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+
+#[derive(AggregateState)]
+struct AccountState(u32, u64);
+
+fn main() {}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+
+#[derive(AggregateState)]
+struct AccountState {
+    #[generation]
+    version: u64,
+    #[generation]
+    revision: u64,
+}
+
+fn main() {}
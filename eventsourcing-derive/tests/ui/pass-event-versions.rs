@@ -0,0 +1,34 @@
+#[macro_use]
+extern crate eventsourcing_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_derive;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+mod versions {
+    pub const LEGACY: &str = "0.9";
+}
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/ui")]
+enum ConstVersion {
+    Happened,
+    #[event_type_version(versions::LEGACY)]
+    Legacy,
+}
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_type_version("2.0")]
+#[event_source = "events://github.com/pholactery/eventsourcing/tests/ui"]
+enum LiteralVersion {
+    Happened(u32),
+}
+
+fn main() {
+    use eventsourcing::Event;
+    assert_eq!(ConstVersion::Happened.event_type_version(), "1.0");
+    assert_eq!(ConstVersion::Legacy.event_type_version(), "0.9");
+    assert_eq!(LiteralVersion::Happened(1).event_type_version(), "2.0");
+}
@@ -23,19 +23,14 @@ enum BankCommand {
     DepositFunds(String, u32),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, AggregateState)]
 struct AccountData {
     acctnum: String,
     balance: u32,
+    #[generation]
     generation: u64,
 }
 
-impl AggregateState for AccountData {
-    fn generation(&self) -> u64 {
-        self.generation
-    }
-}
-
 struct Account;
 
 impl Aggregate for Account {
@@ -44,19 +39,14 @@ impl Aggregate for Account {
     type Command = BankCommand;
 
     fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
-        let state = match *evt {
-            BankEvent::FundsWithdrawn(_, amt) => AccountData {
-                balance: state.balance - amt,
-                acctnum: state.acctnum.to_owned(),
-                generation: state.generation + 1,
-            },
-            BankEvent::FundsDeposited(_, amt) => AccountData {
-                balance: state.balance + amt,
-                acctnum: state.acctnum.to_owned(),
-                generation: state.generation + 1,
-            },
+        let balance = match *evt {
+            BankEvent::FundsWithdrawn(_, amt) => state.balance - amt,
+            BankEvent::FundsDeposited(_, amt) => state.balance + amt,
         };
-        Ok(state)
+        Ok(AccountData {
+            balance,
+            ..state.clone()
+        })
     }
 
     fn handle_command(_state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
@@ -87,8 +77,20 @@ fn main() {
     };
 
     let post_deposit = Account::handle_command(&initial_state, &deposit).unwrap();
-    let state = Account::apply_event(&initial_state, &post_deposit[0]).unwrap();
+    let state = Account::apply(&initial_state, &post_deposit[0]).unwrap();
 
     println!("{:#?}", post_deposit);
     println!("{:#?}", state);
+
+    let withdrawal = BankCommand::WithdrawFunds("SAVINGS100".to_string(), 200);
+    let post_withdrawal = Account::handle_command(&state, &withdrawal).unwrap();
+    let state = Account::apply_all(&state, &post_withdrawal).unwrap();
+
+    println!("{:#?}", post_withdrawal);
+    println!(
+        "{} balance at generation {}: {}",
+        state.acctnum,
+        state.generation(),
+        state.balance
+    );
 }
@@ -1,6 +1,6 @@
 const DOMAIN_VERSION: &str = "1.0";
 
-use eventsourcing::{Aggregate, AggregateState, Result};
+use eventsourcing::{Aggregate, Result};
 
 #[derive(Debug)]
 pub enum CombatCommand {
@@ -16,27 +16,31 @@ pub enum CombatEvent {
     UnitEvent,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, AggregateState)]
 pub struct CombatState {
     pub entity_id: String,
     pub hitpoints: u32,
+    #[generation]
     pub generation: u64,
 }
 
-impl AggregateState for CombatState {
-    fn generation(&self) -> u64 {
-        self.generation
-    }
-}
-
 pub struct Combat;
 impl Aggregate for Combat {
     type Event = CombatEvent;
     type Command = CombatCommand;
     type State = CombatState;
 
-    fn apply_event(_state: &Self::State, _evt: &Self::Event) -> Result<Self::State> {
-        unimplemented!()
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        let hitpoints = match *evt {
+            CombatEvent::EntityAttacked(ref entity_id, pts) if *entity_id == state.entity_id => {
+                state.hitpoints.saturating_sub(pts)
+            }
+            _ => state.hitpoints,
+        };
+        Ok(CombatState {
+            hitpoints,
+            ..state.clone()
+        })
     }
 
     fn handle_command(_state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
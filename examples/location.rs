@@ -10,23 +10,18 @@ use eventsourcing::{eventstore::MemoryEventStore, prelude::*, Result};
 
 const DOMAIN_VERSION: &str = "1.0";
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, AggregateState)]
 struct LocationData {
     lat: f32,
     long: f32,
     alt: f32,
+    #[generation]
     generation: u64,
 }
 
-impl AggregateState for LocationData {
-    fn generation(&self) -> u64 {
-        self.generation
-    }
-}
-
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
 #[event_type_version(DOMAIN_VERSION)]
 #[event_source("events://github.com/pholactery/eventsourcing/samples/location")]
-#[derive(Serialize, Deserialize, Debug, Clone, Event)]
 enum LocationEvent {
     LocationUpdated { lat: f32, long: f32, alt: f32 },
 }
@@ -48,7 +43,7 @@ impl Aggregate for Location {
                 lat,
                 long,
                 alt,
-                generation: state.generation + 1,
+                ..state.clone()
             },
         };
         Ok(ld)
@@ -92,6 +87,10 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
 
     println!("Original state: {:?}", old_state);
     println!("Post-process state: {:?}", state);
+    println!(
+        "Moved to lat {}, long {}, alt {}",
+        state.lat, state.long, state.alt
+    );
 
     println!(
         "all events - {:#?}",
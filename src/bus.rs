@@ -0,0 +1,117 @@
+//! Command bus
+//!
+//! A command bus routes each command, by its type, to the aggregate registered to handle it.
+//! The aggregate's state is loaded from its store through a [`Repository`], the command is
+//! handled and the resulting events are appended to the stream named by the command's
+//! aggregate ID, as long as no other events were appended to it in the meantime.
+//!
+//! Middleware registered with the bus sees every command before it is handled, and can
+//! reject it (for validation or authorization), as well as every outcome (for logging).
+use super::cloudevents::CloudEvent;
+use super::eventstore::EventStore;
+use super::repository::Repository;
+use super::{Aggregate, Command, CommandHandler, Error, EventCatalog, Kind, Result};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+type Handler = Box<dyn Fn(&dyn Any) -> Result<Vec<CloudEvent>> + Send + Sync>;
+
+/// Hooks run around every command sent through a command bus
+pub trait Middleware: Send + Sync {
+    /// Runs before the command is handled. Returning an error rejects the command.
+    fn before(&self, _cmd: &dyn Command) -> Result<()> {
+        Ok(())
+    }
+
+    /// Runs after the command was handled (or rejected) with its outcome
+    fn after(&self, _cmd: &dyn Command, _outcome: &Result<Vec<CloudEvent>>) {}
+}
+
+/// Plain functions can validate or authorize commands
+impl<F> Middleware for F
+where
+    F: Fn(&dyn Command) -> Result<()> + Send + Sync,
+{
+    fn before(&self, cmd: &dyn Command) -> Result<()> {
+        self(cmd)
+    }
+}
+
+/// Routes commands to the aggregates registered to handle them
+#[derive(Default)]
+pub struct CommandBus {
+    handlers: HashMap<TypeId, Handler>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl CommandBus {
+    /// Creates a bus without any aggregates or middleware
+    pub fn new() -> CommandBus {
+        CommandBus::default()
+    }
+
+    /// Registers an aggregate, stored in the given store, as the handler of its command
+    /// type. Registering another aggregate for the same command type replaces it.
+    pub fn register<A, S>(&mut self, store: S) -> &mut CommandBus
+    where
+        A: Aggregate + 'static,
+        A::Command: Command + 'static,
+        A::Event: EventCatalog,
+        A::State: Default,
+        S: EventStore + Send + Sync + 'static,
+    {
+        self.register_with::<A, S, ()>(store, ())
+    }
+
+    /// Registers an aggregate that handles its commands with the given context
+    pub fn register_with<A, S, Ctx>(&mut self, store: S, ctx: Ctx) -> &mut CommandBus
+    where
+        A: CommandHandler<Ctx> + 'static,
+        A::Command: Command + 'static,
+        A::Event: EventCatalog,
+        A::State: Default,
+        S: EventStore + Send + Sync + 'static,
+        Ctx: Send + Sync + 'static,
+    {
+        let repository: Repository<A, S> = Repository::new(store);
+        let handler: Handler = Box::new(move |cmd: &dyn Any| {
+            let cmd = cmd
+                .downcast_ref::<A::Command>()
+                .expect("commands are routed by their type");
+            repository.execute_command_with(cmd, &ctx)
+        });
+        self.handlers.insert(TypeId::of::<A::Command>(), handler);
+        self
+    }
+
+    /// Adds middleware, run in the order in which it was added
+    pub fn add_middleware(&mut self, middleware: impl Middleware + 'static) -> &mut CommandBus {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Sends a command to the aggregate registered for its type, returning the stored
+    /// cloud events
+    pub fn send<C: Command + 'static>(&self, cmd: &C) -> Result<Vec<CloudEvent>> {
+        let outcome = self.route(cmd);
+        for middleware in &self.middleware {
+            middleware.after(cmd, &outcome);
+        }
+        outcome
+    }
+
+    fn route<C: Command + 'static>(&self, cmd: &C) -> Result<Vec<CloudEvent>> {
+        for middleware in &self.middleware {
+            middleware.before(cmd)?;
+        }
+        match self.handlers.get(&TypeId::of::<C>()) {
+            Some(handler) => handler(cmd),
+            None => Err(Error {
+                kind: Kind::CommandFailure(format!(
+                    "No aggregate is registered for command {}",
+                    cmd.command_type()
+                )),
+            }),
+        }
+    }
+}
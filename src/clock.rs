@@ -0,0 +1,68 @@
+//! Clocks
+//!
+//! Business logic that depends on the current time should read it from a [`Clock`] passed
+//! in through its command handling context rather than calling `Utc::now()` directly, so
+//! tests can control the time with a [`ManualClock`].
+use chrono::prelude::*;
+use chrono::Duration;
+use std::sync::Mutex;
+
+/// A source of the current time
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, for tests
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the given time
+    pub fn new(now: DateTime<Utc>) -> ManualClock {
+        ManualClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Sets the clock to the given time
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Moves the clock forward (or, with a negative duration, backward)
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += by;
+    }
+}
+
+impl Default for ManualClock {
+    /// Creates a clock stopped at the Unix epoch
+    fn default() -> Self {
+        ManualClock::new(Utc.timestamp_opt(0, 0).unwrap())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> DateTime<Utc> {
+        (**self).now()
+    }
+}
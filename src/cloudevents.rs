@@ -6,11 +6,55 @@
 //!
 //! In the current version of this library, only the _application/json_ content type is supported
 //! for the `data` field on the cloud event.
-use super::Event;
+//!
+//! Events received from other systems should be read with [`CloudEvent::from_json`] or
+//! [`CloudEvent::from_value`] rather than plain deserialization. Those readers accept both
+//! the v0.3 and v1.0 JSON formats, normalize v0.3 attribute names into the v1.0 model and
+//! validate the result.
+use super::{Error, Event, EventCatalog, Kind, Result};
 use chrono::prelude::*;
-use serde_json;
+use serde_json::{self, Map, Value};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
+/// The CloudEvents spec version produced by this library
+pub const SPEC_VERSION: &str = "1.0";
+
+/// The previous CloudEvents spec version accepted (and normalized) by the readers
+pub const SPEC_VERSION_03: &str = "0.3";
+
+/// The extension attribute holding the time at which an event took effect in the business
+pub const EFFECTIVE_TIME: &str = "effectivetime";
+
+/// The extension attribute marking events whose data, or part of it, could not be decrypted
+/// because its key was deleted
+pub const REDACTED: &str = "redacted";
+
+/// The names of the context attributes, which extension attributes can't take. `typeversion`
+/// is this library's own attribute.
+const CONTEXT_ATTRIBUTES: &[&str] = &[
+    "specversion",
+    "id",
+    "source",
+    "type",
+    "time",
+    "datacontenttype",
+    "dataschema",
+    "subject",
+    "data",
+    "typeversion",
+];
+
+/// The times of a cloud event that queries can filter by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeDimension {
+    /// When the event was recorded, its `time` attribute
+    Recorded,
+    /// When the event took effect in the business, which is when it was recorded unless
+    /// the event has an effective time
+    Effective,
+}
+
 /// CloudEvent provides a data structure that is JSON-compliant with v1.0 of the CloudEvents
 /// specification. This means that any system with which you want to communicate that is
 /// also CloudEvents-aware can accept the serialized version of this data structure.
@@ -25,11 +69,24 @@ pub struct CloudEvent {
     pub source: String, // URI
     #[serde(rename = "id")]
     pub event_id: String,
-    #[serde(rename = "time")]
-    pub event_time: DateTime<Utc>,
+    /// When the event was recorded. Events created here always have one, while events
+    /// read from elsewhere may not.
+    #[serde(rename = "time", default, skip_serializing_if = "Option::is_none")]
+    pub event_time: Option<DateTime<Utc>>,
     #[serde(rename = "datacontenttype")]
     pub content_type: String,
+    #[serde(
+        rename = "dataschema",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub data_schema: Option<String>, // URI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
     pub data: serde_json::Value,
+    /// Extension attributes, serialized alongside the context attributes
+    #[serde(flatten)]
+    pub extensions: BTreeMap<String, serde_json::Value>,
 }
 
 impl<E> From<E> for CloudEvent
@@ -38,16 +95,348 @@ where
 {
     fn from(source: E) -> Self {
         let raw_data = serde_json::to_string(&source).unwrap();
+        let mut extensions = BTreeMap::new();
+        if let Some(time) = source.effective_time() {
+            extensions.insert(EFFECTIVE_TIME.to_owned(), effective_time_value(time));
+        }
 
         CloudEvent {
-            cloud_events_version: "1.0".to_owned(),
+            cloud_events_version: SPEC_VERSION.to_owned(),
             event_type: source.event_type().to_owned(),
             event_type_version: source.event_type_version().to_owned(),
             source: source.event_source().to_owned(),
             event_id: Uuid::new_v4().to_hyphenated().to_string(),
-            event_time: Utc::now(),
+            event_time: Some(Utc::now()),
             content_type: "application/json".to_owned(),
+            data_schema: None,
+            subject: source.subject(),
             data: serde_json::from_str(&raw_data).unwrap(),
+            extensions,
         }
     }
 }
+
+impl CloudEvent {
+    /// Reads a cloud event from its JSON representation. See [`CloudEvent::from_value`].
+    pub fn from_json(json: &str) -> Result<CloudEvent> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|e| validation_error(format!("Cloud event is not valid JSON: {}", e)))?;
+        CloudEvent::from_value(value)
+    }
+
+    /// Reads a cloud event from a JSON value in either the v0.3 or the v1.0 format. Version
+    /// 0.3 attributes are normalized into the v1.0 model (`schemaurl` becomes `dataschema` and
+    /// `datacontentencoding` payloads are decoded), and the resulting event is validated.
+    /// Only `specversion`, `id`, `source` and `type` are required; events without a `time`
+    /// are read without an `event_time`.
+    pub fn from_value(value: Value) -> Result<CloudEvent> {
+        let mut attrs = match value {
+            Value::Object(attrs) => attrs,
+            _ => return Err(validation_error("Cloud event must be a JSON object")),
+        };
+
+        match attrs.get("specversion").and_then(Value::as_str) {
+            Some(SPEC_VERSION) => {
+                if let Some(encoded) = attrs.remove("data_base64") {
+                    let data = decode_base64_data(&attrs, &encoded)?;
+                    attrs.insert("data".to_owned(), data);
+                }
+            }
+            Some(SPEC_VERSION_03) => normalize_v03(&mut attrs)?,
+            Some(other) => {
+                return Err(validation_error(format!(
+                    "Unsupported cloud events spec version '{}'",
+                    other
+                )))
+            }
+            None => return Err(validation_error("Missing required attribute 'specversion'")),
+        }
+
+        for attr in &["id", "source", "type"] {
+            match attrs.get(*attr) {
+                Some(Value::String(_)) => {}
+                Some(_) => {
+                    return Err(validation_error(format!(
+                        "Attribute '{}' must be a string",
+                        attr
+                    )))
+                }
+                None => {
+                    return Err(validation_error(format!(
+                        "Missing required attribute '{}'",
+                        attr
+                    )))
+                }
+            }
+        }
+        match attrs.get("time") {
+            None => {}
+            Some(Value::String(time)) if DateTime::parse_from_rfc3339(time).is_ok() => {}
+            Some(time) => {
+                return Err(validation_error(format!(
+                    "Attribute 'time' is not an RFC 3339 timestamp: {}",
+                    time
+                )))
+            }
+        }
+        attrs
+            .entry("datacontenttype")
+            .or_insert_with(|| Value::String("application/json".to_owned()));
+        attrs
+            .entry("typeversion")
+            .or_insert_with(|| Value::String(String::new()));
+        attrs.entry("data").or_insert(Value::Null);
+
+        let event: CloudEvent = serde_json::from_value(Value::Object(attrs))
+            .map_err(|e| validation_error(format!("Malformed cloud event: {}", e)))?;
+        event.validate()?;
+        Ok(event)
+    }
+
+    /// Sets the URI of the schema the event data adheres to
+    pub fn with_data_schema(mut self, uri: impl Into<String>) -> CloudEvent {
+        self.data_schema = Some(uri.into());
+        self
+    }
+
+    /// Sets the time at which the event took effect in the business
+    pub fn with_effective_time(mut self, time: DateTime<Utc>) -> CloudEvent {
+        self.extensions
+            .insert(EFFECTIVE_TIME.to_owned(), effective_time_value(time));
+        self
+    }
+
+    /// The time at which the event took effect in the business, if it has one
+    pub fn effective_time(&self) -> Option<DateTime<Utc>> {
+        self.extensions
+            .get(EFFECTIVE_TIME)
+            .and_then(Value::as_str)
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.with_timezone(&Utc))
+    }
+
+    /// Converts the event back into the type that produced it, failing if its type is
+    /// unknown or its data doesn't deserialize, as can happen once its data is redacted
+    pub fn to_event<E: EventCatalog>(&self) -> Result<E> {
+        E::deserialize_by_type(&self.event_type, &self.data)
+    }
+
+    /// Whether some of the event's data was redacted, in which case the redacted values
+    /// read as `null`
+    pub fn is_redacted(&self) -> bool {
+        self.extensions.get(REDACTED) == Some(&Value::Bool(true))
+    }
+
+    /// The time of the event in the given dimension, if it has one
+    pub fn time(&self, dimension: TimeDimension) -> Option<DateTime<Utc>> {
+        match dimension {
+            TimeDimension::Recorded => self.event_time,
+            TimeDimension::Effective => self.effective_time().or(self.event_time),
+        }
+    }
+
+    /// Validates this event against the v1.0 spec: the required attributes must be present
+    /// and non-empty, `source` (and `dataschema`, if set) must be a URI-reference,
+    /// `datacontenttype` must be a media type and extension attribute names must be
+    /// lower-case alphanumeric and not those of context attributes. The `time` attribute, if
+    /// set, is an RFC 3339 timestamp by construction; the `effectivetime` extension, if set,
+    /// must be one too.
+    pub fn validate(&self) -> Result<()> {
+        if self.cloud_events_version != SPEC_VERSION {
+            return Err(validation_error(format!(
+                "Unsupported cloud events spec version '{}', expected '{}'",
+                self.cloud_events_version, SPEC_VERSION
+            )));
+        }
+        if self.event_id.is_empty() {
+            return Err(validation_error("Attribute 'id' must not be empty"));
+        }
+        if self.event_type.is_empty() {
+            return Err(validation_error("Attribute 'type' must not be empty"));
+        }
+        if !is_uri_reference(&self.source) {
+            return Err(validation_error(format!(
+                "Attribute 'source' is not a valid URI-reference: '{}'",
+                self.source
+            )));
+        }
+        if !is_media_type(&self.content_type) {
+            return Err(validation_error(format!(
+                "Attribute 'datacontenttype' is not a valid media type: '{}'",
+                self.content_type
+            )));
+        }
+        if let Some(ref schema) = self.data_schema {
+            if !is_uri_reference(schema) {
+                return Err(validation_error(format!(
+                    "Attribute 'dataschema' is not a valid URI: '{}'",
+                    schema
+                )));
+            }
+        }
+        if let Some(ref subject) = self.subject {
+            if subject.is_empty() {
+                return Err(validation_error("Attribute 'subject' must not be empty"));
+            }
+        }
+        if let Some(name) = self.extensions.keys().find(|name| !is_extension_name(name)) {
+            return Err(validation_error(format!(
+                "Extension attribute name '{}' must consist of lower-case letters and digits",
+                name
+            )));
+        }
+        if let Some(name) = self
+            .extensions
+            .keys()
+            .find(|name| CONTEXT_ATTRIBUTES.contains(&name.as_str()))
+        {
+            return Err(validation_error(format!(
+                "Extension attribute name '{}' is taken by a context attribute",
+                name
+            )));
+        }
+        if let Some(time) = self.extensions.get(EFFECTIVE_TIME) {
+            if self.effective_time().is_none() {
+                return Err(validation_error(format!(
+                    "Attribute '{}' is not an RFC 3339 timestamp: {}",
+                    EFFECTIVE_TIME, time
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Effective times are written in the same format as the `time` attribute
+fn effective_time_value(time: DateTime<Utc>) -> Value {
+    serde_json::to_value(time).unwrap()
+}
+
+fn validation_error<S: Into<String>>(msg: S) -> Error {
+    Error {
+        kind: Kind::ValidationFailure(msg.into()),
+    }
+}
+
+fn normalize_v03(attrs: &mut Map<String, Value>) -> Result<()> {
+    attrs.insert(
+        "specversion".to_owned(),
+        Value::String(SPEC_VERSION.to_owned()),
+    );
+    if let Some(schema) = attrs.remove("schemaurl") {
+        attrs.insert("dataschema".to_owned(), schema);
+    }
+    match attrs.remove("datacontentencoding") {
+        None => Ok(()),
+        Some(Value::String(ref encoding)) if encoding.eq_ignore_ascii_case("base64") => {
+            let encoded = attrs.remove("data").unwrap_or(Value::Null);
+            let data = decode_base64_data(attrs, &encoded)?;
+            attrs.insert("data".to_owned(), data);
+            Ok(())
+        }
+        Some(other) => Err(validation_error(format!(
+            "Unsupported datacontentencoding {}",
+            other
+        ))),
+    }
+}
+
+fn decode_base64_data(attrs: &Map<String, Value>, encoded: &Value) -> Result<Value> {
+    let content_type = attrs
+        .get("datacontenttype")
+        .and_then(Value::as_str)
+        .unwrap_or("application/json");
+    if !is_json_media_type(content_type) {
+        return Err(validation_error(format!(
+            "Only JSON data is supported, found binary data of type '{}'",
+            content_type
+        )));
+    }
+    let encoded = encoded
+        .as_str()
+        .ok_or_else(|| validation_error("Base64 encoded data must be a string"))?;
+    let bytes = base64::decode(encoded)
+        .map_err(|e| validation_error(format!("Data is not valid base64: {}", e)))?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| validation_error(format!("Decoded data is not valid JSON: {}", e)))
+}
+
+fn is_json_media_type(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+/// Checks the RFC 3986 URI-reference grammar: only permitted characters, well-formed
+/// percent-encodings, at most one fragment and a well-formed scheme if one is present.
+fn is_uri_reference(uri: &str) -> bool {
+    if uri.is_empty() {
+        return false;
+    }
+    let bytes = uri.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                if i + 2 >= bytes.len()
+                    || !bytes[i + 1].is_ascii_hexdigit()
+                    || !bytes[i + 2].is_ascii_hexdigit()
+                {
+                    return false;
+                }
+                i += 2;
+            }
+            c if c.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=".contains(&c) => {}
+            _ => return false,
+        }
+        i += 1;
+    }
+    if uri.matches('#').count() > 1 {
+        return false;
+    }
+    match uri.find([':', '/', '?', '#']) {
+        Some(idx) if bytes[idx] == b':' => {
+            let scheme = &uri[..idx];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        _ => true,
+    }
+}
+
+/// Checks the RFC 2046 `type "/" subtype *(";" parameter)` media type grammar
+fn is_media_type(content_type: &str) -> bool {
+    fn is_token(s: &str) -> bool {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+    }
+    let mut parts = content_type.split(';');
+    let essence = parts.next().unwrap_or("").trim();
+    let mut essence_parts = essence.splitn(2, '/');
+    let valid_essence = match (essence_parts.next(), essence_parts.next()) {
+        (Some(t), Some(subtype)) => is_token(t) && is_token(subtype),
+        _ => false,
+    };
+    valid_essence
+        && parts.all(|param| {
+            let mut kv = param.trim().splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) => is_token(k) && !v.is_empty(),
+                _ => false,
+            }
+        })
+}
+
+fn is_extension_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+}
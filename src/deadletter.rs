@@ -0,0 +1,213 @@
+//! Dead-letter handling
+//!
+//! An event handler, such as a projection or a subscriber, may fail on a particular event
+//! every time it sees it, for instance because the event's data can't be deserialized.
+//! Wrapping the handler in a [`DeadLetterHandler`] retries a failing event according to a
+//! [`RetryPolicy`] and then sets it aside in a [`DeadLetterStore`], along with its error
+//! and the number of attempts, so the handler can move on to the next event. Dead-lettered
+//! events can later be inspected, replayed once the cause is fixed, or skipped.
+use super::cloudevents::CloudEvent;
+use super::retry::RetryPolicy;
+use super::{Error, Kind, Result};
+use std::sync::Mutex;
+
+/// Handles events read from a store or received from another system
+pub trait EventHandler {
+    fn handle(&self, evt: &CloudEvent) -> Result<()>;
+}
+
+/// Plain functions can handle events
+impl<F> EventHandler for F
+where
+    F: Fn(&CloudEvent) -> Result<()>,
+{
+    fn handle(&self, evt: &CloudEvent) -> Result<()> {
+        self(evt)
+    }
+}
+
+/// An event a handler repeatedly failed on
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub event: CloudEvent,
+    /// The error of the last attempt
+    pub error: String,
+    /// The number of times handling the event was attempted, including earlier replays
+    pub attempts: u32,
+}
+
+/// Keeps dead-lettered events, at most one per event ID
+pub trait DeadLetterStore {
+    /// Adds a dead letter, replacing any earlier one for the same event
+    fn add(&self, letter: DeadLetter) -> Result<()>;
+
+    /// The dead letters in the order in which they were added
+    fn list(&self) -> Result<Vec<DeadLetter>>;
+
+    /// Removes the dead letter for the given event, returning it
+    fn remove(&self, event_id: &str) -> Result<Option<DeadLetter>>;
+
+    /// The dead letter for the given event, if any
+    fn get(&self, event_id: &str) -> Result<Option<DeadLetter>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|letter| letter.event.event_id == event_id))
+    }
+}
+
+/// A simple, in-memory dead-letter store. The resulting store is thread-safe.
+#[derive(Debug, Default)]
+pub struct MemoryDeadLetterStore {
+    letters: Mutex<Vec<DeadLetter>>,
+}
+
+impl MemoryDeadLetterStore {
+    pub fn new() -> MemoryDeadLetterStore {
+        MemoryDeadLetterStore::default()
+    }
+}
+
+impl DeadLetterStore for MemoryDeadLetterStore {
+    fn add(&self, letter: DeadLetter) -> Result<()> {
+        let mut guard = self.letters.lock().unwrap();
+        guard.retain(|stored| stored.event.event_id != letter.event.event_id);
+        guard.push(letter);
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<DeadLetter>> {
+        Ok(self.letters.lock().unwrap().clone())
+    }
+
+    fn remove(&self, event_id: &str) -> Result<Option<DeadLetter>> {
+        let mut guard = self.letters.lock().unwrap();
+        let position = guard
+            .iter()
+            .position(|letter| letter.event.event_id == event_id);
+        Ok(position.map(|idx| guard.remove(idx)))
+    }
+}
+
+/// What became of an event delivered to a dead-letter handler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delivery {
+    Handled,
+    DeadLettered,
+}
+
+/// The outcome of replaying a dead-lettered event
+#[derive(Debug)]
+pub struct Replayed {
+    pub event_id: String,
+    pub outcome: Result<Delivery>,
+}
+
+/// Retries an event handler on failure, dead-lettering the events it keeps failing on
+pub struct DeadLetterHandler<H, D> {
+    handler: H,
+    dead_letters: D,
+    retry: RetryPolicy,
+}
+
+impl<H: EventHandler, D: DeadLetterStore> DeadLetterHandler<H, D> {
+    /// Wraps a handler, retrying failed events with the default retry policy
+    pub fn new(handler: H, dead_letters: D) -> DeadLetterHandler<H, D> {
+        DeadLetterHandler {
+            handler,
+            dead_letters,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets the policy for retrying failed events before they are dead-lettered
+    pub fn with_retry_policy(self, retry: RetryPolicy) -> DeadLetterHandler<H, D> {
+        DeadLetterHandler { retry, ..self }
+    }
+
+    /// The underlying dead-letter store
+    pub fn dead_letters(&self) -> &D {
+        &self.dead_letters
+    }
+
+    /// Hands an event to the handler, dead-lettering it if it still fails after retrying.
+    /// Only fails if the event can't be added to the dead-letter store.
+    pub fn deliver(&self, evt: &CloudEvent) -> Result<Delivery> {
+        self.attempt(evt, 0)
+    }
+
+    /// Delivers a dead-lettered event again, removing it from the store once it is handled.
+    /// Events that fail again stay in the store with their attempts added up; the letter
+    /// is only replaced, so it isn't lost if the store fails.
+    pub fn replay(&self, event_id: &str) -> Result<Delivery> {
+        let letter = self
+            .dead_letters
+            .get(event_id)?
+            .ok_or_else(|| missing(event_id))?;
+        let delivery = self.attempt(&letter.event, letter.attempts)?;
+        if delivery == Delivery::Handled {
+            self.dead_letters.remove(event_id)?;
+        }
+        Ok(delivery)
+    }
+
+    /// Replays every dead-lettered event, in the order in which they were dead-lettered.
+    /// Each event is replayed whatever became of the ones before it, so only listing the
+    /// dead letters fails; the outcome of each replay is returned along with its event ID.
+    pub fn replay_all(&self) -> Result<Vec<Replayed>> {
+        Ok(self
+            .dead_letters
+            .list()?
+            .into_iter()
+            .map(|letter| {
+                let event_id = letter.event.event_id;
+                let outcome = self.replay(&event_id);
+                Replayed { event_id, outcome }
+            })
+            .collect())
+    }
+
+    /// Gives up on a dead-lettered event, removing it from the store without handling it
+    pub fn skip(&self, event_id: &str) -> Result<DeadLetter> {
+        self.take(event_id)
+    }
+
+    fn attempt(&self, evt: &CloudEvent, previous_attempts: u32) -> Result<Delivery> {
+        let mut attempts = 0;
+        let outcome = self.retry.run(|attempt| {
+            attempts = attempt;
+            self.handler.handle(evt)
+        });
+        match outcome {
+            Ok(()) => Ok(Delivery::Handled),
+            Err(e) => {
+                self.dead_letters.add(DeadLetter {
+                    event: evt.clone(),
+                    error: e.to_string(),
+                    attempts: previous_attempts + attempts,
+                })?;
+                Ok(Delivery::DeadLettered)
+            }
+        }
+    }
+
+    fn take(&self, event_id: &str) -> Result<DeadLetter> {
+        self.dead_letters
+            .remove(event_id)?
+            .ok_or_else(|| missing(event_id))
+    }
+}
+
+fn missing(event_id: &str) -> Error {
+    Error {
+        kind: Kind::ValidationFailure(format!("No dead-lettered event with ID {}", event_id)),
+    }
+}
+
+/// Dead-letter handlers can be used wherever a handler is, handling every event that can
+/// be dead-lettered without failing
+impl<H: EventHandler, D: DeadLetterStore> EventHandler for DeadLetterHandler<H, D> {
+    fn handle(&self, evt: &CloudEvent) -> Result<()> {
+        self.deliver(evt).map(|_| ())
+    }
+}
@@ -0,0 +1,394 @@
+//! Payload encryption and crypto-shredding
+//!
+//! An [`EncryptingStore`] wraps any event store and encrypts the data of appended events
+//! with AES-256-GCM, using a data key per subject kept in a [`KeyStore`]. The subject of an
+//! event is its [`Event::subject`] (the CloudEvents `subject` attribute once stored), or the
+//! stream it is appended to if it has none, so one subject can span several streams. Events marking fields `#[encrypted]` only have
+//! those fields encrypted; the rest of their data stays readable.
+//!
+//! Deleting a subject's key with [`EncryptingStore::shred`] makes its encrypted data
+//! unreadable for good without removing any event, so erasure requests can be honoured
+//! while keeping the history intact. Reading such events yields `null` in place of each
+//! value that could not be decrypted, and marks the events as redacted (see
+//! [`CloudEvent::is_redacted`]).
+use super::cloudevents::{CloudEvent, REDACTED};
+use super::eventstore::{
+    decorated_event_store, Decorator, EventStore, ExpectedVersion, RecordedEvent,
+};
+use super::{Error, Event, Kind, Result};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use chrono::prelude::*;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// The key of the JSON object that replaces an encrypted value in event data, holding a
+/// versioned envelope: `{"$encrypted": {"v": 1, "key": .., "nonce": .., "ciphertext": ..}}`
+pub const ENCRYPTED: &str = "$encrypted";
+
+/// The version of the envelopes written by this store
+const ENVELOPE_VERSION: u32 = 1;
+
+/// A key encrypting the data of a single subject
+#[derive(Clone, PartialEq)]
+pub struct DataKey {
+    pub id: String,
+    material: [u8; 32],
+}
+
+impl DataKey {
+    pub fn new(id: impl Into<String>, material: [u8; 32]) -> DataKey {
+        DataKey {
+            id: id.into(),
+            material,
+        }
+    }
+
+    /// Generates a random key with a random ID
+    pub fn generate() -> DataKey {
+        let mut material = [0u8; 32];
+        material.copy_from_slice(&Aes256Gcm::generate_key(OsRng));
+        DataKey::new(Uuid::new_v4().to_hyphenated().to_string(), material)
+    }
+
+    /// The raw bytes of the key
+    pub fn material(&self) -> &[u8; 32] {
+        &self.material
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.material))
+    }
+}
+
+/// Data keys never show up in logs
+impl fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DataKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Keeps the data keys of subjects
+pub trait KeyStore {
+    /// The current data key of the subject, if it has one
+    fn key(&self, subject: &str) -> Result<Option<DataKey>>;
+
+    /// Saves a new data key for the subject, replacing any current one
+    fn save_key(&self, subject: &str, key: DataKey) -> Result<()>;
+
+    /// Deletes the subject's data key. Deleting a key that doesn't exist has no effect.
+    fn delete_key(&self, subject: &str) -> Result<()>;
+}
+
+/// A simple, in-memory key store. The resulting store is thread-safe.
+#[derive(Debug, Default)]
+pub struct MemoryKeyStore {
+    keys: Mutex<HashMap<String, DataKey>>,
+}
+
+impl MemoryKeyStore {
+    pub fn new() -> MemoryKeyStore {
+        MemoryKeyStore::default()
+    }
+}
+
+impl KeyStore for MemoryKeyStore {
+    fn key(&self, subject: &str) -> Result<Option<DataKey>> {
+        Ok(self.keys.lock().unwrap().get(subject).cloned())
+    }
+
+    fn save_key(&self, subject: &str, key: DataKey) -> Result<()> {
+        self.keys.lock().unwrap().insert(subject.to_owned(), key);
+        Ok(())
+    }
+
+    fn delete_key(&self, subject: &str) -> Result<()> {
+        self.keys.lock().unwrap().remove(subject);
+        Ok(())
+    }
+}
+
+/// An encrypted value as stored in event data
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Envelope {
+    v: u32,
+    key: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// An event whose data is replaced by its (partly) encrypted form
+struct Sealed<E> {
+    event: E,
+    data: Value,
+}
+
+impl<E: Event> Serialize for Sealed<E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
+}
+
+impl<E: Event> Event for Sealed<E> {
+    fn event_type_version(&self) -> &str {
+        self.event.event_type_version()
+    }
+
+    fn event_type(&self) -> &str {
+        self.event.event_type()
+    }
+
+    fn event_source(&self) -> &str {
+        self.event.event_source()
+    }
+
+    fn effective_time(&self) -> Option<DateTime<Utc>> {
+        self.event.effective_time()
+    }
+
+    fn subject(&self) -> Option<String> {
+        self.event.subject()
+    }
+}
+
+/// An event store encrypting event data with per-subject keys. Events are appended with
+/// their data, or the fields they mark, encrypted under the key of their subject, which is
+/// created if the subject has none, and are returned with their data in plain text.
+/// Appending fails, appending nothing, if a marked field isn't in the data.
+pub struct EncryptingStore<S, K = MemoryKeyStore> {
+    store: S,
+    keys: K,
+    creating_keys: Mutex<()>,
+}
+
+impl<S: EventStore, K: KeyStore> EncryptingStore<S, K> {
+    pub fn new(store: S, keys: K) -> EncryptingStore<S, K> {
+        EncryptingStore {
+            store,
+            keys,
+            creating_keys: Mutex::new(()),
+        }
+    }
+
+    /// The underlying event store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// The underlying key store
+    pub fn keys(&self) -> &K {
+        &self.keys
+    }
+
+    /// Deletes the subject's data key, redacting the encrypted data of every event appended
+    /// for the subject so far. Events appended afterwards are encrypted with a new key.
+    pub fn shred(&self, subject: &str) -> Result<()> {
+        self.keys.delete_key(subject)
+    }
+
+    fn key_for(&self, subject: &str) -> Result<DataKey> {
+        let _guard = self.creating_keys.lock().unwrap();
+        match self.keys.key(subject)? {
+            Some(key) => Ok(key),
+            None => {
+                let key = DataKey::generate();
+                self.keys.save_key(subject, key.clone())?;
+                Ok(key)
+            }
+        }
+    }
+
+    /// Encrypts the event's data, or the fields it marks, under the key of its subject,
+    /// creating the key if the subject has none. Returns the event to store along with its
+    /// data in plain text.
+    fn seal_event<E: Event>(&self, evt: E, stream: &str) -> Result<(Sealed<E>, Value)> {
+        let subject = evt.subject().unwrap_or_else(|| stream.to_owned());
+        let key = self.key_for(&subject)?;
+        let plain = serde_json::to_value(&evt).map_err(|e| Error {
+            kind: Kind::ApplicationFailure(format!("Failed to serialize event {:?}", e)),
+        })?;
+        let mut data = plain.clone();
+        match evt.encrypted_fields() {
+            None => data = seal(&key, &data)?,
+            Some(pointers) => {
+                for pointer in pointers {
+                    let field = data.pointer_mut(&pointer).ok_or_else(|| Error {
+                        kind: Kind::ApplicationFailure(format!(
+                            "Event {} has no field at {} to encrypt",
+                            evt.event_type(),
+                            pointer
+                        )),
+                    })?;
+                    *field = seal(&key, field)?;
+                }
+            }
+        }
+        Ok((Sealed { event: evt, data }, plain))
+    }
+
+    /// Decrypts an event read from the stream with the key of its subject, looking each
+    /// subject's key up only once
+    fn open_cached(
+        &self,
+        keys: &mut HashMap<String, Option<DataKey>>,
+        stream: &str,
+        evt: CloudEvent,
+    ) -> Result<CloudEvent> {
+        let subject = evt.subject.as_deref().unwrap_or(stream);
+        if !keys.contains_key(subject) {
+            keys.insert(subject.to_owned(), self.keys.key(subject)?);
+        }
+        let key = keys[subject].clone();
+        open_event(evt, key.as_ref())
+    }
+}
+
+impl<S: EventStore, K: KeyStore> Decorator for EncryptingStore<S, K> {
+    type Inner = S;
+
+    fn inner(&self) -> &S {
+        &self.store
+    }
+
+    /// Encrypts each event and appends them through the underlying store, which appends
+    /// them atomically if it can
+    fn append_events<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        let mut sealed = Vec::with_capacity(evts.len());
+        let mut plain = Vec::with_capacity(evts.len());
+        for evt in evts {
+            let (evt, data) = self.seal_event(evt, stream)?;
+            sealed.push(evt);
+            plain.push(data);
+        }
+        let mut stored = self.store.append_batch(sealed, stream, expected)?;
+        for (evt, data) in stored.iter_mut().zip(plain) {
+            evt.data = data;
+        }
+        Ok(stored)
+    }
+
+    /// Decrypts the events of a stream, each with the key of its subject
+    fn read_events(&self, stream: &str, evts: Vec<CloudEvent>) -> Result<Vec<CloudEvent>> {
+        let mut keys = HashMap::new();
+        evts.into_iter()
+            .map(|evt| self.open_cached(&mut keys, stream, evt))
+            .collect()
+    }
+
+    /// Decrypts events of any streams, each with the key of its subject
+    fn read_recorded(&self, recorded: Vec<RecordedEvent>) -> Result<Vec<RecordedEvent>> {
+        let mut keys = HashMap::new();
+        recorded
+            .into_iter()
+            .map(|mut recorded| {
+                recorded.event = self.open_cached(&mut keys, &recorded.stream, recorded.event)?;
+                Ok(recorded)
+            })
+            .collect()
+    }
+}
+
+decorated_event_store!([S: EventStore, K: KeyStore] EncryptingStore<S, K>);
+
+/// Encrypts a value into an envelope
+fn seal(key: &DataKey, value: &Value) -> Result<Value> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(value).map_err(|e| Error {
+        kind: Kind::ApplicationFailure(format!("Failed to serialize event data {:?}", e)),
+    })?;
+    let ciphertext = key
+        .cipher()
+        .encrypt(&nonce, plaintext.as_ref())
+        .map_err(|_| Error {
+            kind: Kind::ApplicationFailure("Failed to encrypt event data".to_owned()),
+        })?;
+    let envelope = Envelope {
+        v: ENVELOPE_VERSION,
+        key: key.id.clone(),
+        nonce: base64::encode(nonce),
+        ciphertext: base64::encode(ciphertext),
+    };
+    let envelope = serde_json::to_value(envelope).map_err(|e| Error {
+        kind: Kind::ApplicationFailure(format!("Failed to serialize encrypted data {:?}", e)),
+    })?;
+    let mut sealed = serde_json::Map::new();
+    sealed.insert(ENCRYPTED.to_owned(), envelope);
+    Ok(Value::Object(sealed))
+}
+
+/// Decrypts an event's data, marking it as redacted if any of it could not be decrypted
+fn open_event(mut evt: CloudEvent, key: Option<&DataKey>) -> Result<CloudEvent> {
+    let mut redacted = false;
+    open(&mut evt.data, key, &mut redacted)?;
+    if redacted {
+        evt.extensions
+            .insert(REDACTED.to_owned(), Value::Bool(true));
+    }
+    Ok(evt)
+}
+
+/// Decrypts every envelope within a value in place. Envelopes encrypted under a key other
+/// than the given one (which was deleted) are replaced by `null`. Objects that merely look
+/// like envelopes, without the version and fields this store writes, are left as they are.
+fn open(value: &mut Value, key: Option<&DataKey>, redacted: &mut bool) -> Result<()> {
+    let envelope = match value {
+        Value::Object(fields) if fields.len() == 1 => fields.get(ENCRYPTED).and_then(envelope),
+        _ => None,
+    };
+    let envelope = match envelope {
+        Some(envelope) => envelope,
+        None => {
+            return match value {
+                Value::Object(fields) => fields
+                    .values_mut()
+                    .try_for_each(|field| open(field, key, redacted)),
+                Value::Array(items) => items
+                    .iter_mut()
+                    .try_for_each(|item| open(item, key, redacted)),
+                _ => Ok(()),
+            }
+        }
+    };
+    let unreadable = |reason: &str| Error {
+        kind: Kind::StoreFailure(format!("Encrypted event data {}", reason)),
+    };
+    *value = match key.filter(|key| key.id == envelope.key) {
+        Some(key) => {
+            let nonce = base64::decode(&envelope.nonce).map_err(|_| unreadable("is malformed"))?;
+            let ciphertext =
+                base64::decode(&envelope.ciphertext).map_err(|_| unreadable("is malformed"))?;
+            if nonce.len() != 12 {
+                return Err(unreadable("is malformed"));
+            }
+            let plaintext = key
+                .cipher()
+                .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+                .map_err(|_| unreadable("could not be decrypted"))?;
+            serde_json::from_slice(&plaintext).map_err(|_| unreadable("is malformed"))?
+        }
+        None => {
+            *redacted = true;
+            Value::Null
+        }
+    };
+    Ok(())
+}
+
+/// The envelope held by the value, if it is one of the version this store writes
+fn envelope(value: &Value) -> Option<Envelope> {
+    serde_json::from_value::<Envelope>(value.clone())
+        .ok()
+        .filter(|envelope| envelope.v == ENVELOPE_VERSION)
+}
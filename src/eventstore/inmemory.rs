@@ -5,75 +5,399 @@
 //! it is recommended that you only use this for testing/demonstration purposes.
 
 #[cfg(feature = "eventstore")]
-use super::super::cloudevents::CloudEvent;
+use super::super::cloudevents::{CloudEvent, TimeDimension};
+#[cfg(feature = "eventstore")]
+use super::super::outbox::Outbox;
 use super::super::Event;
-use super::super::Result;
+use super::super::{Error, Kind, Result};
 #[cfg(feature = "eventstore")]
-use super::EventStore;
+use super::{
+    check_version, in_category, Deletion, EventStore, ExpectedVersion, Position, RecordedEvent,
+    StreamMetadata,
+};
 use chrono::prelude::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Mutex;
+
+#[cfg(feature = "eventstore")]
+struct StoredEvent {
+    stream: String,
+    /// The (zero-based) position of the event in its stream
+    position: u64,
+    event: CloudEvent,
+    unpublished: bool,
+}
+
+/// The positions of a stream's events and its metadata, which together with the time decide
+/// which events are hidden. Hidden events are kept, as a real store keeps them until it
+/// scavenges.
+#[cfg(feature = "eventstore")]
+#[derive(Default)]
+struct StreamState {
+    next_position: u64,
+    tombstoned: bool,
+    /// The position before which a soft delete hid the events, kept apart from the
+    /// metadata so that setting the metadata doesn't bring them back
+    deleted_before: Option<u64>,
+    metadata: StreamMetadata,
+}
+
 #[cfg(feature = "eventstore")]
 /// An simple, in-memory implementation of the event store trait
 pub struct MemoryEventStore {
-    evts: Mutex<Vec<CloudEvent>>,
+    evts: Mutex<Vec<StoredEvent>>,
+    // Always locked after `evts`
+    streams: Mutex<HashMap<String, StreamState>>,
+    // Always locked after `evts` and `streams`
+    subscribers: Mutex<Vec<(String, Sender<RecordedEvent>)>>,
 }
 #[cfg(feature = "eventstore")]
 impl MemoryEventStore {
     /// Creates a new in-memory event store. The resulting store is thread-safe.
     pub fn new() -> MemoryEventStore {
         MemoryEventStore {
-            evts: Mutex::new(Vec::<CloudEvent>::new()),
+            evts: Mutex::new(Vec::<StoredEvent>::new()),
+            streams: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Subscribes to the streams of a category (see [`StreamName`](super::StreamName)). The
+    /// subscription first receives the category's events from the given position in the
+    /// feed of all streams on, then every event appended to the category, without gaps or
+    /// duplicates in between. Dropping the receiver ends the subscription.
+    pub fn subscribe_to_category(&self, category: &str, from: Position) -> Receiver<RecordedEvent> {
+        let guard = self.evts.lock().unwrap();
+        let streams = self.streams.lock().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let now = Utc::now();
+        for (index, stored) in guard.iter().enumerate() {
+            if index as u64 >= from.commit()
+                && in_category(&stored.stream, category)
+                && is_visible(stored, &streams, now)
+            {
+                let _ = sender.send(recorded(index, stored));
+            }
         }
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push((category.to_owned(), sender));
+        receiver
+    }
+}
+#[cfg(feature = "eventstore")]
+impl Default for MemoryEventStore {
+    fn default() -> Self {
+        MemoryEventStore::new()
     }
 }
 #[cfg(feature = "eventstore")]
 impl EventStore for MemoryEventStore {
     /// Appends an event to the in-memory store
-    fn append(&self, evt: impl Event, _stream: &str) -> Result<CloudEvent> {
-        let mut guard = self.evts.lock().unwrap();
-        let cloud_event = CloudEvent::from(evt);
-        guard.push(cloud_event.clone());
-        Ok(cloud_event)
+    fn append(&self, evt: impl Event, stream: &str) -> Result<CloudEvent> {
+        Ok(self
+            .push(vec![evt], stream, ExpectedVersion::Any, false)?
+            .remove(0))
     }
-}
 
-#[cfg(feature = "eventstore")]
-impl MemoryEventStore {
-    pub fn get_all(&self, event_type: &str) -> Result<Vec<CloudEvent>> {
+    /// Checks the version and appends the events under the same lock
+    fn append_batch<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        if evts.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.push(evts, stream, expected, false)
+    }
+
+    fn read_stream(&self, stream: &str) -> Result<Vec<CloudEvent>> {
         let guard = self.evts.lock().unwrap();
+        let streams = self.streams.lock().unwrap();
+        if streams.get(stream).is_some_and(|state| state.tombstoned) {
+            return Err(deleted(stream));
+        }
+        let now = Utc::now();
         let matches = guard
             .iter()
-            .filter(|evt| evt.event_type == event_type)
-            .cloned()
+            .filter(|stored| stored.stream == stream && is_visible(stored, &streams, now))
+            .map(|stored| stored.event.clone())
             .collect();
-
         Ok(matches)
     }
 
-    pub fn get_from(&self, event_type: &str, start: DateTime<Utc>) -> Result<Vec<CloudEvent>> {
+    fn read_stream_numbered(&self, stream: &str) -> Result<Vec<(u64, CloudEvent)>> {
         let guard = self.evts.lock().unwrap();
+        let streams = self.streams.lock().unwrap();
+        if streams.get(stream).is_some_and(|state| state.tombstoned) {
+            return Err(deleted(stream));
+        }
+        let now = Utc::now();
         let matches = guard
             .iter()
-            .filter(|evt| evt.event_type == event_type && evt.event_time >= start)
-            .cloned()
+            .filter(|stored| stored.stream == stream && is_visible(stored, &streams, now))
+            .map(|stored| (stored.position, stored.event.clone()))
             .collect();
         Ok(matches)
     }
 
+    fn delete_stream(&self, stream: &str, deletion: Deletion) -> Result<()> {
+        let guard = self.evts.lock().unwrap();
+        let mut streams = self.streams.lock().unwrap();
+        let now = Utc::now();
+        let exists = guard
+            .iter()
+            .any(|stored| stored.stream == stream && is_visible(stored, &streams, now));
+        let state = streams.entry(stream.to_owned()).or_default();
+        if state.tombstoned {
+            return Err(deleted(stream));
+        }
+        if !exists {
+            return Err(Error {
+                kind: Kind::StoreFailure(format!("Stream {} does not exist", stream)),
+            });
+        }
+        match deletion {
+            Deletion::Soft => state.deleted_before = Some(state.next_position),
+            Deletion::Hard => state.tombstoned = true,
+        }
+        Ok(())
+    }
+
+    fn get_stream_metadata(&self, stream: &str) -> Result<StreamMetadata> {
+        let streams = self.streams.lock().unwrap();
+        match streams.get(stream) {
+            Some(state) if state.tombstoned => Err(deleted(stream)),
+            Some(state) => Ok(state.metadata.clone()),
+            None => Ok(StreamMetadata::default()),
+        }
+    }
+
+    /// Sets the stream's metadata, whose retention rules apply from the next read on
+    fn set_stream_metadata(&self, stream: &str, metadata: StreamMetadata) -> Result<()> {
+        let mut streams = self.streams.lock().unwrap();
+        let state = streams.entry(stream.to_owned()).or_default();
+        if state.tombstoned {
+            return Err(deleted(stream));
+        }
+        state.metadata = metadata;
+        Ok(())
+    }
+
+    /// Reads the events of all streams. The position of an event counts the events appended
+    /// to the store up to and including it.
+    fn read_all(&self, from: Position, max_count: usize) -> Result<Vec<RecordedEvent>> {
+        let guard = self.evts.lock().unwrap();
+        let streams = self.streams.lock().unwrap();
+        let now = Utc::now();
+        let recorded = guard
+            .iter()
+            .enumerate()
+            .skip(usize::try_from(from.commit()).unwrap_or(usize::MAX))
+            .filter(|(_, stored)| is_visible(stored, &streams, now))
+            .take(max_count)
+            .map(|(index, stored)| recorded(index, stored))
+            .collect();
+        Ok(recorded)
+    }
+}
+
+#[cfg(feature = "eventstore")]
+impl Outbox for MemoryEventStore {
+    /// Appends an event and queues it for publication under the same lock
+    fn append_to_outbox(&self, evt: impl Event, stream: &str) -> Result<CloudEvent> {
+        Ok(self
+            .push(vec![evt], stream, ExpectedVersion::Any, true)?
+            .remove(0))
+    }
+
+    fn append_batch_to_outbox<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        if evts.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.push(evts, stream, expected, true)
+    }
+
+    fn pending_publications(&self) -> Result<Vec<CloudEvent>> {
+        let guard = self.evts.lock().unwrap();
+        let pending = guard
+            .iter()
+            .filter(|stored| stored.unpublished)
+            .map(|stored| stored.event.clone())
+            .collect();
+        Ok(pending)
+    }
+
+    fn mark_published(&self, event_id: &str) -> Result<()> {
+        let mut guard = self.evts.lock().unwrap();
+        for stored in guard.iter_mut() {
+            if stored.event.event_id == event_id {
+                stored.unpublished = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "eventstore")]
+impl MemoryEventStore {
+    /// Appends the events if the stream is at the expected version. A soft deleted stream
+    /// counts as not existing until it is appended to again.
+    fn push<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+        unpublished: bool,
+    ) -> Result<Vec<CloudEvent>> {
+        if stream.is_empty() {
+            return Err(Error {
+                kind: Kind::StoreFailure("Stream name must not be empty".to_owned()),
+            });
+        }
+        let mut guard = self.evts.lock().unwrap();
+        let mut streams = self.streams.lock().unwrap();
+        let state = streams.entry(stream.to_owned()).or_default();
+        if state.tombstoned {
+            return Err(deleted(stream));
+        }
+        let current = Some(state.next_position)
+            .filter(|next| *next > state.deleted_before.unwrap_or(0))
+            .map(|next| next - 1);
+        check_version(stream, expected, current)?;
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let mut appended = Vec::with_capacity(evts.len());
+        for evt in evts {
+            let cloud_event = CloudEvent::from(evt);
+            let stored = StoredEvent {
+                stream: stream.to_owned(),
+                position: state.next_position,
+                event: cloud_event.clone(),
+                unpublished,
+            };
+            state.next_position += 1;
+            let index = guard.len();
+            subscribers.retain(|(category, sender)| {
+                !in_category(stream, category) || sender.send(recorded(index, &stored)).is_ok()
+            });
+            guard.push(stored);
+            appended.push(cloud_event);
+        }
+        Ok(appended)
+    }
+
+    pub fn get_all(&self, event_type: &str) -> Result<Vec<CloudEvent>> {
+        self.matching(|evt| evt.event_type == event_type)
+    }
+
+    pub fn get_from(&self, event_type: &str, start: DateTime<Utc>) -> Result<Vec<CloudEvent>> {
+        self.matching(|evt| {
+            evt.event_type == event_type && evt.event_time.is_some_and(|time| time >= start)
+        })
+    }
+
     pub fn get_range(
         &self,
         event_type: &str,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
     ) -> Result<Vec<CloudEvent>> {
+        self.get_range_by(event_type, TimeDimension::Recorded, start, end)
+    }
+
+    /// Gets the events of the given type whose time in the given dimension falls within
+    /// the given (inclusive) range. Events without a time are left out.
+    pub fn get_range_by(
+        &self,
+        event_type: &str,
+        dimension: TimeDimension,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CloudEvent>> {
+        self.matching(|evt| {
+            evt.event_type == event_type
+                && evt
+                    .time(dimension)
+                    .is_some_and(|time| time >= start && time <= end)
+        })
+    }
+
+    fn matching<F>(&self, predicate: F) -> Result<Vec<CloudEvent>>
+    where
+        F: Fn(&CloudEvent) -> bool,
+    {
         let guard = self.evts.lock().unwrap();
+        let streams = self.streams.lock().unwrap();
+        let now = Utc::now();
         let matches = guard
             .iter()
-            .filter(|evt| {
-                evt.event_type == event_type && evt.event_time >= start && evt.event_time <= end
-            })
+            .filter(|stored| is_visible(stored, &streams, now))
+            .map(|stored| &stored.event)
+            .filter(|evt| predicate(evt))
             .cloned()
             .collect();
         Ok(matches)
     }
 }
+
+/// Whether an event is neither in a hard deleted stream nor hidden by deleting or
+/// truncating its stream or by the stream's retention rules
+#[cfg(feature = "eventstore")]
+fn is_visible(
+    stored: &StoredEvent,
+    streams: &HashMap<String, StreamState>,
+    now: DateTime<Utc>,
+) -> bool {
+    let state = match streams.get(&stored.stream) {
+        Some(state) => state,
+        None => return true,
+    };
+    let StreamMetadata {
+        max_age,
+        max_count,
+        truncate_before,
+        ..
+    } = state.metadata;
+    !state.tombstoned
+        && state
+            .deleted_before
+            .into_iter()
+            .all(|position| stored.position >= position)
+        && truncate_before
+            .into_iter()
+            .all(|position| stored.position >= position)
+        && max_count
+            .into_iter()
+            .all(|count| stored.position + count >= state.next_position)
+        && max_age
+            .zip(stored.event.event_time)
+            .into_iter()
+            .all(|(age, time)| time + age >= now)
+}
+
+#[cfg(feature = "eventstore")]
+fn recorded(index: usize, stored: &StoredEvent) -> RecordedEvent {
+    let next = index as u64 + 1;
+    RecordedEvent {
+        position: Position::new(next, next),
+        stream: stored.stream.clone(),
+        event: stored.event.clone(),
+    }
+}
+
+#[cfg(feature = "eventstore")]
+fn deleted(stream: &str) -> Error {
+    Error {
+        kind: Kind::StoreFailure(format!("Stream {} has been deleted", stream)),
+    }
+}
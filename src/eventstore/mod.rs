@@ -1,7 +1,20 @@
 //! Event store trait and implementations
 #[cfg(feature = "eventstore")]
-use super::cloudevents::CloudEvent;
-use super::{Event, Result};
+use super::cloudevents::{CloudEvent, TimeDimension};
+#[cfg(feature = "eventstore")]
+use super::{Error, Event, Kind, Result};
+#[cfg(feature = "eventstore")]
+use chrono::prelude::*;
+#[cfg(feature = "eventstore")]
+use chrono::Duration;
+#[cfg(feature = "eventstore")]
+use serde_json::{Map, Value};
+#[cfg(feature = "eventstore")]
+use std::fmt;
+#[cfg(feature = "eventstore")]
+use std::str::FromStr;
+#[cfg(feature = "eventstore")]
+use std::sync::Arc;
 
 #[cfg(feature = "eventstore")]
 pub use self::inmemory::MemoryEventStore;
@@ -10,11 +23,678 @@ pub use self::inmemory::MemoryEventStore;
 pub use self::orgeventstore::OrgEventStore;
 
 #[cfg(feature = "eventstore")]
-/// Trait required for event stores. For the moment, event stores are append-only
+/// Trait required for event stores. Stored events are never changed, but stores may support
+/// deleting and truncating streams.
 pub trait EventStore {
+    /// Appends an event to the end of the named stream, returning the stored cloud event
     fn append(&self, evt: impl Event, stream: &str) -> Result<CloudEvent>;
+
+    /// Appends events to the end of the named stream if the stream is at the expected
+    /// version, returning the stored cloud events. Appending to a stream at another version
+    /// fails with a store failure. An empty batch appends nothing and isn't checked.
+    ///
+    /// Stores that support optimistic concurrency check the version and append the batch
+    /// atomically. By default, the version is read from the stream and the events are then
+    /// appended one at a time, so a concurrent append can slip in between, and a failing
+    /// append leaves the events before it appended.
+    fn append_batch<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        if evts.is_empty() {
+            return Ok(Vec::new());
+        }
+        check_read_version(self, stream, expected)?;
+        evts.into_iter()
+            .map(|evt| self.append(evt, stream))
+            .collect()
+    }
+
+    /// Reads all of the events in the named stream in the order in which they were appended.
+    /// Reading a stream that does not exist yields no events. Stores that can't read their
+    /// streams back fail.
+    fn read_stream(&self, stream: &str) -> Result<Vec<CloudEvent>> {
+        Err(unsupported(stream, "read"))
+    }
+
+    /// Reads the events in the named stream along with their (zero-based) positions in it,
+    /// which skip the events hidden by deleting or truncating the stream or by its retention
+    /// rules. By default, positions are counted from the start of the stream, so reading a
+    /// stream whose metadata hides events fails.
+    fn read_stream_numbered(&self, stream: &str) -> Result<Vec<(u64, CloudEvent)>> {
+        if self.get_stream_metadata(stream)?.hides_events() {
+            return Err(Error {
+                kind: Kind::StoreFailure(format!(
+                    "Stream {} hides events, so this event store can't number the others",
+                    stream
+                )),
+            });
+        }
+        Ok(self
+            .read_stream(stream)?
+            .into_iter()
+            .enumerate()
+            .map(|(position, evt)| (position as u64, evt))
+            .collect())
+    }
+
+    /// Reads the events in the named stream whose event time falls within the given
+    /// (inclusive) range, in the order in which they were appended.
+    fn read_stream_range(
+        &self,
+        stream: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CloudEvent>> {
+        self.read_stream_range_by(stream, TimeDimension::Recorded, start, end)
+    }
+
+    /// Reads the events in the named stream whose time in the given dimension falls within
+    /// the given (inclusive) range, in the order in which they were appended. Events without
+    /// a time are left out.
+    fn read_stream_range_by(
+        &self,
+        stream: &str,
+        dimension: TimeDimension,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CloudEvent>> {
+        Ok(self
+            .read_stream(stream)?
+            .into_iter()
+            .filter(|evt| {
+                evt.time(dimension)
+                    .is_some_and(|time| time >= start && time <= end)
+            })
+            .collect())
+    }
+
+    /// Deletes the named stream, which must exist. A soft deleted stream reads as empty
+    /// and is recreated by the next append, whose events take the positions after the
+    /// deleted ones. A hard deleted stream leaves a tombstone: reading, appending to or
+    /// deleting it fails from then on. Stores that can't delete streams fail.
+    fn delete_stream(&self, stream: &str, _deletion: Deletion) -> Result<()> {
+        Err(unsupported(stream, "deleted"))
+    }
+
+    /// Hides the events of the named stream before the given (zero-based) position from
+    /// reads, keeping the positions of the later events. This sets the stream's
+    /// `truncate_before` metadata, keeping its other metadata.
+    fn truncate_before(&self, stream: &str, position: u64) -> Result<()> {
+        let metadata = self.get_stream_metadata(stream)?;
+        self.set_stream_metadata(
+            stream,
+            StreamMetadata {
+                truncate_before: Some(position),
+                ..metadata
+            },
+        )
+    }
+
+    /// The metadata of the named stream, which is empty for streams that have none and for
+    /// stores that don't support metadata
+    fn get_stream_metadata(&self, _stream: &str) -> Result<StreamMetadata> {
+        Ok(StreamMetadata::default())
+    }
+
+    /// Replaces the metadata of the named stream. Stores enforce the retention rules it
+    /// sets on read. Stores that don't support metadata fail.
+    fn set_stream_metadata(&self, stream: &str, _metadata: StreamMetadata) -> Result<()> {
+        Err(unsupported(stream, "given metadata"))
+    }
+
+    /// Reads up to `max_count` events of all streams in the order in which they were
+    /// appended, starting at the given position: [`Position::START`], or the position of an
+    /// event read before to continue after it. Events hidden from their streams' reads are
+    /// skipped. Stores that don't keep a global order fail.
+    fn read_all(&self, _from: Position, _max_count: usize) -> Result<Vec<RecordedEvent>> {
+        Err(Error {
+            kind: Kind::StoreFailure("All streams can't be read by this event store".to_owned()),
+        })
+    }
+
+    /// Reads the events of the streams of every aggregate in the category (see
+    /// [`StreamName`]) in the order in which they were appended. Positions increase through
+    /// the category's feed, but needn't be positions in the feed of all streams. By default
+    /// this filters the feed of all streams.
+    fn read_category(&self, category: &str) -> Result<Vec<RecordedEvent>> {
+        Ok(self
+            .read_all(Position::START, usize::MAX)?
+            .into_iter()
+            .filter(|recorded| in_category(&recorded.stream, category))
+            .collect())
+    }
+}
+
+#[cfg(feature = "eventstore")]
+/// Checks a stream's current version, the (zero-based) number of its last event or `None`
+/// if it doesn't exist, against the expected one
+pub(crate) fn check_version(
+    stream: &str,
+    expected: ExpectedVersion,
+    current: Option<u64>,
+) -> Result<()> {
+    let matches = match expected {
+        ExpectedVersion::Any => true,
+        ExpectedVersion::NoStream => current.is_none(),
+        ExpectedVersion::StreamExists => current.is_some(),
+        ExpectedVersion::Exact(version) => current == Some(version),
+    };
+    if matches {
+        return Ok(());
+    }
+    Err(Error {
+        kind: Kind::StoreFailure(format!(
+            "Wrong expected version for stream {}: expected {:?}, current version is {}",
+            stream,
+            expected,
+            current.map_or("-1".to_owned(), |version| version.to_string())
+        )),
+    })
+}
+
+#[cfg(feature = "eventstore")]
+/// Checks the version of the stream, as read from the store, against the expected one
+pub(crate) fn check_read_version<S: EventStore + ?Sized>(
+    store: &S,
+    stream: &str,
+    expected: ExpectedVersion,
+) -> Result<()> {
+    if expected == ExpectedVersion::Any {
+        return Ok(());
+    }
+    let current = store
+        .read_stream_numbered(stream)?
+        .last()
+        .map(|(position, _)| *position);
+    check_version(stream, expected, current)
 }
 
+#[cfg(feature = "eventstore")]
+pub(crate) fn unsupported(stream: &str, action: &str) -> Error {
+    Error {
+        kind: Kind::StoreFailure(format!(
+            "Stream {} can't be {} by this event store",
+            stream, action
+        )),
+    }
+}
+
+#[cfg(feature = "eventstore")]
+/// A store wrapping another, such as one encrypting or hash-chaining its events. The
+/// decorator implements `EventStore` through [`decorated_event_store!`], which hands every
+/// operation to the inner store, through the hooks below.
+pub(crate) trait Decorator {
+    type Inner: EventStore;
+
+    fn inner(&self) -> &Self::Inner;
+
+    /// Appends events to the named stream, as `EventStore::append_batch` does
+    fn append_events<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        self.inner().append_batch(evts, stream, expected)
+    }
+
+    /// Passes the events read from the named stream through the decorator
+    fn read_events(&self, _stream: &str, evts: Vec<CloudEvent>) -> Result<Vec<CloudEvent>> {
+        Ok(evts)
+    }
+
+    /// Passes the events read from the feed of all streams, or of a category, through the
+    /// decorator
+    fn read_recorded(&self, recorded: Vec<RecordedEvent>) -> Result<Vec<RecordedEvent>> {
+        Ok(recorded)
+    }
+
+    /// Checks that the named stream may be deleted, truncated or given metadata (the
+    /// action) through the decorator
+    fn manage_stream(&self, _stream: &str, _action: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "eventstore")]
+/// Implements `EventStore` for a [`Decorator`], so that every operation of the trait goes
+/// through the decorator's hooks or to the inner store and none is left to its default.
+/// Takes the impl's generics in brackets, then the decorator type; `Decorator` must be in
+/// scope.
+macro_rules! decorated_event_store {
+    ([$($generics:tt)*] $decorator:ty) => {
+        impl<$($generics)*> $crate::eventstore::EventStore for $decorator {
+            fn append(
+                &self,
+                evt: impl $crate::Event,
+                stream: &str,
+            ) -> $crate::Result<$crate::cloudevents::CloudEvent> {
+                Ok(self
+                    .append_events(
+                        vec![evt],
+                        stream,
+                        $crate::eventstore::ExpectedVersion::Any,
+                    )?
+                    .remove(0))
+            }
+
+            fn append_batch<E: $crate::Event>(
+                &self,
+                evts: Vec<E>,
+                stream: &str,
+                expected: $crate::eventstore::ExpectedVersion,
+            ) -> $crate::Result<Vec<$crate::cloudevents::CloudEvent>> {
+                self.append_events(evts, stream, expected)
+            }
+
+            fn read_stream(
+                &self,
+                stream: &str,
+            ) -> $crate::Result<Vec<$crate::cloudevents::CloudEvent>> {
+                let evts = self.inner().read_stream(stream)?;
+                self.read_events(stream, evts)
+            }
+
+            fn read_stream_numbered(
+                &self,
+                stream: &str,
+            ) -> $crate::Result<Vec<(u64, $crate::cloudevents::CloudEvent)>> {
+                let (positions, evts): (Vec<u64>, Vec<_>) = self
+                    .inner()
+                    .read_stream_numbered(stream)?
+                    .into_iter()
+                    .unzip();
+                let evts = self.read_events(stream, evts)?;
+                Ok(positions.into_iter().zip(evts).collect())
+            }
+
+            fn read_stream_range(
+                &self,
+                stream: &str,
+                start: ::chrono::DateTime<::chrono::Utc>,
+                end: ::chrono::DateTime<::chrono::Utc>,
+            ) -> $crate::Result<Vec<$crate::cloudevents::CloudEvent>> {
+                let evts = self.inner().read_stream_range(stream, start, end)?;
+                self.read_events(stream, evts)
+            }
+
+            fn read_stream_range_by(
+                &self,
+                stream: &str,
+                dimension: $crate::cloudevents::TimeDimension,
+                start: ::chrono::DateTime<::chrono::Utc>,
+                end: ::chrono::DateTime<::chrono::Utc>,
+            ) -> $crate::Result<Vec<$crate::cloudevents::CloudEvent>> {
+                let evts = self.inner()
+                    .read_stream_range_by(stream, dimension, start, end)?;
+                self.read_events(stream, evts)
+            }
+
+            fn delete_stream(
+                &self,
+                stream: &str,
+                deletion: $crate::eventstore::Deletion,
+            ) -> $crate::Result<()> {
+                self.manage_stream(stream, "deleted")?;
+                self.inner().delete_stream(stream, deletion)
+            }
+
+            fn truncate_before(&self, stream: &str, position: u64) -> $crate::Result<()> {
+                self.manage_stream(stream, "truncated")?;
+                self.inner().truncate_before(stream, position)
+            }
+
+            fn get_stream_metadata(
+                &self,
+                stream: &str,
+            ) -> $crate::Result<$crate::eventstore::StreamMetadata> {
+                self.inner().get_stream_metadata(stream)
+            }
+
+            fn set_stream_metadata(
+                &self,
+                stream: &str,
+                metadata: $crate::eventstore::StreamMetadata,
+            ) -> $crate::Result<()> {
+                self.manage_stream(stream, "given metadata")?;
+                self.inner().set_stream_metadata(stream, metadata)
+            }
+
+            fn read_all(
+                &self,
+                from: $crate::eventstore::Position,
+                max_count: usize,
+            ) -> $crate::Result<Vec<$crate::eventstore::RecordedEvent>> {
+                let recorded = self.inner().read_all(from, max_count)?;
+                self.read_recorded(recorded)
+            }
+
+            fn read_category(
+                &self,
+                category: &str,
+            ) -> $crate::Result<Vec<$crate::eventstore::RecordedEvent>> {
+                let recorded = self.inner().read_category(category)?;
+                self.read_recorded(recorded)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "eventstore")]
+pub(crate) use decorated_event_store;
+
+#[cfg(feature = "eventstore")]
+/// Shared stores, e.g. a store registered with a command bus that is also read elsewhere
+impl<S: EventStore + ?Sized> EventStore for Arc<S> {
+    fn append(&self, evt: impl Event, stream: &str) -> Result<CloudEvent> {
+        (**self).append(evt, stream)
+    }
+
+    fn append_batch<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        (**self).append_batch(evts, stream, expected)
+    }
+
+    fn read_stream(&self, stream: &str) -> Result<Vec<CloudEvent>> {
+        (**self).read_stream(stream)
+    }
+
+    fn read_stream_numbered(&self, stream: &str) -> Result<Vec<(u64, CloudEvent)>> {
+        (**self).read_stream_numbered(stream)
+    }
+
+    fn read_stream_range(
+        &self,
+        stream: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CloudEvent>> {
+        (**self).read_stream_range(stream, start, end)
+    }
+
+    fn read_stream_range_by(
+        &self,
+        stream: &str,
+        dimension: TimeDimension,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CloudEvent>> {
+        (**self).read_stream_range_by(stream, dimension, start, end)
+    }
+
+    fn delete_stream(&self, stream: &str, deletion: Deletion) -> Result<()> {
+        (**self).delete_stream(stream, deletion)
+    }
+
+    fn truncate_before(&self, stream: &str, position: u64) -> Result<()> {
+        (**self).truncate_before(stream, position)
+    }
+
+    fn get_stream_metadata(&self, stream: &str) -> Result<StreamMetadata> {
+        (**self).get_stream_metadata(stream)
+    }
+
+    fn set_stream_metadata(&self, stream: &str, metadata: StreamMetadata) -> Result<()> {
+        (**self).set_stream_metadata(stream, metadata)
+    }
+
+    fn read_all(&self, from: Position, max_count: usize) -> Result<Vec<RecordedEvent>> {
+        (**self).read_all(from, max_count)
+    }
+
+    fn read_category(&self, category: &str) -> Result<Vec<RecordedEvent>> {
+        (**self).read_category(category)
+    }
+}
+
+#[cfg(feature = "eventstore")]
+/// The version a stream is expected to be at when appending to it. Stores that support
+/// optimistic concurrency reject appends when the stream is at a different version.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpectedVersion {
+    /// Append regardless of the stream's current version
+    Any,
+    /// The stream must not exist yet
+    NoStream,
+    /// The stream must exist, at any version
+    StreamExists,
+    /// The last event in the stream must have this (zero-based) number
+    Exact(u64),
+}
+
+#[cfg(feature = "eventstore")]
+/// A position in a store's feed of all streams. Positions are opaque: each store decides
+/// what they hold, eventstore.org's being a commit and a prepare position in its transaction
+/// log. They order the events of a feed, and can be kept as text to continue reading later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    commit: u64,
+    prepare: u64,
+}
+
+#[cfg(feature = "eventstore")]
+impl Position {
+    /// The position before the first event of every feed
+    pub const START: Position = Position {
+        commit: 0,
+        prepare: 0,
+    };
+
+    /// Creates a position from a commit and a prepare position, for stores implementing
+    /// [`EventStore::read_all`]
+    pub fn new(commit: u64, prepare: u64) -> Position {
+        Position { commit, prepare }
+    }
+
+    pub fn commit(&self) -> u64 {
+        self.commit
+    }
+
+    pub fn prepare(&self) -> u64 {
+        self.prepare
+    }
+}
+
+#[cfg(feature = "eventstore")]
+/// Positions are written as eventstore.org writes them in `$all` feed links: the commit and
+/// the prepare position as 16 hex digits each
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016X}{:016X}", self.commit, self.prepare)
+    }
+}
+
+#[cfg(feature = "eventstore")]
+impl FromStr for Position {
+    type Err = Error;
+
+    fn from_str(position: &str) -> Result<Position> {
+        let invalid = || Error {
+            kind: Kind::ValidationFailure(format!("{:?} is not a feed position", position)),
+        };
+        if position.len() != 32 || !position.is_ascii() {
+            return Err(invalid());
+        }
+        let (commit, prepare) = position.split_at(16);
+        match (
+            u64::from_str_radix(commit, 16),
+            u64::from_str_radix(prepare, 16),
+        ) {
+            (Ok(commit), Ok(prepare)) => Ok(Position { commit, prepare }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(feature = "eventstore")]
+/// An event read from the feed of all streams
+#[derive(Debug, Clone)]
+pub struct RecordedEvent {
+    /// The position from which reading the feed continues after the event. Positions never
+    /// decrease along a feed. Stores that only know the positions between the pages of their
+    /// feed, as eventstore.org does, give the events within a page the position the page
+    /// starts at, so continuing from those reads some events again but never skips one.
+    pub position: Position,
+    /// The stream the event was appended to
+    pub stream: String,
+    pub event: CloudEvent,
+}
+
+#[cfg(feature = "eventstore")]
+/// Separates the category of a stream name from the ID
+pub const CATEGORY_SEPARATOR: char = '-';
+
+#[cfg(feature = "eventstore")]
+/// A stream name following the `{category}-{id}` convention, e.g. `account-123`, which groups
+/// the streams of the same kind of aggregate into a category. As with eventstore.org's
+/// `$by_category` projection, the category ends at the first separator, so IDs may contain
+/// separators but categories can't. A name without an ID stands for the category itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StreamName {
+    category: String,
+    id: Option<String>,
+}
+
+#[cfg(feature = "eventstore")]
+impl StreamName {
+    /// Names the stream of the aggregate with the given ID in the category
+    pub fn new(category: &str, id: &str) -> Result<StreamName> {
+        if id.is_empty() {
+            return Err(invalid_name(&format!("{}{}", category, CATEGORY_SEPARATOR)));
+        }
+        Ok(StreamName {
+            id: Some(id.to_owned()),
+            ..StreamName::for_category(category)?
+        })
+    }
+
+    /// Names a category without an ID
+    pub fn for_category(category: &str) -> Result<StreamName> {
+        if category.is_empty() || category.contains(CATEGORY_SEPARATOR) {
+            return Err(invalid_name(category));
+        }
+        Ok(StreamName {
+            category: category.to_owned(),
+            id: None,
+        })
+    }
+
+    /// Splits a stream name at its first separator
+    pub fn parse(name: &str) -> Result<StreamName> {
+        match name.split_once(CATEGORY_SEPARATOR) {
+            Some((category, id)) => StreamName::new(category, id),
+            None => StreamName::for_category(name),
+        }
+        .map_err(|_| invalid_name(name))
+    }
+
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+}
+
+#[cfg(feature = "eventstore")]
+/// Whether the named stream is the stream of an aggregate in the category
+pub(crate) fn in_category(stream: &str, category: &str) -> bool {
+    StreamName::parse(stream).is_ok_and(|name| name.id.is_some() && name.category == category)
+}
+
+#[cfg(feature = "eventstore")]
+fn invalid_name(name: &str) -> Error {
+    Error {
+        kind: Kind::ValidationFailure(format!(
+            "Stream name {:?} doesn't follow the {{category}}{}{{id}} convention",
+            name, CATEGORY_SEPARATOR
+        )),
+    }
+}
+
+#[cfg(feature = "eventstore")]
+impl fmt::Display for StreamName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.id {
+            Some(ref id) => write!(f, "{}{}{}", self.category, CATEGORY_SEPARATOR, id),
+            None => f.write_str(&self.category),
+        }
+    }
+}
+
+#[cfg(feature = "eventstore")]
+impl FromStr for StreamName {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<StreamName> {
+        StreamName::parse(name)
+    }
+}
+
+#[cfg(feature = "eventstore")]
+/// How a stream is deleted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Deletion {
+    /// Hide the stream's events, allowing the stream to be recreated
+    Soft,
+    /// Leave a tombstone, so that the stream can never be used again
+    Hard,
+}
+
+#[cfg(feature = "eventstore")]
+/// Settings of a stream: retention rules, as with eventstore.org's `$maxAge` and `$maxCount`,
+/// and custom properties such as the owning team or a data classification
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamMetadata {
+    /// How long events are kept after they were recorded. Stores may round it to seconds.
+    pub max_age: Option<Duration>,
+    /// How many of the stream's latest events are kept
+    pub max_count: Option<u64>,
+    /// The (zero-based) position before which events are hidden
+    pub truncate_before: Option<u64>,
+    pub custom: Map<String, Value>,
+}
+
+#[cfg(feature = "eventstore")]
+impl StreamMetadata {
+    /// Whether the metadata hides some of the stream's events from reads, by truncating it
+    /// or through retention rules
+    pub fn hides_events(&self) -> bool {
+        self.max_age.is_some() || self.max_count.is_some() || self.truncate_before.is_some()
+    }
+
+    /// Sets how long events are kept after they were recorded
+    pub fn with_max_age(self, max_age: Duration) -> StreamMetadata {
+        StreamMetadata {
+            max_age: Some(max_age),
+            ..self
+        }
+    }
+
+    /// Sets how many of the stream's latest events are kept
+    pub fn with_max_count(self, max_count: u64) -> StreamMetadata {
+        StreamMetadata {
+            max_count: Some(max_count),
+            ..self
+        }
+    }
+
+    /// Sets a custom property
+    pub fn with_property(mut self, name: &str, value: impl Into<Value>) -> StreamMetadata {
+        self.custom.insert(name.to_owned(), value.into());
+        self
+    }
+}
+
+#[cfg(feature = "eventstore")]
 mod inmemory;
 #[cfg(feature = "orgeventstore")]
 mod orgeventstore;
@@ -4,14 +4,21 @@
 use super::super::cloudevents::CloudEvent;
 use super::super::{Error, Event, Kind, Result};
 #[cfg(feature = "orgeventstore")]
-use super::EventStore;
-use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use super::{Deletion, EventStore, ExpectedVersion, Position, RecordedEvent, StreamMetadata};
+use chrono::Duration;
+use reqwest::blocking::RequestBuilder;
+use reqwest::header::{HeaderMap, ACCEPT, CONTENT_TYPE};
 use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+/// Number of events requested per page when reading a stream's Atom feed
+const PAGE_SIZE: u64 = 20;
 
 /// Client for the eventstore.org Event Store
 pub struct OrgEventStore {
     host: String,
     port: u16,
+    credentials: Option<(String, String)>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -20,6 +27,57 @@ struct StoreEvent {
     event_id: String,
     event_type: String,
     data: serde_json::Value,
+    metadata: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct AtomFeed {
+    entries: Vec<AtomEntry>,
+    #[serde(rename = "headOfStream", default)]
+    head_of_stream: Option<bool>,
+    #[serde(default)]
+    links: Vec<AtomLink>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AtomLink {
+    uri: String,
+    relation: String,
+}
+
+impl AtomFeed {
+    /// The URI of the feed's link with the given relation, if it has one
+    fn link(&self, relation: &str) -> Option<&str> {
+        self.links
+            .iter()
+            .find(|link| link.relation == relation)
+            .map(|link| link.uri.as_str())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AtomEntry {
+    event_id: String,
+    event_type: String,
+    event_number: u64,
+    #[serde(default)]
+    data: Value,
+    #[serde(default)]
+    meta_data: Value,
+    updated: String,
+    #[serde(default)]
+    stream_id: Option<String>,
+    /// The entry's number in the stream being read, which differs from the event number
+    /// for events linked from another stream
+    #[serde(default)]
+    position_event_number: Option<u64>,
+}
+
+impl AtomEntry {
+    fn position_in_feed(&self) -> u64 {
+        self.position_event_number.unwrap_or(self.event_number)
+    }
 }
 
 impl OrgEventStore {
@@ -28,12 +86,240 @@ impl OrgEventStore {
         OrgEventStore {
             host: host.to_owned(),
             port,
+            credentials: None,
+        }
+    }
+
+    /// Sends the given user's credentials with every request. Reading the `$all` feed
+    /// needs an admin user's, such as the default `admin`/`changeit`.
+    pub fn with_credentials(self, username: &str, password: &str) -> OrgEventStore {
+        OrgEventStore {
+            credentials: Some((username.to_owned(), password.to_owned())),
+            ..self
+        }
+    }
+
+    /// Adds the credentials, if any, to a request
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match self.credentials {
+            Some((ref username, ref password)) => request.basic_auth(username, Some(password)),
+            None => request,
         }
     }
 
     fn build_stream_url(&self, stream: &str) -> String {
         format!("http://{}:{}/streams/{}", self.host, self.port, stream)
     }
+
+    fn build_metadata_url(&self, stream: &str) -> String {
+        format!("{}/metadata", self.build_stream_url(stream))
+    }
+
+    fn build_page_url(&self, stream: &str, start: u64) -> String {
+        format!(
+            "{}/{}/forward/{}?embed=tryharder",
+            self.build_stream_url(stream),
+            start,
+            PAGE_SIZE
+        )
+    }
+
+    fn build_all_page_url(&self, position: Position, count: u64) -> String {
+        format!(
+            "http://{}:{}/streams/%24all/{}/forward/{}?embed=tryharder",
+            self.host, self.port, position, count
+        )
+    }
+}
+
+impl OrgEventStore {
+    /// Appends an event to a stream only if the stream is at the expected version. Appending
+    /// at the wrong version fails with a store failure naming the stream's current version.
+    pub fn append_with_version(
+        &self,
+        evt: impl Event,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<CloudEvent> {
+        Ok(self
+            .post_events(vec![evt], stream, Some(expected))?
+            .remove(0))
+    }
+
+    /// Posts the events in a single request, which the server appends atomically
+    fn post_events<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: Option<ExpectedVersion>,
+    ) -> Result<Vec<CloudEvent>> {
+        if stream.is_empty() {
+            return Err(store_error("Stream name must not be empty".to_owned()));
+        }
+        let ces: Vec<CloudEvent> = evts.into_iter().map(CloudEvent::from).collect();
+        let mut se = Vec::with_capacity(ces.len());
+        for ce in &ces {
+            let mut metadata = serde_json::to_value(ce)
+                .map_err(|e| store_error(format!("Failed to serialize event metadata {:?}", e)))?;
+            if let Value::Object(ref mut attrs) = metadata {
+                attrs.remove("data");
+            }
+            se.push(StoreEvent {
+                event_id: ce.event_id.to_owned(),
+                event_type: ce.event_type.to_owned(),
+                data: ce.data.clone(),
+                metadata,
+            });
+        }
+
+        let client = reqwest::blocking::Client::new();
+
+        let url = self.build_stream_url(stream);
+        let mut headers = generate_headers();
+        if let Some(expected) = expected {
+            headers.insert(
+                EXPECTED_VERSION,
+                expected_version_header(expected).parse().unwrap(),
+            );
+        }
+
+        match self
+            .authorized(client.post(&url))
+            .json(&se)
+            .headers(headers)
+            .send()
+        {
+            Ok(response) => match response.status() {
+                StatusCode::CREATED => Ok(ces),
+                StatusCode::BAD_REQUEST if response.headers().contains_key(CURRENT_VERSION) => {
+                    let current = response.headers()[CURRENT_VERSION]
+                        .to_str()
+                        .unwrap_or("unknown")
+                        .to_owned();
+                    Err(store_error(format!(
+                        "Wrong expected version for stream {}: expected {:?}, current version is {}",
+                        stream,
+                        expected.unwrap_or(ExpectedVersion::Any),
+                        current
+                    )))
+                }
+                StatusCode::GONE => Err(store_error(format!("Stream {} has been deleted", stream))),
+                status => Err(Error {
+                    kind: Kind::StoreFailure(format!("Failed to post to event store ({})", status)),
+                }),
+            },
+            Err(e) => Err(Error {
+                kind: Kind::StoreFailure(format!("Failed to post to event store {:?}", e)),
+            }),
+        }
+    }
+
+    /// Reads the stream's metadata, which is empty if none was ever written
+    fn read_metadata(&self, stream: &str) -> Result<serde_json::Map<String, Value>> {
+        let client = reqwest::blocking::Client::new();
+        let response = self
+            .authorized(client.get(&self.build_metadata_url(stream)))
+            .header(ACCEPT, "application/json")
+            .send()
+            .map_err(|e| store_error(format!("Failed to read from event store {:?}", e)))?;
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Ok(serde_json::Map::new()),
+            StatusCode::GONE => {
+                return Err(store_error(format!("Stream {} has been deleted", stream)))
+            }
+            status => {
+                return Err(store_error(format!(
+                    "Failed to read from event store ({})",
+                    status
+                )))
+            }
+        }
+        let body = response
+            .text()
+            .map_err(|e| store_error(format!("Failed to read from event store {:?}", e)))?;
+        match serde_json::from_str(&body) {
+            Ok(Value::Object(metadata)) => Ok(metadata),
+            _ if body.trim().is_empty() => Ok(serde_json::Map::new()),
+            _ => Err(store_error(format!(
+                "Malformed metadata for stream {}",
+                stream
+            ))),
+        }
+    }
+
+    /// Replaces the stream's metadata
+    fn write_metadata(&self, stream: &str, metadata: serde_json::Map<String, Value>) -> Result<()> {
+        let client = reqwest::blocking::Client::new();
+        let body = json!([{
+            "eventId": uuid::Uuid::new_v4().to_hyphenated().to_string(),
+            "eventType": METADATA_EVENT_TYPE,
+            "data": metadata,
+        }]);
+        let response = self
+            .authorized(client.post(&self.build_metadata_url(stream)))
+            .json(&body)
+            .headers(generate_headers())
+            .send()
+            .map_err(|e| store_error(format!("Failed to post to event store {:?}", e)))?;
+        match response.status() {
+            StatusCode::CREATED => Ok(()),
+            StatusCode::GONE => Err(store_error(format!("Stream {} has been deleted", stream))),
+            status => Err(store_error(format!(
+                "Failed to post to event store ({})",
+                status
+            ))),
+        }
+    }
+
+    /// Reads every entry of a stream's Atom feed, in order
+    fn read_feed(&self, stream: &str) -> Result<Vec<AtomEntry>> {
+        let client = reqwest::blocking::Client::new();
+        let mut entries = Vec::new();
+        let mut start = 0;
+
+        loop {
+            let response = self
+                .authorized(client.get(&self.build_page_url(stream, start)))
+                .headers(generate_read_headers())
+                .send()
+                .map_err(|e| store_error(format!("Failed to read from event store {:?}", e)))?;
+            match response.status() {
+                StatusCode::OK => {}
+                StatusCode::NOT_FOUND => return Ok(entries),
+                StatusCode::GONE => {
+                    return Err(store_error(format!("Stream {} has been deleted", stream)))
+                }
+                status => {
+                    return Err(store_error(format!(
+                        "Failed to read from event store ({})",
+                        status
+                    )))
+                }
+            }
+            let feed: AtomFeed = response
+                .json()
+                .map_err(|e| store_error(format!("Malformed event store feed {:?}", e)))?;
+
+            // Feed pages list their entries newest first. Pages of a truncated stream can
+            // be empty without being the last.
+            let page_len = feed.entries.len() as u64;
+            let more = match feed.head_of_stream {
+                Some(head) => !head,
+                None => page_len == PAGE_SIZE,
+            };
+            let mut page = feed.entries;
+            page.sort_by_key(AtomEntry::position_in_feed);
+            start = match page.last() {
+                Some(last) => last.position_in_feed() + 1,
+                None => start + PAGE_SIZE,
+            };
+            entries.extend(page);
+            if !more {
+                return Ok(entries);
+            }
+        }
+    }
 }
 
 impl Default for OrgEventStore {
@@ -43,6 +329,26 @@ impl Default for OrgEventStore {
     }
 }
 
+const EXPECTED_VERSION: &str = "ES-ExpectedVersion";
+const CURRENT_VERSION: &str = "ES-CurrentVersion";
+const HARD_DELETE: &str = "ES-HardDelete";
+const METADATA_EVENT_TYPE: &str = "$metadata";
+/// The metadata settings of a stream's retention rules
+const MAX_AGE: &str = "$maxAge";
+const MAX_COUNT: &str = "$maxCount";
+const TRUNCATE_BEFORE: &str = "$tb";
+/// Prefix of the streams linking to the events of a category
+const CATEGORY_STREAM_PREFIX: &str = "$ce-";
+
+fn expected_version_header(expected: ExpectedVersion) -> String {
+    match expected {
+        ExpectedVersion::Any => "-2".to_owned(),
+        ExpectedVersion::NoStream => "-1".to_owned(),
+        ExpectedVersion::StreamExists => "-4".to_owned(),
+        ExpectedVersion::Exact(n) => n.to_string(),
+    }
+}
+
 fn generate_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
 
@@ -53,36 +359,280 @@ fn generate_headers() -> HeaderMap {
     headers
 }
 
+fn generate_read_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        ACCEPT,
+        "application/vnd.eventstore.atom+json".parse().unwrap(),
+    );
+    headers
+}
+
+fn store_error(msg: String) -> Error {
+    Error {
+        kind: Kind::StoreFailure(msg),
+    }
+}
+
+/// The position a forward `$all` feed link reads from, as in
+/// `.../streams/%24all/{position}/forward/{count}`
+fn link_position(uri: &str) -> Option<Position> {
+    let path = uri.split('?').next()?;
+    let mut segments = path.rsplit('/').skip(1);
+    match (segments.next(), segments.next()) {
+        (Some("forward"), Some(position)) => position.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Embedded bodies are returned as JSON values or, depending on the embed mode,
+/// as strings containing JSON.
+fn embedded_json(value: Value) -> Value {
+    match value {
+        Value::String(s) => serde_json::from_str(&s).unwrap_or(Value::String(s)),
+        other => other,
+    }
+}
+
+/// Rebuilds a cloud event from an Atom entry. The cloud event attributes are kept in the
+/// event metadata; events written by other clients fall back to the entry's own attributes.
+fn entry_to_cloud_event(entry: AtomEntry, stream_url: &str) -> Result<CloudEvent> {
+    let AtomEntry {
+        event_id,
+        event_type,
+        event_number,
+        data,
+        meta_data,
+        updated,
+        ..
+    } = entry;
+    let mut attrs = match embedded_json(meta_data) {
+        Value::Object(attrs) => attrs,
+        _ => serde_json::Map::new(),
+    };
+    attrs
+        .entry("specversion")
+        .or_insert_with(|| Value::String("1.0".to_owned()));
+    attrs.entry("id").or_insert_with(|| Value::String(event_id));
+    attrs
+        .entry("type")
+        .or_insert_with(|| Value::String(event_type));
+    attrs
+        .entry("source")
+        .or_insert_with(|| Value::String(stream_url.to_owned()));
+    attrs
+        .entry("time")
+        .or_insert_with(|| Value::String(updated));
+    attrs.insert("data".to_owned(), embedded_json(data));
+    CloudEvent::from_value(Value::Object(attrs)).map_err(|e| {
+        store_error(format!(
+            "Event {} of {} is not a valid cloud event: {}",
+            event_number, stream_url, e
+        ))
+    })
+}
+
 impl EventStore for OrgEventStore {
     fn append(&self, evt: impl Event, stream: &str) -> Result<CloudEvent> {
-        let ce: CloudEvent = evt.into();
-        let se = vec![StoreEvent {
-            event_id: ce.event_id.to_owned(),
-            event_type: ce.event_type.to_owned(),
-            data: ce.data.clone(),
-        }];
+        Ok(self.post_events(vec![evt], stream, None)?.remove(0))
+    }
+
+    /// Posts the events in a single request with the `ES-ExpectedVersion` header, so the
+    /// server checks the version and appends them atomically
+    fn append_batch<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        if evts.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.post_events(evts, stream, Some(expected))
+    }
+
+    fn read_stream(&self, stream: &str) -> Result<Vec<CloudEvent>> {
+        let stream_url = self.build_stream_url(stream);
+        self.read_feed(stream)?
+            .into_iter()
+            .map(|entry| entry_to_cloud_event(entry, &stream_url))
+            .collect()
+    }
 
+    /// Reads the stream's feed, numbering its events with their event numbers
+    fn read_stream_numbered(&self, stream: &str) -> Result<Vec<(u64, CloudEvent)>> {
+        let stream_url = self.build_stream_url(stream);
+        self.read_feed(stream)?
+            .into_iter()
+            .map(|entry| {
+                let number = entry.position_in_feed();
+                Ok((number, entry_to_cloud_event(entry, &stream_url)?))
+            })
+            .collect()
+    }
+
+    /// Deletes the stream with an HTTP `DELETE`, sending `ES-HardDelete` for hard deletes
+    fn delete_stream(&self, stream: &str, deletion: Deletion) -> Result<()> {
         let client = reqwest::blocking::Client::new();
+        let mut request = self.authorized(client.delete(&self.build_stream_url(stream)));
+        if deletion == Deletion::Hard {
+            request = request.header(HARD_DELETE, "true");
+        }
+        let response = request
+            .send()
+            .map_err(|e| store_error(format!("Failed to delete from event store {:?}", e)))?;
+        match response.status() {
+            StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+            StatusCode::NOT_FOUND => Err(store_error(format!("Stream {} does not exist", stream))),
+            StatusCode::GONE => Err(store_error(format!("Stream {} has been deleted", stream))),
+            status => Err(store_error(format!(
+                "Failed to delete from event store ({})",
+                status
+            ))),
+        }
+    }
 
-        let url = self.build_stream_url(stream);
-        let headers = generate_headers();
-
-        match client.post(&url).json(&se).headers(headers).send() {
-            Ok(response) => {
-                if response.status() == StatusCode::CREATED {
-                    Ok(ce)
-                } else {
-                    Err(Error {
-                        kind: Kind::StoreFailure(format!(
-                            "Failed to post to event store ({})",
-                            response.status()
-                        )),
-                    })
+    /// Reads the stream's metadata stream (`$$stream`), mapping `$maxAge`, `$maxCount` and
+    /// `$tb` to the retention rules and every other property to a custom one
+    fn get_stream_metadata(&self, stream: &str) -> Result<StreamMetadata> {
+        let mut raw = self.read_metadata(stream)?;
+        let mut setting = |name: &str| match raw.remove(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => value.as_u64().map(Some).ok_or_else(|| {
+                store_error(format!(
+                    "Metadata {} of stream {} is not a number: {}",
+                    name, stream, value
+                ))
+            }),
+        };
+        let max_age = setting(MAX_AGE)?.map(|secs| Duration::seconds(secs as i64));
+        let max_count = setting(MAX_COUNT)?;
+        let truncate_before = setting(TRUNCATE_BEFORE)?;
+        Ok(StreamMetadata {
+            max_age,
+            max_count,
+            truncate_before,
+            custom: raw,
+        })
+    }
+
+    /// Writes the stream's metadata stream (`$$stream`). The maximum age is written in
+    /// whole seconds, rounded up to at least one.
+    fn set_stream_metadata(&self, stream: &str, metadata: StreamMetadata) -> Result<()> {
+        let StreamMetadata {
+            max_age,
+            max_count,
+            truncate_before,
+            custom: mut raw,
+        } = metadata;
+        if let Some(max_age) = max_age {
+            let secs = (max_age + Duration::milliseconds(999)).num_seconds().max(1);
+            raw.insert(MAX_AGE.to_owned(), Value::from(secs));
+        }
+        if let Some(max_count) = max_count {
+            raw.insert(MAX_COUNT.to_owned(), Value::from(max_count));
+        }
+        if let Some(position) = truncate_before {
+            raw.insert(TRUNCATE_BEFORE.to_owned(), Value::from(position));
+        }
+        self.write_metadata(stream, raw)
+    }
+
+    /// Reads the category's `$ce-` stream, which needs the `$by_category` system projection
+    /// to be running. The position of an event counts the entries of that stream up to and
+    /// including it.
+    fn read_category(&self, category: &str) -> Result<Vec<RecordedEvent>> {
+        let category_stream = format!("{}{}", CATEGORY_STREAM_PREFIX, category);
+        self.read_feed(&category_stream)?
+            .into_iter()
+            .map(|entry| {
+                let number = entry.position_in_feed();
+                let stream = entry.stream_id.clone().ok_or_else(|| {
+                    store_error(format!(
+                        "Entry {} of {} doesn't name its stream",
+                        number, category_stream
+                    ))
+                })?;
+                let event = entry_to_cloud_event(entry, &self.build_stream_url(&stream))?;
+                Ok(RecordedEvent {
+                    position: Position::new(number + 1, number + 1),
+                    stream,
+                    event,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads the `$all` feed forwards, skipping the events of system streams, whose names
+    /// start with `$`. Reading `$all` needs admin credentials (see
+    /// [`OrgEventStore::with_credentials`]). The feed only gives positions in its links
+    /// between pages, so the last event of each page gets the position the next page starts
+    /// at and the others the position their page starts at.
+    fn read_all(&self, from: Position, max_count: usize) -> Result<Vec<RecordedEvent>> {
+        let client = reqwest::blocking::Client::new();
+        let mut recorded = Vec::new();
+        let mut position = from;
+
+        while recorded.len() < max_count {
+            let count = PAGE_SIZE.min((max_count - recorded.len()) as u64);
+            let response = self
+                .authorized(client.get(&self.build_all_page_url(position, count)))
+                .headers(generate_read_headers())
+                .send()
+                .map_err(|e| store_error(format!("Failed to read from event store {:?}", e)))?;
+            match response.status() {
+                StatusCode::OK => {}
+                StatusCode::UNAUTHORIZED => {
+                    return Err(store_error(
+                        "Reading $all needs the credentials of an admin user".to_owned(),
+                    ))
+                }
+                status => {
+                    return Err(store_error(format!(
+                        "Failed to read from event store ({})",
+                        status
+                    )))
                 }
             }
-            Err(e) => Err(Error {
-                kind: Kind::StoreFailure(format!("Failed to post to event store {:?}", e)),
-            }),
+            let feed: AtomFeed = response
+                .json()
+                .map_err(|e| store_error(format!("Malformed event store feed {:?}", e)))?;
+
+            // Forward pages of `$all` list their entries newest first, and link to the
+            // page of the events after them as `previous`
+            let next = feed.link("previous").and_then(link_position);
+            let more = match feed.head_of_stream {
+                Some(head) => !head,
+                None => feed.entries.len() as u64 == count,
+            };
+            if feed.entries.is_empty() {
+                break;
+            }
+            let mut page = Vec::new();
+            for entry in feed.entries.into_iter().rev() {
+                let stream = entry.stream_id.clone().ok_or_else(|| {
+                    store_error(format!("Malformed $all entry for event {}", entry.event_id))
+                })?;
+                if stream.starts_with('$') {
+                    continue;
+                }
+                let event = entry_to_cloud_event(entry, &self.build_stream_url(&stream))?;
+                page.push(RecordedEvent {
+                    position,
+                    stream,
+                    event,
+                });
+            }
+            if let (Some(last), Some(next)) = (page.last_mut(), next) {
+                last.position = next;
+            }
+            recorded.extend(page);
+            position = match next {
+                Some(next) if more => next,
+                _ => break,
+            };
         }
+        Ok(recorded)
     }
 }
@@ -0,0 +1,323 @@
+//! Tamper-evident event streams
+//!
+//! A [`HashChainStore`] wraps any event store and records, for every appended event, a
+//! SHA-256 hash of the event's canonical JSON chained with the hash of the event before it
+//! in the same stream. Altering, removing or inserting a stored event afterwards breaks the
+//! chain from that event on, which [`HashChainStore::verify`] reports.
+//!
+//! The canonical JSON of an event is its serialization with object keys sorted and no
+//! insignificant whitespace. The hashes are kept in a [`HashStore`], which should live
+//! somewhere the events' writers can't alter. Streams can't be deleted, truncated or given
+//! retention rules through a hash-chained store, as that would break their chains; events
+//! the underlying store hides from the start of a stream are skipped when verifying it.
+use super::cloudevents::CloudEvent;
+use super::eventstore::{
+    check_version, decorated_event_store, unsupported, Decorator, EventStore, ExpectedVersion,
+};
+use super::{Error, Event, Kind, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// The hash recorded for an event of a chained stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainLink {
+    pub event_id: String,
+    /// The hex-encoded hash of the event chained with the previous link's hash
+    pub hash: String,
+}
+
+/// Keeps the hash chains of streams
+pub trait HashStore {
+    /// Adds a link to the end of the named stream's chain
+    fn append_link(&self, stream: &str, link: ChainLink) -> Result<()>;
+
+    /// The links of the named stream's chain, in the order in which they were added
+    fn links(&self, stream: &str) -> Result<Vec<ChainLink>>;
+}
+
+/// A simple, in-memory hash store. The resulting store is thread-safe.
+#[derive(Debug, Default)]
+pub struct MemoryHashStore {
+    chains: Mutex<HashMap<String, Vec<ChainLink>>>,
+}
+
+impl MemoryHashStore {
+    pub fn new() -> MemoryHashStore {
+        MemoryHashStore::default()
+    }
+}
+
+impl HashStore for MemoryHashStore {
+    fn append_link(&self, stream: &str, link: ChainLink) -> Result<()> {
+        let mut guard = self.chains.lock().unwrap();
+        guard.entry(stream.to_owned()).or_default().push(link);
+        Ok(())
+    }
+
+    fn links(&self, stream: &str) -> Result<Vec<ChainLink>> {
+        let guard = self.chains.lock().unwrap();
+        Ok(guard.get(stream).cloned().unwrap_or_default())
+    }
+}
+
+/// How a stream's events diverge from its hash chain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Break {
+    /// The event no longer matches the hash recorded for it
+    Altered,
+    /// The chain records an event that isn't in the stream at this position
+    Missing,
+    /// The stream holds an event the chain has no record of
+    Unchained,
+}
+
+/// The first event at which a stream diverges from its hash chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenLink {
+    /// The (zero-based) position in the stream
+    pub position: usize,
+    /// The ID of the event in the stream at this position or, if the stream ends before,
+    /// of the event recorded in the chain
+    pub event_id: String,
+    pub kind: Break,
+}
+
+/// The canonical JSON of an event: its serialization with object keys sorted and no
+/// insignificant whitespace. Keys are sorted here rather than left to serde_json, whose
+/// maps keep insertion order once its `preserve_order` feature is enabled in the build.
+pub fn canonical_json(evt: &CloudEvent) -> Result<String> {
+    let value = serde_json::to_value(evt).map_err(|e| Error {
+        kind: Kind::ApplicationFailure(format!("Failed to serialize event {:?}", e)),
+    })?;
+    let mut canonical = String::new();
+    write_canonical(&value, &mut canonical);
+    Ok(canonical)
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write!(out, "{}:", Value::from(key.as_str())).unwrap();
+                write_canonical(&fields[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => write!(out, "{}", scalar).unwrap(),
+    }
+}
+
+/// Hashes an event chained with the hash of the event before it, if there is one
+pub fn chain_hash(previous: Option<&str>, evt: &CloudEvent) -> Result<String> {
+    let canonical = canonical_json(evt)?;
+    let mut hasher = Sha256::new();
+    hasher.update(previous.unwrap_or_default().as_bytes());
+    hasher.update(canonical.as_bytes());
+    let mut hash = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        write!(hash, "{:02x}", byte).unwrap();
+    }
+    Ok(hash)
+}
+
+/// Walks a stream's events, numbered by their positions in it, alongside its chain,
+/// returning the first broken link. The events may start after the first position, when
+/// the store hides the events before them, but must follow on from there.
+pub fn verify_chain(
+    events: &[(u64, CloudEvent)],
+    links: &[ChainLink],
+) -> Result<Option<BrokenLink>> {
+    let first = events.first().map_or(0, |(position, _)| *position as usize);
+    let mut previous: Option<&str> = first
+        .checked_sub(1)
+        .and_then(|before| links.get(before))
+        .map(|link| link.hash.as_str());
+    for (offset, (numbered, evt)) in events.iter().enumerate() {
+        let position = first + offset;
+        let broken = |kind| {
+            Ok(Some(BrokenLink {
+                position,
+                event_id: evt.event_id.clone(),
+                kind,
+            }))
+        };
+        let link = match links.get(position) {
+            Some(link) => link,
+            None => return broken(Break::Unchained),
+        };
+        if *numbered as usize != position || link.event_id != evt.event_id {
+            return broken(Break::Missing);
+        }
+        if chain_hash(previous, evt)? != link.hash {
+            return broken(Break::Altered);
+        }
+        previous = Some(&link.hash);
+    }
+    let end = first + events.len();
+    Ok(links.get(end).map(|link| BrokenLink {
+        position: end,
+        event_id: link.event_id.clone(),
+        kind: Break::Missing,
+    }))
+}
+
+/// An event store recording a hash chain of every stream appended to through it. Appends
+/// through the same store are serialized so that links are added in the order of the
+/// events.
+pub struct HashChainStore<S, H = MemoryHashStore> {
+    store: S,
+    hashes: H,
+    appending: Mutex<()>,
+}
+
+impl<S: EventStore> HashChainStore<S> {
+    /// Creates a store keeping its hash chains in memory
+    pub fn new(store: S) -> HashChainStore<S> {
+        HashChainStore::with_hash_store(store, MemoryHashStore::new())
+    }
+}
+
+impl<S: EventStore, H: HashStore> HashChainStore<S, H> {
+    /// Creates a store keeping its hash chains in the given hash store
+    pub fn with_hash_store(store: S, hashes: H) -> HashChainStore<S, H> {
+        HashChainStore {
+            store,
+            hashes,
+            appending: Mutex::new(()),
+        }
+    }
+
+    /// The underlying event store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// The underlying hash store
+    pub fn hashes(&self) -> &H {
+        &self.hashes
+    }
+
+    /// Checks the named stream against its hash chain, returning the first broken link or
+    /// `None` if every event is intact. Events the underlying store hides from the start of
+    /// the stream, by truncating it or by its retention rules, aren't checked, so a stream
+    /// whose events are all hidden verifies.
+    pub fn verify(&self, stream: &str) -> Result<Option<BrokenLink>> {
+        let events = self.store.read_stream_numbered(stream)?;
+        if events.is_empty() && self.store.get_stream_metadata(stream)?.hides_events() {
+            return Ok(None);
+        }
+        let links = self.hashes.links(stream)?;
+        verify_chain(&events, &links)
+    }
+
+    /// Links the events at the end of the named stream that its chain has no record of,
+    /// returning how many were linked. This repairs a stream whose events were stored but
+    /// not linked, because adding their links failed; the events should be checked to be
+    /// genuine first, as they are trusted from then on. Streams that are otherwise broken
+    /// aren't relinked and fail.
+    pub fn relink(&self, stream: &str) -> Result<usize> {
+        let _guard = self.appending.lock().unwrap();
+        let events = self.store.read_stream_numbered(stream)?;
+        let links = self.hashes.links(stream)?;
+        match verify_chain(&events, &links)? {
+            None => Ok(0),
+            Some(BrokenLink {
+                position,
+                kind: Break::Unchained,
+                ..
+            }) if position == links.len() => {
+                let first = events.first().map_or(0, |(position, _)| *position as usize);
+                let unlinked = &events[position - first..];
+                let previous = links.last().map(|link| link.hash.clone());
+                self.link(stream, previous, unlinked.iter().map(|(_, evt)| evt))?;
+                Ok(unlinked.len())
+            }
+            Some(broken) => Err(Error {
+                kind: Kind::StoreFailure(format!(
+                    "Stream {} can't be relinked, as its chain is broken at {:?}",
+                    stream, broken
+                )),
+            }),
+        }
+    }
+
+    /// Adds the links of the given events, in order, after the given hash
+    fn link<'a>(
+        &self,
+        stream: &str,
+        mut previous: Option<String>,
+        evts: impl IntoIterator<Item = &'a CloudEvent>,
+    ) -> Result<()> {
+        for evt in evts {
+            let hash = chain_hash(previous.as_deref(), evt)?;
+            self.hashes.append_link(
+                stream,
+                ChainLink {
+                    event_id: evt.event_id.clone(),
+                    hash: hash.clone(),
+                },
+            )?;
+            previous = Some(hash);
+        }
+        Ok(())
+    }
+}
+
+impl<S: EventStore, H: HashStore> Decorator for HashChainStore<S, H> {
+    type Inner = S;
+
+    fn inner(&self) -> &S {
+        &self.store
+    }
+
+    /// Appends the events through the underlying store and links them, in order, to the
+    /// end of the stream's chain. The events are appended only if the stream is at the
+    /// version its chain records, so a stream with events the chain has no record of can't
+    /// be appended to until they are relinked (see [`HashChainStore::relink`]). Events whose
+    /// links fail to be added stay stored, unlinked, and the append fails.
+    fn append_events<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        if evts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let _guard = self.appending.lock().unwrap();
+        let links = self.hashes.links(stream)?;
+        let chained = (links.len() as u64).checked_sub(1);
+        check_version(stream, expected, chained)?;
+        let chained = chained.map_or(ExpectedVersion::NoStream, ExpectedVersion::Exact);
+        let stored = self.store.append_batch(evts, stream, chained)?;
+        let previous = links.last().map(|link| link.hash.clone());
+        self.link(stream, previous, &stored)?;
+        Ok(stored)
+    }
+
+    /// Streams can't be deleted, truncated or given metadata, which would break their chains
+    fn manage_stream(&self, stream: &str, action: &str) -> Result<()> {
+        Err(unsupported(stream, action))
+    }
+}
+
+decorated_event_store!([S: EventStore, H: HashStore] HashChainStore<S, H>);
@@ -49,7 +49,9 @@
 //!}
 //! ```
 //!
-//! We then define a type that represents the state to be used by an aggregate.
+//! We then define a type that represents the state to be used by an aggregate. The derive
+//! macro for aggregate state keeps track of the state's generation on our behalf, so the
+//! business logic never has to touch it.
 //! With that in place, we write all of our business logic, the core of our event sourcing system,
 //! in the aggregate.
 //!```rust
@@ -69,19 +71,15 @@
 //!# enum LocationCommand {
 //!#    UpdateLocation { lat: f32, long: f32, alt: f32 },
 //!# }
-//!#[derive(Debug, Clone)]
+//!#[derive(Debug, Clone, AggregateState)]
 //!struct LocationData {
 //!    lat: f32,
 //!    long: f32,
 //!    alt: f32,
+//!    #[generation]
 //!    generation: u64,
 //!}
 //!
-//!impl AggregateState for LocationData {
-//!    fn generation(&self) -> u64 {
-//!        self.generation
-//!    }
-//!}
 //!struct Location;
 //!impl Aggregate for Location {
 //!   type Event = LocationEvent;
@@ -95,7 +93,7 @@
 //!               lat: *lat,
 //!               long: *long,
 //!               alt: *alt,
-//!               generation: state.generation + 1,
+//!               ..state.clone()
 //!           },
 //!       };
 //!       Ok(ld)
@@ -110,20 +108,30 @@
 //!}
 //! ```
 
+#[cfg(feature = "eventstore")]
+extern crate base64;
 extern crate chrono;
-extern crate serde;
+#[cfg(feature = "eventstore")]
 #[macro_use]
+extern crate eventsourcing_derive;
+extern crate serde;
+#[cfg_attr(feature = "eventstore", macro_use)]
 extern crate serde_derive;
 extern crate serde_json;
 #[cfg(feature = "eventstore")]
 extern crate uuid;
 
+/// Lets the derive macros, which name this crate `::eventsourcing`, be used within it
+#[cfg(feature = "eventstore")]
+extern crate self as eventsourcing;
+
 #[cfg(feature = "eventstore")]
 pub use cloudevents::CloudEvent;
 
+use chrono::{DateTime, Utc};
 #[cfg(feature = "eventstore")]
 use eventstore::EventStore;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 use std::fmt;
 
 /// An event sourcing error
@@ -148,16 +156,18 @@ impl fmt::Display for Error {
             Kind::ApplicationFailure(ref s) => fmt::Display::fmt(s, f),
             Kind::CommandFailure(ref s) => fmt::Display::fmt(s, f),
             Kind::StoreFailure(ref s) => fmt::Display::fmt(s, f),
+            Kind::ValidationFailure(ref s) => fmt::Display::fmt(s, f),
         }
     }
 }
 
 /// Indicates the kind of event sourcing error that occurred.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Kind {
     ApplicationFailure(String),
     CommandFailure(String),
     StoreFailure(String),
+    ValidationFailure(String),
 }
 
 /// A Result where failure is an event sourcing error
@@ -171,14 +181,118 @@ pub trait Event: Serialize {
     fn event_type_version(&self) -> &str;
     fn event_type(&self) -> &str;
     fn event_source(&self) -> &str;
+
+    /// The time at which the event took effect in the business, if it differs from the
+    /// time at which it is recorded, as with back-dated corrections
+    fn effective_time(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    /// The subject the event is about, such as the person whose personal data it holds, if
+    /// it should be told apart from the stream the event is appended to
+    fn subject(&self) -> Option<String> {
+        None
+    }
+
+    /// The JSON pointers of the fields of the event's data holding personal data, which
+    /// stores that encrypt events encrypt instead of the whole data. `None` means the whole
+    /// data is encrypted.
+    fn encrypted_fields(&self) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// An event whose effective time, unless the event sets its own, is the given one. Events
+/// produced by a command take the command's effective time this way.
+pub struct WithEffectiveTime<E> {
+    event: E,
+    effective_time: Option<DateTime<Utc>>,
+}
+
+impl<E: Event> WithEffectiveTime<E> {
+    pub fn new(event: E, effective_time: Option<DateTime<Utc>>) -> WithEffectiveTime<E> {
+        WithEffectiveTime {
+            event,
+            effective_time,
+        }
+    }
+}
+
+impl<E: Event> Serialize for WithEffectiveTime<E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.event.serialize(serializer)
+    }
+}
+
+impl<E: Event> Event for WithEffectiveTime<E> {
+    fn event_type_version(&self) -> &str {
+        self.event.event_type_version()
+    }
+
+    fn event_type(&self) -> &str {
+        self.event.event_type()
+    }
+
+    fn event_source(&self) -> &str {
+        self.event.event_source()
+    }
+
+    fn effective_time(&self) -> Option<DateTime<Utc>> {
+        self.event.effective_time().or(self.effective_time)
+    }
+
+    fn subject(&self) -> Option<String> {
+        self.event.subject()
+    }
+
+    fn encrypted_fields(&self) -> Option<Vec<String>> {
+        self.event.encrypted_fields()
+    }
+}
+
+/// A catalog of every event type a type of event can produce, used for routing,
+/// registering upcasters and validating stored data. The derive macro for events
+/// implements this trait as well.
+pub trait EventCatalog: Sized {
+    /// The event types this type produces
+    const EVENT_TYPES: &'static [&'static str];
+
+    /// Whether the event type is one this type produces or accepts as an alias
+    fn is_known_type(event_type: &str) -> bool {
+        Self::EVENT_TYPES.contains(&event_type)
+    }
+
+    /// Deserializes the event data stored for the given event type
+    fn deserialize_by_type(event_type: &str, data: &serde_json::Value) -> Result<Self>;
+}
+
+/// Commands can expose metadata that lets generic infrastructure route them: a type
+/// name and the ID of the aggregate instance they target, which doubles as the name of
+/// that aggregate's event stream. If you use the derive macro for commands, you do not
+/// have to implement these functions manually.
+pub trait Command {
+    fn command_type(&self) -> &str;
+    fn aggregate_id(&self) -> String;
+
+    /// The effective time of the events the command produces, for commands that record
+    /// something that took effect at another time
+    fn effective_time(&self) -> Option<DateTime<Utc>> {
+        None
+    }
 }
 
 /// Aggregate state only requires that it expose the generation number. State generation
 /// can be thought of as a sequential _version_. When a previous state is combined with
 /// an event to produce a new state, that new state has a generation 1 higher than the
 /// previous.
+///
+/// States that also implement `set_generation` don't need to maintain the generation
+/// themselves: `Aggregate::apply` and `Aggregate::apply_all` set it after every successful
+/// `apply_event`. If you use the derive macro for aggregate state, both functions are
+/// generated from the field marked `#[generation]`.
 pub trait AggregateState {
     fn generation(&self) -> u64;
+    fn set_generation(&mut self, _generation: u64) {}
 }
 
 /// An aggregate is where the vast majority of business logic for an event sourcing system
@@ -193,11 +307,46 @@ pub trait Aggregate {
     type State: AggregateState + Clone;
 
     fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State>;
+
+    /// Handles a command without a context. Aggregates that need dependencies to handle
+    /// their commands implement `CommandHandler` as well, and are handled through it by
+    /// dispatchers and repositories given a context.
     fn handle_command(state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>>;
+
+    /// Applies an event through `apply_event`, then sets the new state's generation to one
+    /// higher than the previous state's.
+    fn apply(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        let mut new_state = Self::apply_event(state, evt)?;
+        new_state.set_generation(state.generation() + 1);
+        Ok(new_state)
+    }
+
     fn apply_all(state: &Self::State, evts: &[Self::Event]) -> Result<Self::State> {
-        Ok(evts.iter().fold(state.clone(), |acc_state, event| {
-            Self::apply_event(&acc_state, event).unwrap()
-        }))
+        evts.iter().try_fold(state.clone(), |acc_state, event| {
+            Self::apply(&acc_state, event)
+        })
+    }
+}
+
+/// Handles commands with access to a context: a clock, ID generators, configuration or
+/// read-only lookups such as currency rates. The context is passed by whoever handles the
+/// command, such as a dispatcher or a repository. Every aggregate handles commands with
+/// the empty context `()` through its `Aggregate::handle_command`.
+pub trait CommandHandler<Ctx>: Aggregate {
+    fn handle_command_with(
+        state: &Self::State,
+        cmd: &Self::Command,
+        ctx: &Ctx,
+    ) -> Result<Vec<Self::Event>>;
+}
+
+impl<A: Aggregate> CommandHandler<()> for A {
+    fn handle_command_with(
+        state: &Self::State,
+        cmd: &Self::Command,
+        _ctx: &(),
+    ) -> Result<Vec<Self::Event>> {
+        A::handle_command(state, cmd)
     }
 }
 
@@ -206,7 +355,7 @@ pub trait Aggregate {
 /// immediately to a store, for a given event stream name. You don't have to build a dispatcher
 /// yourself, you can use a derive macro to make a placeholder struct your dispatcher.
 /// The result of a dispatch is a vector capturing the success of command application. If it
-/// succeeded, you will get a CloudEvent, a CloudEvents v0.1 spec-compliant data structure.
+/// succeeded, you will get a CloudEvent, a CloudEvents v1.0 spec-compliant data structure.
 #[cfg(feature = "orgeventstore")]
 pub trait Dispatcher {
     type Command;
@@ -220,10 +369,202 @@ pub trait Dispatcher {
         store: &impl EventStore,
         stream: &str,
     ) -> Vec<Result<CloudEvent>>;
+
+    /// The effective time of the events the command produces, which they take unless they
+    /// set their own. The derive macro for dispatchers reads it from `Command::effective_time`
+    /// for commands that implement `Command`.
+    fn effective_time(_cmd: &Self::Command) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    /// Dispatches a command to the stream of the aggregate instance it targets
+    fn dispatch_command(
+        state: &Self::State,
+        cmd: &Self::Command,
+        store: &impl EventStore,
+    ) -> Vec<Result<CloudEvent>>
+    where
+        Self::Command: Command,
+    {
+        Self::dispatch(state, cmd, store, &cmd.aggregate_id())
+    }
+
+    /// Dispatches a command to an aggregate that handles it with the given context
+    fn dispatch_with<Ctx>(
+        state: &Self::State,
+        cmd: &Self::Command,
+        ctx: &Ctx,
+        store: &impl EventStore,
+        stream: &str,
+    ) -> Vec<Result<CloudEvent>>
+    where
+        Self::Aggregate: CommandHandler<Ctx>,
+    {
+        let effective_time = Self::effective_time(cmd);
+        match Self::Aggregate::handle_command_with(state, cmd, ctx) {
+            Ok(evts) => evts
+                .into_iter()
+                .map(|evt| store.append(WithEffectiveTime::new(evt, effective_time), stream))
+                .collect(),
+            Err(e) => vec![Err(e)],
+        }
+    }
+}
+
+/// Support code for the derive macros. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    use super::{Command, Error, Kind, Result};
+    use chrono::Utc;
+    pub use serde::de::DeserializeOwned;
+    pub use serde_json::Value;
+
+    pub type DateTime = chrono::DateTime<Utc>;
+
+    /// Fields that can be marked `#[effective_time]`
+    pub trait EffectiveTime {
+        fn effective_time(&self) -> Option<DateTime>;
+    }
+
+    impl EffectiveTime for DateTime {
+        fn effective_time(&self) -> Option<DateTime> {
+            Some(*self)
+        }
+    }
+
+    impl EffectiveTime for Option<DateTime> {
+        fn effective_time(&self) -> Option<DateTime> {
+            *self
+        }
+    }
+
+    pub fn effective_time<T: EffectiveTime>(field: &T) -> Option<DateTime> {
+        field.effective_time()
+    }
+
+    /// A command whose effective time derived dispatchers read: calling
+    /// `(&&CommandTime(cmd)).effective_time()` goes through `Command::effective_time` for
+    /// commands that implement `Command`, and finds no effective time for other commands.
+    pub struct CommandTime<'a, C>(pub &'a C);
+
+    pub trait CommandEffectiveTime {
+        fn effective_time(&self) -> Option<DateTime>;
+    }
+
+    impl<'a, C: Command> CommandEffectiveTime for &CommandTime<'a, C> {
+        fn effective_time(&self) -> Option<DateTime> {
+            self.0.effective_time()
+        }
+    }
+
+    pub trait NoEffectiveTime {
+        fn effective_time(&self) -> Option<DateTime>;
+    }
+
+    impl<'a, C> NoEffectiveTime for CommandTime<'a, C> {
+        fn effective_time(&self) -> Option<DateTime> {
+            None
+        }
+    }
+
+    /// Fields that can be marked `#[subject]`
+    pub trait Subject {
+        fn subject(&self) -> Option<String>;
+    }
+
+    impl Subject for String {
+        fn subject(&self) -> Option<String> {
+            Some(self.clone())
+        }
+    }
+
+    impl Subject for Option<String> {
+        fn subject(&self) -> Option<String> {
+            self.clone()
+        }
+    }
+
+    pub fn subject<T: Subject>(field: &T) -> Option<String> {
+        field.subject()
+    }
+
+    /// Deserializes externally tagged event data. Data that was persisted under an old
+    /// variant name (through an event type alias) is retagged to the given variant; data
+    /// that doesn't deserialize as that variant is deserialized as-is.
+    pub fn deserialize_variant<T: DeserializeOwned>(
+        data: Value,
+        variant: Option<&str>,
+    ) -> serde_json::Result<T> {
+        let retagged = match (variant, &data) {
+            (Some(variant), Value::Object(fields)) if fields.len() == 1 => {
+                let payload = fields.values().next().cloned().unwrap_or(Value::Null);
+                let mut retagged = serde_json::Map::new();
+                retagged.insert(variant.to_owned(), payload);
+                Some(Value::Object(retagged))
+            }
+            (Some(variant), Value::String(_)) => Some(Value::String(variant.to_owned())),
+            _ => None,
+        };
+        match retagged.map(serde_json::from_value) {
+            Some(Ok(evt)) => Ok(evt),
+            _ => serde_json::from_value(data),
+        }
+    }
+
+    /// Deserializes the data of a known event type into the given variant (if any)
+    pub fn deserialize_event<T: DeserializeOwned>(
+        event_type: &str,
+        data: &Value,
+        variant: Option<&str>,
+    ) -> Result<T> {
+        deserialize_variant(data.clone(), variant).map_err(|e| Error {
+            kind: Kind::ValidationFailure(format!(
+                "Data for event type {} could not be deserialized: {}",
+                event_type, e
+            )),
+        })
+    }
+
+    /// Unwraps event data tagged with the given variant name, as written by an enum
+    /// that delegates to the events it wraps
+    pub fn untag<'a>(data: &'a Value, variant: &str) -> &'a Value {
+        match data {
+            Value::Object(fields) if fields.len() == 1 => fields.get(variant).unwrap_or(data),
+            _ => data,
+        }
+    }
+
+    pub fn unknown_event_type(event_type: &str) -> Error {
+        Error {
+            kind: Kind::ValidationFailure(format!("Unknown event type {}", event_type)),
+        }
+    }
 }
 
 #[cfg(feature = "eventstore")]
 pub mod cloudevents;
 
+#[cfg(feature = "eventstore")]
+pub mod bus;
+pub mod clock;
+#[cfg(feature = "eventstore")]
+pub mod deadletter;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod eventstore;
+#[cfg(feature = "integrity")]
+pub mod integrity;
+#[cfg(feature = "eventstore")]
+pub mod outbox;
 pub mod prelude;
+#[cfg(feature = "eventstore")]
+pub mod process;
+#[cfg(feature = "eventstore")]
+pub mod repository;
+pub mod retry;
+#[cfg(feature = "eventstore")]
+pub mod scheduler;
+pub mod schema;
+pub mod snapshot;
+#[cfg(feature = "test-util")]
+pub mod testing;
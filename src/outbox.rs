@@ -0,0 +1,218 @@
+//! Transactional outbox
+//!
+//! Publishing events to a message broker after appending them to a store can fail
+//! independently of the append, leaving the two out of step. Stores implementing
+//! [`Outbox`] record a pending publication in the same operation as the append, so an
+//! event is never stored without also being queued for publication.
+//!
+//! An [`OutboxRelay`] delivers the pending publications to a [`Publisher`], retrying
+//! failed deliveries according to a [`RetryPolicy`]. A publication is only marked as
+//! published once its delivery succeeded, so every event is delivered at least once:
+//! publishers' consumers should expect, and ignore, duplicates by event ID.
+use super::cloudevents::CloudEvent;
+use super::eventstore::{
+    check_read_version, decorated_event_store, Decorator, EventStore, ExpectedVersion,
+};
+use super::retry::RetryPolicy;
+use super::{Event, Result};
+use std::sync::Arc;
+
+#[cfg(feature = "orgeventstore")]
+use super::{Error, Kind};
+
+/// Event stores that can record pending publications atomically with an append
+pub trait Outbox: EventStore {
+    /// Appends an event to the named stream and queues it for publication
+    fn append_to_outbox(&self, evt: impl Event, stream: &str) -> Result<CloudEvent>;
+
+    /// Appends events to the named stream if it is at the expected version, and queues them
+    /// for publication (see [`EventStore::append_batch`]). By default, this checks the
+    /// version and appends the events one at a time, as `append_batch` does by default.
+    fn append_batch_to_outbox<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        if evts.is_empty() {
+            return Ok(Vec::new());
+        }
+        check_read_version(self, stream, expected)?;
+        evts.into_iter()
+            .map(|evt| self.append_to_outbox(evt, stream))
+            .collect()
+    }
+
+    /// The events queued for publication that haven't been published yet, in the order in
+    /// which they were appended
+    fn pending_publications(&self) -> Result<Vec<CloudEvent>>;
+
+    /// Marks an event as published. Marking an event that isn't pending has no effect.
+    fn mark_published(&self, event_id: &str) -> Result<()>;
+}
+
+impl<S: Outbox + ?Sized> Outbox for Arc<S> {
+    fn append_to_outbox(&self, evt: impl Event, stream: &str) -> Result<CloudEvent> {
+        (**self).append_to_outbox(evt, stream)
+    }
+
+    fn append_batch_to_outbox<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        (**self).append_batch_to_outbox(evts, stream, expected)
+    }
+
+    fn pending_publications(&self) -> Result<Vec<CloudEvent>> {
+        (**self).pending_publications()
+    }
+
+    fn mark_published(&self, event_id: &str) -> Result<()> {
+        (**self).mark_published(event_id)
+    }
+}
+
+/// An event store that queues every appended event for publication, so that code written
+/// against `EventStore`, such as dispatchers, repositories and the command bus, goes
+/// through the outbox
+pub struct OutboxStore<S> {
+    store: S,
+}
+
+impl<S: Outbox> OutboxStore<S> {
+    pub fn new(store: S) -> OutboxStore<S> {
+        OutboxStore { store }
+    }
+
+    /// The underlying event store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S: Outbox> Decorator for OutboxStore<S> {
+    type Inner = S;
+
+    fn inner(&self) -> &S {
+        &self.store
+    }
+
+    fn append_events<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        self.store.append_batch_to_outbox(evts, stream, expected)
+    }
+}
+
+decorated_event_store!([S: Outbox] OutboxStore<S>);
+
+/// Delivers events to an external system, such as a message broker or a webhook
+pub trait Publisher {
+    fn publish(&self, evt: &CloudEvent) -> Result<()>;
+}
+
+/// Plain functions can publish events
+impl<F> Publisher for F
+where
+    F: Fn(&CloudEvent) -> Result<()>,
+{
+    fn publish(&self, evt: &CloudEvent) -> Result<()> {
+        self(evt)
+    }
+}
+
+/// Delivers the pending publications of an outbox to a publisher
+pub struct OutboxRelay<S, P> {
+    store: S,
+    publisher: P,
+    retry: RetryPolicy,
+}
+
+impl<S: Outbox, P: Publisher> OutboxRelay<S, P> {
+    /// Creates a relay retrying failed deliveries with the default retry policy
+    pub fn new(store: S, publisher: P) -> OutboxRelay<S, P> {
+        OutboxRelay {
+            store,
+            publisher,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets the policy for retrying failed deliveries
+    pub fn with_retry_policy(self, retry: RetryPolicy) -> OutboxRelay<S, P> {
+        OutboxRelay { retry, ..self }
+    }
+
+    /// The underlying event store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Publishes the pending publications in order, returning how many were published.
+    /// Stops at the first event that still can't be delivered after retrying, leaving it
+    /// and the events after it pending for the next run, and returns its error.
+    pub fn relay(&self) -> Result<usize> {
+        let pending = self.store.pending_publications()?;
+        for evt in &pending {
+            self.retry.run(|_| self.publisher.publish(evt))?;
+            self.store.mark_published(&evt.event_id)?;
+        }
+        Ok(pending.len())
+    }
+}
+
+/// Publishes events to an HTTP endpoint as CloudEvents in structured JSON mode
+#[cfg(feature = "orgeventstore")]
+pub struct WebhookPublisher {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+/// The media type of a CloudEvent in structured JSON mode
+#[cfg(feature = "orgeventstore")]
+pub const CLOUDEVENTS_JSON: &str = "application/cloudevents+json; charset=utf-8";
+
+#[cfg(feature = "orgeventstore")]
+impl WebhookPublisher {
+    /// Creates a publisher posting events to the given URL
+    pub fn new(url: &str) -> WebhookPublisher {
+        WebhookPublisher {
+            url: url.to_owned(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+}
+
+#[cfg(feature = "orgeventstore")]
+impl Publisher for WebhookPublisher {
+    /// Posts the event, succeeding if the endpoint answers with any 2xx status
+    fn publish(&self, evt: &CloudEvent) -> Result<()> {
+        let body = serde_json::to_string(evt).map_err(|e| Error {
+            kind: Kind::ApplicationFailure(format!("Failed to serialize event {:?}", e)),
+        })?;
+        let response = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, CLOUDEVENTS_JSON)
+            .body(body)
+            .send()
+            .map_err(|e| Error {
+                kind: Kind::ApplicationFailure(format!("Failed to post to webhook {:?}", e)),
+            })?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error {
+                kind: Kind::ApplicationFailure(format!(
+                    "Failed to post to webhook ({})",
+                    response.status()
+                )),
+            })
+        }
+    }
+}
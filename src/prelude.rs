@@ -1,8 +1,9 @@
 //! Standard prelude for eventsourcing applications
-pub use super::{Aggregate, AggregateState, Event, Kind};
-
+pub use super::{Aggregate, AggregateState, Command, CommandHandler, Event, EventCatalog, Kind};
 
 #[cfg(feature = "orgeventstore")]
 pub use super::CloudEvent;
 #[cfg(feature = "orgeventstore")]
+pub use super::Dispatcher;
+#[cfg(feature = "orgeventstore")]
 pub use crate::eventstore::EventStore;
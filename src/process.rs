@@ -0,0 +1,226 @@
+//! Process managers
+//!
+//! A process manager (or saga) coordinates a workflow that spans several aggregates, such
+//! as a transfer between two bank accounts. It reacts to the events those aggregates emit
+//! by sending them further commands through a [`CommandBus`].
+//!
+//! Each running process has its own event-sourced state. Events are correlated with a
+//! process instance through an ID found in their data. The process records its progress
+//! as events in a stream named after that ID, so it can be picked up again after a
+//! restart. The events of a reaction are recorded together, once its commands have been
+//! sent. When one of its commands fails, the process manager can record the failure
+//! and send compensating commands that undo the steps already taken. Without compensation
+//! nothing is recorded, and handling the same event again retries the whole reaction.
+use super::bus::CommandBus;
+use super::cloudevents::CloudEvent;
+use super::eventstore::{EventStore, ExpectedVersion};
+use super::{Aggregate, AggregateState, Command, Error, Event, EventCatalog, Result};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::marker::PhantomData;
+
+/// What a process manager does in response to an event: the events that record its
+/// progress, and the commands it sends
+pub struct Reaction<E, C> {
+    pub events: Vec<E>,
+    pub commands: Vec<C>,
+}
+
+impl<E, C> Reaction<E, C> {
+    /// A reaction that neither records events nor sends commands
+    pub fn new() -> Reaction<E, C> {
+        Reaction {
+            events: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    /// Records an event in the process's stream
+    pub fn record(mut self, evt: E) -> Reaction<E, C> {
+        self.events.push(evt);
+        self
+    }
+
+    /// Sends a command once the events have been recorded
+    pub fn send(mut self, cmd: C) -> Reaction<E, C> {
+        self.commands.push(cmd);
+        self
+    }
+}
+
+impl<E, C> Default for Reaction<E, C> {
+    fn default() -> Self {
+        Reaction::new()
+    }
+}
+
+/// A workflow that reacts to events with commands. Like aggregates, process managers are
+/// stateless: their state is rebuilt from the events they recorded.
+pub trait ProcessManager {
+    /// The events the process reacts to
+    type Event: EventCatalog;
+    /// The events that record the process's progress
+    type ProcessEvent: Event + EventCatalog;
+    type State: AggregateState + Clone + Default;
+    /// The commands the process sends
+    type Command: Command + 'static;
+
+    /// Names the process, prefixing the streams of its instances
+    const PROCESS_TYPE: &'static str;
+
+    /// The ID of the process instance an event belongs to, if any
+    fn correlation_id(evt: &Self::Event) -> Option<String>;
+
+    fn apply_event(state: &Self::State, evt: &Self::ProcessEvent) -> Result<Self::State>;
+
+    /// Reacts to an event belonging to the process instance in the given state
+    fn handle_event(
+        state: &Self::State,
+        evt: &Self::Event,
+    ) -> Result<Reaction<Self::ProcessEvent, Self::Command>>;
+
+    /// Reacts to one of the process's commands failing, usually by recording the failure
+    /// and sending commands that undo the steps already taken. The state is the one the
+    /// reaction's events lead to, and those events are recorded along with the
+    /// compensation's. Commands after the failed one are not sent. By default there is no
+    /// compensation: the failure is returned and none of the reaction's events are
+    /// recorded, so the process stays where it was.
+    fn compensate(
+        _state: &Self::State,
+        _cmd: &Self::Command,
+        error: &Error,
+    ) -> Result<Reaction<Self::ProcessEvent, Self::Command>> {
+        Err(Error {
+            kind: error.kind.clone(),
+        })
+    }
+
+    /// Sends a command through the bus. Processes whose command type wraps the commands of
+    /// several aggregates override this to send the wrapped command instead.
+    fn send(bus: &CommandBus, cmd: &Self::Command) -> Result<Vec<CloudEvent>> {
+        bus.send(cmd)
+    }
+
+    /// The name of the stream holding the events of a process instance
+    fn stream_name(correlation_id: &str) -> String {
+        format!("{}-{}", Self::PROCESS_TYPE, correlation_id)
+    }
+}
+
+/// A process instance's state, rebuilt from its events as an aggregate's is, which keeps
+/// the generation of states that set it. The process handles events rather than commands.
+struct ProcessState<P>(PhantomData<fn() -> P>);
+
+impl<P: ProcessManager> Aggregate for ProcessState<P> {
+    type Event = P::ProcessEvent;
+    type Command = Infallible;
+    type State = P::State;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        P::apply_event(state, evt)
+    }
+
+    fn handle_command(_state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        match *cmd {}
+    }
+}
+
+/// Runs the instances of a process manager, persisting their state in an event store
+pub struct ProcessRunner<P, S> {
+    store: S,
+    process: PhantomData<fn() -> P>,
+}
+
+impl<P, S> ProcessRunner<P, S>
+where
+    P: ProcessManager,
+    S: EventStore,
+{
+    /// Creates a runner that keeps the processes' events in the given store
+    pub fn new(store: S) -> ProcessRunner<P, S> {
+        ProcessRunner {
+            store,
+            process: PhantomData,
+        }
+    }
+
+    /// The underlying event store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Rebuilds the state of a process instance from the events it recorded
+    pub fn load(&self, correlation_id: &str) -> Result<P::State> {
+        Ok(self.load_versioned(&P::stream_name(correlation_id))?.0)
+    }
+
+    /// Reacts to an event, returning the cloud events emitted by the commands the process
+    /// sent. Events that don't belong to a process instance are ignored.
+    ///
+    /// The reaction's events are recorded once its commands have been sent, in one append
+    /// that fails if the process moved on in the meantime. A command failing without
+    /// compensation fails the reaction and records nothing, but the commands sent before
+    /// it stay sent, and are sent again if the event is handled again.
+    pub fn react(&self, bus: &CommandBus, evt: &P::Event) -> Result<Vec<CloudEvent>> {
+        let correlation_id = match P::correlation_id(evt) {
+            Some(id) => id,
+            None => return Ok(Vec::new()),
+        };
+        let stream = P::stream_name(&correlation_id);
+        let (state, version) = self.load_versioned(&stream)?;
+        let reaction = P::handle_event(&state, evt)?;
+        let mut progress = reaction.events;
+        let state = ProcessState::<P>::apply_all(&state, &progress)?;
+
+        let mut emitted = Vec::new();
+        for cmd in &reaction.commands {
+            match P::send(bus, cmd) {
+                Ok(evts) => emitted.extend(evts),
+                Err(e) => {
+                    let compensation = P::compensate(&state, cmd, &e)?;
+                    ProcessState::<P>::apply_all(&state, &compensation.events)?;
+                    progress.extend(compensation.events);
+                    self.store.append_batch(progress, &stream, version)?;
+                    for cmd in &compensation.commands {
+                        emitted.extend(P::send(bus, cmd)?);
+                    }
+                    return Ok(emitted);
+                }
+            }
+        }
+        self.store.append_batch(progress, &stream, version)?;
+        Ok(emitted)
+    }
+
+    /// Like `react`, for a stored cloud event. Events of types the process doesn't react
+    /// to are ignored.
+    pub fn handle(&self, bus: &CommandBus, ce: &CloudEvent) -> Result<Vec<CloudEvent>> {
+        if !P::Event::is_known_type(&ce.event_type) {
+            return Ok(Vec::new());
+        }
+        let evt = P::Event::deserialize_by_type(&ce.event_type, &ce.data)?;
+        self.react(bus, &evt)
+    }
+
+    /// Handles the given cloud events, as well as those emitted by the commands the
+    /// process sends in turn, until the process has nothing left to react to
+    pub fn handle_all(&self, bus: &CommandBus, evts: Vec<CloudEvent>) -> Result<()> {
+        let mut pending: VecDeque<CloudEvent> = evts.into();
+        while let Some(ce) = pending.pop_front() {
+            pending.extend(self.handle(bus, &ce)?);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the state of a process instance along with the version its stream is at
+    fn load_versioned(&self, stream: &str) -> Result<(P::State, ExpectedVersion)> {
+        let mut version = ExpectedVersion::NoStream;
+        let mut state = P::State::default();
+        for (position, ce) in self.store.read_stream_numbered(stream)? {
+            let evt = P::ProcessEvent::deserialize_by_type(&ce.event_type, &ce.data)?;
+            state = ProcessState::<P>::apply(&state, &evt)?;
+            version = ExpectedVersion::Exact(position);
+        }
+        Ok((state, version))
+    }
+}
@@ -0,0 +1,234 @@
+//! Repositories
+//!
+//! A repository loads an aggregate's state by replaying the events of its stream and
+//! executes commands against that state, appending the resulting events to the stream.
+//!
+//! Repositories can also rebuild the state an aggregate was in at an earlier point, either
+//! at a given time or after a given number of events. Given a [`SnapshotStore`], they
+//! start from the latest suitable snapshot instead of replaying the whole stream.
+use super::cloudevents::CloudEvent;
+use super::eventstore::{EventStore, ExpectedVersion};
+use super::snapshot::{NoSnapshots, Snapshot, SnapshotStore};
+use super::{
+    Aggregate, Command, CommandHandler, Error, EventCatalog, Kind, Result, WithEffectiveTime,
+};
+use chrono::prelude::*;
+use std::marker::PhantomData;
+
+/// Loads aggregates from, and executes commands against, the streams of an event store
+pub struct Repository<A, S, N = NoSnapshots> {
+    store: S,
+    snapshots: N,
+    aggregate: PhantomData<fn() -> A>,
+}
+
+impl<A, S> Repository<A, S>
+where
+    A: Aggregate,
+    A::Event: EventCatalog,
+    A::State: Default,
+    S: EventStore,
+{
+    /// Creates a repository for the aggregate on top of the given store
+    pub fn new(store: S) -> Repository<A, S> {
+        Repository {
+            store,
+            snapshots: NoSnapshots,
+            aggregate: PhantomData,
+        }
+    }
+}
+
+impl<A, S, N> Repository<A, S, N>
+where
+    A: Aggregate,
+    A::Event: EventCatalog,
+    A::State: Default,
+    S: EventStore,
+    N: SnapshotStore<A::State>,
+{
+    /// Keeps the repository's snapshots in the given store
+    pub fn with_snapshots<M>(self, snapshots: M) -> Repository<A, S, M>
+    where
+        M: SnapshotStore<A::State>,
+    {
+        Repository {
+            store: self.store,
+            snapshots,
+            aggregate: PhantomData,
+        }
+    }
+
+    /// The underlying event store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// The underlying snapshot store
+    pub fn snapshots(&self) -> &N {
+        &self.snapshots
+    }
+
+    /// Rebuilds the aggregate's state by replaying every event in the stream on top of the
+    /// default state
+    pub fn load(&self, stream: &str) -> Result<A::State> {
+        Ok(self.fold(stream, |_| true, |_, _| true)?.state)
+    }
+
+    /// Rebuilds the state the aggregate was in at the given time, from the events appended
+    /// up to and including that time
+    pub fn load_as_of(&self, stream: &str, time: DateTime<Utc>) -> Result<A::State> {
+        let snapshot = self.fold(
+            stream,
+            |snapshot| snapshot.event_time.into_iter().all(|t| t <= time),
+            |_, ce| ce.event_time.is_some_and(|t| t <= time),
+        )?;
+        Ok(snapshot.state)
+    }
+
+    /// Rebuilds the state the aggregate was in after the first `generation` events of the
+    /// stream. Fails if the stream has fewer events.
+    pub fn load_at_generation(&self, stream: &str, generation: u64) -> Result<A::State> {
+        let snapshot = self.fold(
+            stream,
+            |snapshot| snapshot.generation <= generation,
+            |next, _| next <= generation,
+        )?;
+        if snapshot.generation < generation {
+            return Err(Error {
+                kind: Kind::ValidationFailure(format!(
+                    "Stream {} has not reached generation {}",
+                    stream, generation
+                )),
+            });
+        }
+        Ok(snapshot.state)
+    }
+
+    /// Loads the aggregate's current state and saves a snapshot of it
+    pub fn snapshot(&self, stream: &str) -> Result<A::State> {
+        let snapshot = self.fold(stream, |_| true, |_, _| true)?;
+        let state = snapshot.state.clone();
+        self.snapshots.save(stream, snapshot)?;
+        Ok(state)
+    }
+
+    /// Folds the stream's events, as long as they qualify, onto the latest usable snapshot.
+    /// Whether an event qualifies is decided from the generation it would produce, which is
+    /// one past its position in the stream. Events hidden before the first one read, as
+    /// after truncating the stream, must be covered by a snapshot.
+    fn fold<U, Q>(&self, stream: &str, usable: U, qualifies: Q) -> Result<Snapshot<A::State>>
+    where
+        U: Fn(&Snapshot<A::State>) -> bool,
+        Q: Fn(u64, &CloudEvent) -> bool,
+    {
+        let evts = self.store.read_stream_numbered(stream)?;
+        let first = evts.first().map_or(0, |(position, _)| *position);
+        let head = evts.last().map_or(first, |(position, _)| position + 1);
+        let snapshot = self
+            .snapshots
+            .snapshots(stream)?
+            .into_iter()
+            .rev()
+            .find(|snapshot| {
+                snapshot.generation >= first && snapshot.generation <= head && usable(snapshot)
+            });
+        let mut current = match snapshot {
+            Some(snapshot) => snapshot,
+            None if first == 0 => Snapshot {
+                state: A::State::default(),
+                generation: 0,
+                event_time: None,
+            },
+            None => {
+                return Err(Error {
+                    kind: Kind::StoreFailure(format!(
+                    "Stream {} hides its events before position {}, and no snapshot covers them",
+                    stream, first
+                )),
+                })
+            }
+        };
+        for (position, ce) in evts {
+            if position < current.generation {
+                continue;
+            }
+            if !qualifies(position + 1, &ce) {
+                break;
+            }
+            let evt = A::Event::deserialize_by_type(&ce.event_type, &ce.data)?;
+            current = Snapshot {
+                state: A::apply(&current.state, &evt)?,
+                generation: position + 1,
+                event_time: ce.event_time,
+            };
+        }
+        Ok(current)
+    }
+
+    /// Loads the aggregate, handles the command and appends the resulting events to the
+    /// stream, returning the stored cloud events. The events are appended only if the stream
+    /// is still at the version it was loaded at, so a command racing with another one on
+    /// the same stream fails with a store failure and can be retried. Whether the events of
+    /// a command are appended atomically depends on the store (see
+    /// [`EventStore::append_batch`]).
+    pub fn execute(&self, stream: &str, cmd: &A::Command) -> Result<Vec<CloudEvent>> {
+        self.execute_with(stream, cmd, &())
+    }
+
+    /// Like `execute`, but handles the command with the given context
+    pub fn execute_with<Ctx>(
+        &self,
+        stream: &str,
+        cmd: &A::Command,
+        ctx: &Ctx,
+    ) -> Result<Vec<CloudEvent>>
+    where
+        A: CommandHandler<Ctx>,
+    {
+        self.handle(stream, cmd, ctx, None)
+    }
+
+    /// Executes the command against the aggregate instance it targets, using the
+    /// command's aggregate ID as the stream name. The resulting events take the command's
+    /// effective time unless they set their own.
+    pub fn execute_command(&self, cmd: &A::Command) -> Result<Vec<CloudEvent>>
+    where
+        A::Command: Command,
+    {
+        self.execute_command_with(cmd, &())
+    }
+
+    /// Like `execute_command`, but handles the command with the given context. The
+    /// resulting events take the command's effective time unless they set their own.
+    pub fn execute_command_with<Ctx>(&self, cmd: &A::Command, ctx: &Ctx) -> Result<Vec<CloudEvent>>
+    where
+        A: CommandHandler<Ctx>,
+        A::Command: Command,
+    {
+        self.handle(&cmd.aggregate_id(), cmd, ctx, cmd.effective_time())
+    }
+
+    fn handle<Ctx>(
+        &self,
+        stream: &str,
+        cmd: &A::Command,
+        ctx: &Ctx,
+        effective_time: Option<DateTime<Utc>>,
+    ) -> Result<Vec<CloudEvent>>
+    where
+        A: CommandHandler<Ctx>,
+    {
+        let current = self.fold(stream, |_| true, |_, _| true)?;
+        let evts = A::handle_command_with(&current.state, cmd, ctx)?
+            .into_iter()
+            .map(|evt| WithEffectiveTime::new(evt, effective_time))
+            .collect();
+        // The loaded generation is one past the position of the last event folded
+        let expected = match current.generation {
+            0 => ExpectedVersion::NoStream,
+            generation => ExpectedVersion::Exact(generation - 1),
+        };
+        self.store.append_batch(evts, stream, expected)
+    }
+}
@@ -0,0 +1,78 @@
+//! Retry policies
+//!
+//! A [`RetryPolicy`] decides how often, and how long apart, an operation that may fail
+//! intermittently is attempted, such as publishing an event to a message broker.
+use super::Result;
+use std::thread;
+use std::time::Duration;
+
+/// How many times an operation is attempted, with an exponential backoff between attempts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of attempts, including the first one
+    pub max_attempts: u32,
+    /// The delay before the first retry
+    pub backoff: Duration,
+    /// The factor by which the delay grows after every retry
+    pub multiplier: u32,
+}
+
+impl RetryPolicy {
+    /// A policy doubling the given backoff after every retry
+    pub fn new(max_attempts: u32, backoff: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff,
+            multiplier: 2,
+        }
+    }
+
+    /// A policy retrying without any delay
+    pub fn immediate(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(max_attempts, Duration::from_millis(0))
+    }
+
+    /// A policy that only attempts the operation once
+    pub fn never() -> RetryPolicy {
+        RetryPolicy::immediate(1)
+    }
+
+    /// Sets the factor by which the delay grows after every retry
+    pub fn with_multiplier(self, multiplier: u32) -> RetryPolicy {
+        RetryPolicy { multiplier, ..self }
+    }
+
+    /// The delay before the given retry, counting from 1
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .checked_pow(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.backoff.checked_mul(factor).unwrap_or(Duration::MAX)
+    }
+
+    /// Runs the operation, which is passed the number of the attempt (counting from 1),
+    /// until it succeeds or the attempts run out. Returns the error of the last attempt.
+    pub fn run<T, F>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut(u32) -> Result<T>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op(attempt) {
+                Err(_) if attempt < self.max_attempts => {
+                    thread::sleep(self.delay(attempt));
+                    attempt += 1;
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, retried after 100 and 200 milliseconds
+    fn default() -> Self {
+        RetryPolicy::new(3, Duration::from_millis(100))
+    }
+}
@@ -0,0 +1,197 @@
+//! Scheduled commands
+//!
+//! A scheduler keeps commands that should only be sent at a later time, such as cancelling
+//! an order when its payment hasn't been received within three days. Scheduled commands
+//! are persisted as events in a stream of the event store, so they survive a restart, and
+//! can be cancelled until they are due.
+//!
+//! The scheduler reads the time from a [`Clock`]; calling `fire_due` sends every command
+//! that is due through the given sink, such as a dispatcher or a
+//! [`CommandBus`](super::bus::CommandBus). Tests can fast-forward a
+//! [`ManualClock`](super::clock::ManualClock) to fire commands deterministically.
+use super::clock::Clock;
+use super::cloudevents::CloudEvent;
+use super::eventstore::EventStore;
+use super::{Command, Error, EventCatalog, Kind, Result};
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::marker::PhantomData;
+
+/// The stream scheduled commands are kept in, unless another one is given
+pub const SCHEDULE_STREAM: &str = "schedule";
+
+/// Events recording the life cycle of scheduled commands. A scheduled command is
+/// identified by the ID of the cloud event that scheduled it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version("1.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/scheduler")]
+#[event_type_prefix("schedule")]
+pub enum ScheduleEvent {
+    CommandScheduled {
+        due: DateTime<Utc>,
+        command_type: String,
+        command: Value,
+    },
+    CommandCancelled {
+        id: String,
+    },
+    CommandFired {
+        id: String,
+    },
+    CommandFailed {
+        id: String,
+        reason: String,
+    },
+}
+
+/// A command waiting to be sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledCommand<C> {
+    pub id: String,
+    pub due: DateTime<Utc>,
+    pub command: C,
+}
+
+/// The outcome of sending a scheduled command that was due
+#[derive(Debug)]
+pub struct FiredCommand {
+    pub id: String,
+    pub outcome: Result<Vec<CloudEvent>>,
+}
+
+/// Keeps commands of one type until they are due, in a stream of an event store
+pub struct Scheduler<C, S, K> {
+    store: S,
+    clock: K,
+    stream: String,
+    command: PhantomData<fn() -> C>,
+}
+
+impl<C, S, K> Scheduler<C, S, K>
+where
+    C: Command + Serialize + DeserializeOwned + 'static,
+    S: EventStore,
+    K: Clock,
+{
+    /// Creates a scheduler keeping its commands in the default stream of the given store
+    pub fn new(store: S, clock: K) -> Scheduler<C, S, K> {
+        Scheduler::with_stream(store, clock, SCHEDULE_STREAM)
+    }
+
+    /// Creates a scheduler keeping its commands in the named stream, so that schedulers
+    /// for different command types can share a store
+    pub fn with_stream(store: S, clock: K, stream: &str) -> Scheduler<C, S, K> {
+        Scheduler {
+            store,
+            clock,
+            stream: stream.to_owned(),
+            command: PhantomData,
+        }
+    }
+
+    /// The underlying event store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Schedules a command to be sent at the given time, returning its ID
+    pub fn schedule_at(&self, cmd: &C, due: DateTime<Utc>) -> Result<String> {
+        let command = serde_json::to_value(cmd).map_err(|e| Error {
+            kind: Kind::ValidationFailure(format!("Command could not be serialized: {}", e)),
+        })?;
+        let ce = self.store.append(
+            ScheduleEvent::CommandScheduled {
+                due,
+                command_type: cmd.command_type().to_owned(),
+                command,
+            },
+            &self.stream,
+        )?;
+        Ok(ce.event_id)
+    }
+
+    /// Schedules a command to be sent once the given time has passed on the clock
+    pub fn schedule_in(&self, cmd: &C, delay: Duration) -> Result<String> {
+        self.schedule_at(cmd, self.clock.now() + delay)
+    }
+
+    /// Cancels a command that hasn't been sent yet
+    pub fn cancel(&self, id: &str) -> Result<()> {
+        if !self.pending()?.iter().any(|scheduled| scheduled.id == id) {
+            return Err(Error {
+                kind: Kind::ValidationFailure(format!("No command with ID {} is pending", id)),
+            });
+        }
+        self.store.append(
+            ScheduleEvent::CommandCancelled { id: id.to_owned() },
+            &self.stream,
+        )?;
+        Ok(())
+    }
+
+    /// The commands that haven't been sent or cancelled yet, in the order they are due
+    pub fn pending(&self) -> Result<Vec<ScheduledCommand<C>>> {
+        let mut pending: Vec<ScheduledCommand<Value>> = Vec::new();
+        for ce in self.store.read_stream(&self.stream)? {
+            match ScheduleEvent::deserialize_by_type(&ce.event_type, &ce.data)? {
+                ScheduleEvent::CommandScheduled { due, command, .. } => {
+                    pending.push(ScheduledCommand {
+                        id: ce.event_id,
+                        due,
+                        command,
+                    })
+                }
+                ScheduleEvent::CommandCancelled { id }
+                | ScheduleEvent::CommandFired { id }
+                | ScheduleEvent::CommandFailed { id, .. } => {
+                    pending.retain(|scheduled| scheduled.id != id)
+                }
+            }
+        }
+        pending.sort_by_key(|scheduled| scheduled.due);
+        pending
+            .into_iter()
+            .map(|ScheduledCommand { id, due, command }| {
+                let command = serde_json::from_value(command).map_err(|e| Error {
+                    kind: Kind::ValidationFailure(format!(
+                        "Scheduled command {} could not be deserialized: {}",
+                        id, e
+                    )),
+                })?;
+                Ok(ScheduledCommand { id, due, command })
+            })
+            .collect()
+    }
+
+    /// Sends every command that is due, in the order they are due, by calling `send` with
+    /// it, e.g. `|cmd| bus.send(cmd)` or a closure dispatching it through a dispatcher or a
+    /// repository. Each command is sent once: whether it succeeded or failed is recorded,
+    /// and neither is sent again.
+    pub fn fire_due<F>(&self, mut send: F) -> Result<Vec<FiredCommand>>
+    where
+        F: FnMut(&C) -> Result<Vec<CloudEvent>>,
+    {
+        let now = self.clock.now();
+        let mut fired = Vec::new();
+        for scheduled in self.pending()? {
+            if scheduled.due > now {
+                break;
+            }
+            let outcome = send(&scheduled.command);
+            let id = scheduled.id;
+            let evt = match &outcome {
+                Ok(_) => ScheduleEvent::CommandFired { id: id.clone() },
+                Err(e) => ScheduleEvent::CommandFailed {
+                    id: id.clone(),
+                    reason: e.to_string(),
+                },
+            };
+            self.store.append(evt, &self.stream)?;
+            fired.push(FiredCommand { id, outcome });
+        }
+        Ok(fired)
+    }
+}
@@ -0,0 +1,310 @@
+//! JSON Schema export for event payloads
+//!
+//! Types implementing [`EventSchema`] (usually through the `EventSchema` derive macro) can
+//! describe the data of every event type they produce as a JSON Schema document, so that
+//! consumers written in other languages know the contract for those events. The derived
+//! schemas follow serde's renames and skipped fields, and only describe enums in serde's
+//! default, externally tagged representation.
+//!
+//! [`async_api`] combines the schemas of all the events an aggregate produces into an
+//! AsyncAPI-style catalog with one channel per event source.
+use super::{Aggregate, EventCatalog};
+use chrono::{DateTime, TimeZone};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+#[cfg(feature = "eventstore")]
+use super::{cloudevents::CloudEvent, Event};
+
+/// The JSON Schema dialect of the generated documents
+pub const JSON_SCHEMA_DIALECT: &str = "http://json-schema.org/draft-07/schema#";
+
+/// The AsyncAPI version of the generated catalogs
+pub const ASYNC_API_VERSION: &str = "2.6.0";
+
+/// Types that can describe themselves as a JSON Schema, as serialized by serde
+pub trait JsonSchema {
+    fn json_schema() -> Value;
+
+    /// Whether the value may be left out of the enclosing object. Only `Option` is optional.
+    fn is_optional() -> bool {
+        false
+    }
+}
+
+/// Event types that can describe the data of every event type they produce
+pub trait EventSchema: EventCatalog {
+    /// The schemas for each event type, in the order of `EventCatalog::EVENT_TYPES`
+    fn event_schemas() -> Vec<EventTypeSchema>;
+
+    /// The schema for a single event type, if this type produces it
+    fn event_schema(event_type: &str) -> Option<EventTypeSchema> {
+        Self::event_schemas()
+            .into_iter()
+            .find(|schema| schema.event_type == event_type)
+    }
+}
+
+/// The schema of the data of a single event type, along with the event's metadata
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventTypeSchema {
+    pub event_type: String,
+    pub event_type_version: String,
+    pub event_source: String,
+    /// JSON Schema of the event data, without the document-level keywords
+    pub data: Value,
+}
+
+impl EventTypeSchema {
+    pub fn new(
+        event_type: &str,
+        event_type_version: &str,
+        event_source: &str,
+        data: Value,
+    ) -> Self {
+        EventTypeSchema {
+            event_type: event_type.to_owned(),
+            event_type_version: event_type_version.to_owned(),
+            event_source: event_source.to_owned(),
+            data,
+        }
+    }
+
+    /// The URI identifying this schema, derived from the event's source, type and version
+    pub fn schema_uri(&self) -> String {
+        format!(
+            "{}/schemas/{}/{}.json",
+            self.event_source.trim_end_matches('/'),
+            self.event_type,
+            self.event_type_version
+        )
+    }
+
+    /// The standalone JSON Schema document for the event data
+    pub fn to_json_schema(&self) -> Value {
+        let mut doc = Map::new();
+        doc.insert("$schema".to_owned(), json!(JSON_SCHEMA_DIALECT));
+        doc.insert("$id".to_owned(), json!(self.schema_uri()));
+        doc.insert("title".to_owned(), json!(self.event_type));
+        doc.insert("x-event-type".to_owned(), json!(self.event_type));
+        doc.insert(
+            "x-event-type-version".to_owned(),
+            json!(self.event_type_version),
+        );
+        doc.insert("x-event-source".to_owned(), json!(self.event_source));
+        if let Value::Object(data) = &self.data {
+            doc.extend(data.clone());
+        }
+        Value::Object(doc)
+    }
+
+    /// The schema of the same event wrapped in an enum variant, as written by an enum that
+    /// delegates to the events it wraps
+    pub fn tagged(self, variant: &str) -> Self {
+        EventTypeSchema {
+            data: tagged(variant, self.data),
+            ..self
+        }
+    }
+}
+
+/// Emits an AsyncAPI-style catalog of every event the aggregate produces. Each event source
+/// becomes a channel, and each event type a message whose payload is the event's schema.
+pub fn async_api<A>(title: &str, version: &str) -> Value
+where
+    A: Aggregate,
+    A::Event: EventSchema,
+{
+    let mut channels: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    let mut messages = Map::new();
+    for schema in A::Event::event_schemas() {
+        channels
+            .entry(schema.event_source.clone())
+            .or_default()
+            .push(json!({ "$ref": format!("#/components/messages/{}", schema.event_type) }));
+        messages.insert(
+            schema.event_type.clone(),
+            json!({
+                "name": schema.event_type,
+                "title": schema.event_type,
+                "contentType": "application/json",
+                "schemaFormat": "application/schema+json;version=draft-07",
+                "x-event-type-version": schema.event_type_version,
+                "payload": schema.to_json_schema(),
+            }),
+        );
+    }
+    let channels: Map<String, Value> = channels
+        .into_iter()
+        .map(|(source, refs)| {
+            (
+                source,
+                json!({ "subscribe": { "message": { "oneOf": refs } } }),
+            )
+        })
+        .collect();
+    json!({
+        "asyncapi": ASYNC_API_VERSION,
+        "info": { "title": title, "version": version },
+        "channels": channels,
+        "components": { "messages": messages },
+    })
+}
+
+/// Converts an event into a cloud event whose `dataschema` is the URI of its generated schema
+#[cfg(feature = "eventstore")]
+pub fn cloud_event<E: Event + EventSchema>(evt: E) -> CloudEvent {
+    let schema = E::event_schema(evt.event_type());
+    let ce: CloudEvent = evt.into();
+    match schema {
+        Some(schema) => ce.with_data_schema(schema.schema_uri()),
+        None => ce,
+    }
+}
+
+/// Schema of an object with the given `(name, schema, optional)` properties
+pub fn object(properties: Vec<(&str, Value, bool)>) -> Value {
+    let required: Vec<&str> = properties
+        .iter()
+        .filter(|(_, _, optional)| !optional)
+        .map(|(name, _, _)| *name)
+        .collect();
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema, _)| (name.to_owned(), schema))
+        .collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Schema of a fixed-length array, as tuples and tuple structs are serialized
+pub fn tuple(items: Vec<Value>) -> Value {
+    let len = items.len();
+    json!({
+        "type": "array",
+        "items": items,
+        "minItems": len,
+        "maxItems": len,
+    })
+}
+
+/// Schema of an enum variant carrying the given payload
+pub fn tagged(variant: &str, payload: Value) -> Value {
+    object(vec![(variant, payload, false)])
+}
+
+/// Schema of a unit enum variant, serialized as its name
+pub fn unit_variant(variant: &str) -> Value {
+    json!({ "const": variant })
+}
+
+macro_rules! json_schema {
+    ($schema:tt => $($ty:ty),*) => {
+        $(
+            impl JsonSchema for $ty {
+                fn json_schema() -> Value {
+                    json!($schema)
+                }
+            }
+        )*
+    };
+}
+
+json_schema!({ "type": "boolean" } => bool);
+json_schema!({ "type": "integer" } => i8, i16, i32, i64, i128, isize);
+json_schema!({ "type": "integer", "minimum": 0 } => u8, u16, u32, u64, u128, usize);
+json_schema!({ "type": "number" } => f32, f64);
+json_schema!({ "type": "string" } => String, str);
+json_schema!({ "type": "string", "minLength": 1, "maxLength": 1 } => char);
+json_schema!({ "type": "null" } => ());
+json_schema!({} => Value);
+
+impl<Tz: TimeZone> JsonSchema for DateTime<Tz> {
+    fn json_schema() -> Value {
+        json!({ "type": "string", "format": "date-time" })
+    }
+}
+
+impl<T: JsonSchema + ?Sized> JsonSchema for &T {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl<T: JsonSchema + ?Sized> JsonSchema for Box<T> {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn json_schema() -> Value {
+        json!({ "anyOf": [T::json_schema(), { "type": "null" }] })
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+}
+
+macro_rules! array_schema {
+    ($($ty:ident),*) => {
+        $(
+            impl<T: JsonSchema> JsonSchema for $ty<T> {
+                fn json_schema() -> Value {
+                    json!({ "type": "array", "items": T::json_schema() })
+                }
+            }
+        )*
+    };
+}
+
+array_schema!(Vec, VecDeque);
+
+impl<T: JsonSchema> JsonSchema for [T] {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for HashSet<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema(), "uniqueItems": true })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for BTreeSet<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema(), "uniqueItems": true })
+    }
+}
+
+impl<K, V: JsonSchema> JsonSchema for HashMap<K, V> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": V::json_schema() })
+    }
+}
+
+impl<K, V: JsonSchema> JsonSchema for BTreeMap<K, V> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": V::json_schema() })
+    }
+}
+
+macro_rules! tuple_schema {
+    ($(($($name:ident),+)),*) => {
+        $(
+            impl<$($name: JsonSchema),+> JsonSchema for ($($name,)+) {
+                fn json_schema() -> Value {
+                    tuple(vec![$($name::json_schema()),+])
+                }
+            }
+        )*
+    };
+}
+
+tuple_schema!((A, B), (A, B, C), (A, B, C, D));
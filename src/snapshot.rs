@@ -0,0 +1,80 @@
+//! Snapshots
+//!
+//! Rebuilding an aggregate's state means folding every event of its stream. A snapshot
+//! records the state after the first events of a stream, so that loading the state only
+//! has to fold the events appended since. Snapshots are taken explicitly, through
+//! `Repository::snapshot`, and kept in a [`SnapshotStore`].
+use super::Result;
+use chrono::prelude::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The state of an aggregate after folding the first events of its stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<St> {
+    pub state: St,
+    /// The number of events folded into the state
+    pub generation: u64,
+    /// The event time of the last event folded into the state, if any
+    pub event_time: Option<DateTime<Utc>>,
+}
+
+/// Keeps the snapshots taken of streams
+pub trait SnapshotStore<St> {
+    /// Saves a snapshot of the named stream, replacing any with the same generation
+    fn save(&self, stream: &str, snapshot: Snapshot<St>) -> Result<()>;
+
+    /// The snapshots of the named stream, in the order of their generation
+    fn snapshots(&self, stream: &str) -> Result<Vec<Snapshot<St>>>;
+}
+
+/// The snapshot store of repositories that don't take snapshots. Saving a snapshot has
+/// no effect and there never are any to load.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoSnapshots;
+
+impl<St> SnapshotStore<St> for NoSnapshots {
+    fn save(&self, _stream: &str, _snapshot: Snapshot<St>) -> Result<()> {
+        Ok(())
+    }
+
+    fn snapshots(&self, _stream: &str) -> Result<Vec<Snapshot<St>>> {
+        Ok(Vec::new())
+    }
+}
+
+/// A simple, in-memory snapshot store. The resulting store is thread-safe.
+#[derive(Debug)]
+pub struct MemorySnapshotStore<St> {
+    snapshots: Mutex<HashMap<String, Vec<Snapshot<St>>>>,
+}
+
+impl<St> MemorySnapshotStore<St> {
+    pub fn new() -> MemorySnapshotStore<St> {
+        MemorySnapshotStore {
+            snapshots: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<St> Default for MemorySnapshotStore<St> {
+    fn default() -> Self {
+        MemorySnapshotStore::new()
+    }
+}
+
+impl<St: Clone> SnapshotStore<St> for MemorySnapshotStore<St> {
+    fn save(&self, stream: &str, snapshot: Snapshot<St>) -> Result<()> {
+        let mut guard = self.snapshots.lock().unwrap();
+        let snapshots = guard.entry(stream.to_owned()).or_default();
+        snapshots.retain(|stored| stored.generation != snapshot.generation);
+        snapshots.push(snapshot);
+        snapshots.sort_by_key(|stored| stored.generation);
+        Ok(())
+    }
+
+    fn snapshots(&self, stream: &str) -> Result<Vec<Snapshot<St>>> {
+        let guard = self.snapshots.lock().unwrap();
+        Ok(guard.get(stream).cloned().unwrap_or_default())
+    }
+}
@@ -0,0 +1,612 @@
+//! Conformance suite for event store implementations
+//!
+//! Each function in this module checks one aspect of the behavior every `EventStore`
+//! must share: ordering, stream isolation, timestamps, filtering, expected versions,
+//! deletion, retention, the feed of all streams, categories and failure handling.
+//! The functions are generic so they can be run against any implementation. The easiest
+//! way to run all of them is the `eventstore_conformance_tests!` macro, which expands to
+//! one `#[test]` per check given an expression that creates the store under test:
+//!
+//! ```rust,ignore
+//! eventstore_conformance_tests!(MemoryEventStore::new());
+//! ```
+//!
+//! The checks write to freshly named streams, so a single store may be shared between them.
+use super::super::cloudevents::CloudEvent;
+use super::super::eventstore::{
+    Deletion, EventStore, ExpectedVersion, Position, RecordedEvent, StreamMetadata, StreamName,
+};
+use super::super::{Event, Kind};
+use chrono::prelude::*;
+use chrono::Duration;
+use std::thread;
+use uuid::Uuid;
+
+/// Source used for the events written by the conformance suite
+pub const CONFORMANCE_SOURCE: &str = "events://github.com/pholactery/eventsourcing/conformance";
+
+/// The event type written by the conformance suite
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ConformanceEvent {
+    Numbered(u32),
+    Labelled { label: String, tags: Vec<String> },
+}
+
+impl Event for ConformanceEvent {
+    fn event_type_version(&self) -> &str {
+        "1.0"
+    }
+
+    fn event_type(&self) -> &str {
+        match self {
+            ConformanceEvent::Numbered(_) => "conformanceevent.numbered",
+            ConformanceEvent::Labelled { .. } => "conformanceevent.labelled",
+        }
+    }
+
+    fn event_source(&self) -> &str {
+        CONFORMANCE_SOURCE
+    }
+}
+
+/// Creates a stream name that has not been used before
+pub fn unique_stream(prefix: &str) -> String {
+    format!("{}-{}", prefix, Uuid::new_v4().to_simple())
+}
+
+fn numbers(events: &[CloudEvent]) -> Vec<u64> {
+    events
+        .iter()
+        .map(|evt| evt.data["Numbered"].as_u64().unwrap_or(u64::MAX))
+        .collect()
+}
+
+fn append_numbered<S: EventStore>(store: &S, stream: &str, count: u32) -> Vec<CloudEvent> {
+    (0..count)
+        .map(|n| {
+            store
+                .append(ConformanceEvent::Numbered(n), stream)
+                .expect("append should succeed")
+        })
+        .collect()
+}
+
+/// Appending returns a valid cloud event describing the appended event
+pub fn append_returns_cloud_event<S: EventStore>(store: &S) {
+    let stream = unique_stream("append");
+    let ce = store
+        .append(ConformanceEvent::Numbered(7), &stream)
+        .expect("append should succeed");
+
+    assert!(ce.validate().is_ok(), "appended event is invalid: {:?}", ce);
+    assert_eq!(ce.event_type, "conformanceevent.numbered");
+    assert_eq!(ce.event_type_version, "1.0");
+    assert_eq!(ce.source, CONFORMANCE_SOURCE);
+    assert_eq!(ce.data["Numbered"], 7);
+}
+
+/// Events are read back in the order in which they were appended, with their ids intact
+pub fn reads_preserve_append_order<S: EventStore>(store: &S) {
+    let stream = unique_stream("order");
+    let appended = append_numbered(store, &stream, 25);
+
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(numbers(&read), (0..25).collect::<Vec<u64>>());
+    let appended_ids: Vec<_> = appended.iter().map(|evt| &evt.event_id).collect();
+    let read_ids: Vec<_> = read.iter().map(|evt| &evt.event_id).collect();
+    assert_eq!(read_ids, appended_ids);
+}
+
+/// Events appended to one stream are never visible in another
+pub fn streams_are_isolated<S: EventStore>(store: &S) {
+    let first = unique_stream("isolated");
+    let second = unique_stream("isolated");
+    store
+        .append(ConformanceEvent::Numbered(1), &first)
+        .expect("append should succeed");
+    store
+        .append(ConformanceEvent::Numbered(2), &second)
+        .expect("append should succeed");
+    store
+        .append(ConformanceEvent::Numbered(3), &first)
+        .expect("append should succeed");
+
+    let first_events = store.read_stream(&first).expect("read should succeed");
+    let second_events = store.read_stream(&second).expect("read should succeed");
+    assert_eq!(numbers(&first_events), vec![1, 3]);
+    assert_eq!(numbers(&second_events), vec![2]);
+}
+
+/// Reading a stream that was never written to yields no events
+pub fn unknown_stream_is_empty<S: EventStore>(store: &S) {
+    let events = store
+        .read_stream(&unique_stream("unknown"))
+        .expect("read should succeed");
+    assert!(events.is_empty());
+}
+
+/// Event times are taken at append time and never decrease within a stream
+pub fn timestamps_are_monotonic<S: EventStore>(store: &S) {
+    let stream = unique_stream("time");
+    let before = Utc::now();
+    append_numbered(store, &stream, 5);
+    let after = Utc::now();
+
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(read.len(), 5);
+    for pair in read.windows(2) {
+        assert!(pair[0].event_time <= pair[1].event_time);
+    }
+    for evt in &read {
+        let time = evt.event_time.expect("stored events should have a time");
+        assert!(time >= before && time <= after);
+    }
+}
+
+/// Time range reads only return the events recorded within the range
+pub fn range_reads_filter_by_time<S: EventStore>(store: &S) {
+    let stream = unique_stream("range");
+    let early = append_numbered(store, &stream, 2);
+    thread::sleep(std::time::Duration::from_millis(5));
+    let start = Utc::now();
+    store
+        .append(ConformanceEvent::Numbered(10), &stream)
+        .expect("append should succeed");
+    let end = Utc::now();
+    thread::sleep(std::time::Duration::from_millis(5));
+    store
+        .append(ConformanceEvent::Numbered(20), &stream)
+        .expect("append should succeed");
+
+    let ranged = store
+        .read_stream_range(&stream, start, end)
+        .expect("read should succeed");
+    assert_eq!(numbers(&ranged), vec![10]);
+
+    let everything = store
+        .read_stream_range(
+            &stream,
+            early[0]
+                .event_time
+                .expect("stored events should have a time")
+                - Duration::seconds(1),
+            Utc::now() + Duration::seconds(1),
+        )
+        .expect("read should succeed");
+    assert_eq!(numbers(&everything), vec![0, 1, 10, 20]);
+}
+
+/// Event types, versions, sources and nested payloads survive a round trip
+pub fn payloads_round_trip<S: EventStore>(store: &S) {
+    let stream = unique_stream("payload");
+    let labelled = ConformanceEvent::Labelled {
+        label: "ünïcödé \"quoted\"".to_owned(),
+        tags: vec!["a".to_owned(), "b".to_owned()],
+    };
+    let appended = store
+        .append(labelled.clone(), &stream)
+        .expect("append should succeed");
+
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(read.len(), 1);
+    let evt = &read[0];
+    assert_eq!(evt.event_id, appended.event_id);
+    assert_eq!(evt.event_type, "conformanceevent.labelled");
+    assert_eq!(evt.event_type_version, "1.0");
+    assert_eq!(evt.source, CONFORMANCE_SOURCE);
+    assert_eq!(evt.event_time, appended.event_time);
+    let payload: ConformanceEvent = serde_json::from_value(evt.data.clone()).unwrap();
+    assert_eq!(payload, labelled);
+}
+
+/// Appending to an unnamed stream fails with a store failure
+pub fn rejects_empty_stream_name<S: EventStore>(store: &S) {
+    match store.append(ConformanceEvent::Numbered(1), "") {
+        Err(e) => match e.kind {
+            Kind::StoreFailure(_) => {}
+            other => panic!("expected a store failure, got {:?}", other),
+        },
+        Ok(evt) => panic!("appending to an empty stream name succeeded: {:?}", evt),
+    }
+}
+
+fn assert_store_failure<T: std::fmt::Debug>(outcome: super::super::Result<T>, action: &str) {
+    match outcome {
+        Err(e) => match e.kind {
+            Kind::StoreFailure(_) => {}
+            other => panic!("expected a store failure, got {:?}", other),
+        },
+        Ok(value) => panic!("{} succeeded: {:?}", action, value),
+    }
+}
+
+/// Batches are appended only if the stream is at the expected version, the number of its
+/// last event. A batch appended at the wrong version appends none of its events.
+pub fn batches_are_appended_at_the_expected_version<S: EventStore>(store: &S) {
+    let stream = unique_stream("versioned");
+    let batch = |numbers: &[u32]| {
+        numbers
+            .iter()
+            .map(|n| ConformanceEvent::Numbered(*n))
+            .collect::<Vec<_>>()
+    };
+    assert_store_failure(
+        store.append_batch(batch(&[0]), &stream, ExpectedVersion::StreamExists),
+        "appending to a stream expected to exist",
+    );
+    let appended = store
+        .append_batch(batch(&[0, 1]), &stream, ExpectedVersion::NoStream)
+        .expect("append should succeed");
+    assert_eq!(numbers(&appended), vec![0, 1]);
+
+    assert_store_failure(
+        store.append_batch(batch(&[7, 8]), &stream, ExpectedVersion::NoStream),
+        "appending to a stream expected not to exist",
+    );
+    assert_store_failure(
+        store.append_batch(batch(&[7, 8]), &stream, ExpectedVersion::Exact(0)),
+        "appending at an old version",
+    );
+    store
+        .append_batch(batch(&[2, 3]), &stream, ExpectedVersion::Exact(1))
+        .expect("append should succeed");
+    store
+        .append_batch(batch(&[4]), &stream, ExpectedVersion::StreamExists)
+        .expect("append should succeed");
+    store
+        .append_batch(batch(&[5]), &stream, ExpectedVersion::Any)
+        .expect("append should succeed");
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(numbers(&read), vec![0, 1, 2, 3, 4, 5]);
+
+    store
+        .delete_stream(&stream, Deletion::Soft)
+        .expect("delete should succeed");
+    store
+        .append_batch(batch(&[9]), &stream, ExpectedVersion::NoStream)
+        .expect("appending to a soft deleted stream should succeed");
+}
+
+/// Soft deleted streams read as empty and are recreated by the next append
+pub fn soft_deleted_streams_can_be_recreated<S: EventStore>(store: &S) {
+    let stream = unique_stream("softdelete");
+    append_numbered(store, &stream, 3);
+    store
+        .delete_stream(&stream, Deletion::Soft)
+        .expect("delete should succeed");
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert!(read.is_empty(), "deleted events were read: {:?}", read);
+
+    store
+        .append(ConformanceEvent::Numbered(9), &stream)
+        .expect("append should succeed");
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(numbers(&read), vec![9]);
+}
+
+/// Setting the metadata of a soft deleted stream doesn't bring its events back
+pub fn soft_deleted_streams_stay_deleted_when_given_metadata<S: EventStore>(store: &S) {
+    let stream = unique_stream("softdelete");
+    append_numbered(store, &stream, 2);
+    store
+        .delete_stream(&stream, Deletion::Soft)
+        .expect("delete should succeed");
+    store
+        .set_stream_metadata(
+            &stream,
+            StreamMetadata::default().with_property("owner", "payments"),
+        )
+        .expect("writing metadata should succeed");
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert!(read.is_empty(), "deleted events were read: {:?}", read);
+}
+
+/// Hard deleted streams can never be read, appended to or deleted again
+pub fn hard_deleted_streams_are_tombstoned<S: EventStore>(store: &S) {
+    let stream = unique_stream("harddelete");
+    append_numbered(store, &stream, 2);
+    store
+        .delete_stream(&stream, Deletion::Hard)
+        .expect("delete should succeed");
+
+    assert_store_failure(store.read_stream(&stream), "reading a deleted stream");
+    assert_store_failure(
+        store.append(ConformanceEvent::Numbered(1), &stream),
+        "appending to a deleted stream",
+    );
+    assert_store_failure(
+        store.delete_stream(&stream, Deletion::Soft),
+        "deleting a deleted stream",
+    );
+}
+
+/// Deleting a stream that doesn't exist fails with a store failure
+pub fn deleting_unknown_stream_fails<S: EventStore>(store: &S) {
+    assert_store_failure(
+        store.delete_stream(&unique_stream("unknown"), Deletion::Soft),
+        "deleting an unknown stream",
+    );
+}
+
+/// Truncating a stream hides the events before the given position, leaving the later
+/// events, and those appended afterwards, readable
+pub fn truncation_hides_earlier_events<S: EventStore>(store: &S) {
+    let stream = unique_stream("truncate");
+    append_numbered(store, &stream, 25);
+    store
+        .truncate_before(&stream, 22)
+        .expect("truncate should succeed");
+    store
+        .append(ConformanceEvent::Numbered(30), &stream)
+        .expect("append should succeed");
+
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(numbers(&read), vec![22, 23, 24, 30]);
+}
+
+/// Numbered reads give every event its position in the stream, which the events after
+/// hidden ones keep
+pub fn numbered_reads_keep_positions<S: EventStore>(store: &S) {
+    let stream = unique_stream("numbered");
+    append_numbered(store, &stream, 4);
+    store
+        .truncate_before(&stream, 2)
+        .expect("truncate should succeed");
+    store
+        .append(ConformanceEvent::Numbered(9), &stream)
+        .expect("append should succeed");
+
+    let (positions, read): (Vec<u64>, Vec<CloudEvent>) = store
+        .read_stream_numbered(&stream)
+        .expect("read should succeed")
+        .into_iter()
+        .unzip();
+    assert_eq!(positions, vec![2, 3, 4]);
+    assert_eq!(numbers(&read), vec![2, 3, 9]);
+}
+
+/// Stream metadata reads back as it was written, and truncating a stream keeps its other
+/// metadata
+pub fn metadata_round_trips<S: EventStore>(store: &S) {
+    let stream = unique_stream("metadata");
+    assert_eq!(
+        store
+            .get_stream_metadata(&stream)
+            .expect("reading metadata should succeed"),
+        StreamMetadata::default()
+    );
+    let metadata = StreamMetadata::default()
+        .with_max_age(Duration::hours(1))
+        .with_max_count(100)
+        .with_property("owner", "payments")
+        .with_property("classification", "confidential");
+    store
+        .set_stream_metadata(&stream, metadata.clone())
+        .expect("writing metadata should succeed");
+    assert_eq!(
+        store
+            .get_stream_metadata(&stream)
+            .expect("reading metadata should succeed"),
+        metadata
+    );
+
+    store
+        .truncate_before(&stream, 1)
+        .expect("truncate should succeed");
+    let truncated = store
+        .get_stream_metadata(&stream)
+        .expect("reading metadata should succeed");
+    assert_eq!(truncated.truncate_before, Some(1));
+    assert_eq!(truncated.custom, metadata.custom);
+}
+
+/// Reads only return the latest events of a stream with a maximum count
+pub fn max_count_limits_reads<S: EventStore>(store: &S) {
+    let stream = unique_stream("maxcount");
+    append_numbered(store, &stream, 5);
+    store
+        .set_stream_metadata(&stream, StreamMetadata::default().with_max_count(2))
+        .expect("writing metadata should succeed");
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(numbers(&read), vec![3, 4]);
+
+    store
+        .append(ConformanceEvent::Numbered(5), &stream)
+        .expect("append should succeed");
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(numbers(&read), vec![4, 5]);
+}
+
+/// Reads only return the events younger than the maximum age of their stream
+pub fn max_age_limits_reads<S: EventStore>(store: &S) {
+    let stream = unique_stream("maxage");
+    append_numbered(store, &stream, 2);
+    thread::sleep(std::time::Duration::from_millis(1100));
+    store
+        .set_stream_metadata(
+            &stream,
+            StreamMetadata::default().with_max_age(Duration::seconds(1)),
+        )
+        .expect("writing metadata should succeed");
+    store
+        .append(ConformanceEvent::Numbered(7), &stream)
+        .expect("append should succeed");
+
+    let read = store.read_stream(&stream).expect("read should succeed");
+    assert_eq!(numbers(&read), vec![7]);
+}
+
+/// Reading all streams yields the events of every stream in append order, with positions
+/// that never decrease, and continuing from the position of any of them skips none of the
+/// events after it
+pub fn read_all_orders_events_globally<S: EventStore>(store: &S) {
+    let first = unique_stream("all");
+    let second = unique_stream("all");
+    let appended: Vec<String> = [(0, &first), (1, &second), (2, &first)]
+        .iter()
+        .map(|(n, stream)| {
+            store
+                .append(ConformanceEvent::Numbered(*n), stream)
+                .expect("append should succeed")
+                .event_id
+        })
+        .collect();
+
+    let read_from = |from: Position| -> Vec<RecordedEvent> {
+        store
+            .read_all(from, usize::MAX)
+            .expect("read should succeed")
+    };
+    let ours = |recorded: Vec<RecordedEvent>| -> Vec<RecordedEvent> {
+        recorded
+            .into_iter()
+            .filter(|recorded| recorded.stream == first || recorded.stream == second)
+            .collect()
+    };
+    let ids = |recorded: &[RecordedEvent]| -> Vec<String> {
+        recorded
+            .iter()
+            .map(|recorded| recorded.event.event_id.clone())
+            .collect()
+    };
+    let all = read_from(Position::START);
+    let read = ours(all.clone());
+    let streams: Vec<&str> = read
+        .iter()
+        .map(|recorded| recorded.stream.as_str())
+        .collect();
+    assert_eq!(ids(&read), appended);
+    assert_eq!(streams, vec![&first, &second, &first]);
+    assert!(
+        all.windows(2)
+            .all(|pair| pair[0].position <= pair[1].position),
+        "positions decrease"
+    );
+
+    for (i, recorded) in read.iter().enumerate() {
+        let continued = ids(&ours(read_from(recorded.position)));
+        assert!(
+            continued.ends_with(&appended[i + 1..]),
+            "continuing after event {} skips events",
+            i
+        );
+    }
+    let seen = ids(&all);
+    let after = read_from(all.last().expect("events were appended").position);
+    assert!(ids(&after).iter().all(|id| !seen.contains(id)));
+    assert_eq!(
+        store
+            .read_all(Position::START, 2)
+            .expect("read should succeed")
+            .len(),
+        2
+    );
+    assert!(store
+        .read_all(Position::START, 0)
+        .expect("read should succeed")
+        .is_empty());
+}
+
+/// Reading a category yields the events of the streams of its aggregates, and no others, in
+/// append order
+pub fn category_reads_span_streams<S: EventStore>(store: &S) {
+    let category = format!("category{}", Uuid::new_v4().to_simple());
+    let first = StreamName::new(&category, "1").unwrap().to_string();
+    let second = StreamName::new(&category, "2-b").unwrap().to_string();
+    let other = unique_stream("other");
+    let appended: Vec<String> = [(0, &first), (1, &other), (2, &second), (3, &first)]
+        .iter()
+        .map(|(n, stream)| {
+            store
+                .append(ConformanceEvent::Numbered(*n), stream)
+                .expect("append should succeed")
+                .event_id
+        })
+        .collect();
+
+    let read = store.read_category(&category).expect("read should succeed");
+    let ids: Vec<&str> = read
+        .iter()
+        .map(|recorded| recorded.event.event_id.as_str())
+        .collect();
+    let streams: Vec<&str> = read
+        .iter()
+        .map(|recorded| recorded.stream.as_str())
+        .collect();
+    assert_eq!(ids, vec![&appended[0], &appended[2], &appended[3]]);
+    assert_eq!(streams, vec![&first, &second, &first]);
+    assert!(
+        read.windows(2)
+            .all(|pair| pair[0].position < pair[1].position),
+        "positions are not increasing"
+    );
+
+    let unknown = format!("category{}", Uuid::new_v4().to_simple());
+    assert!(store
+        .read_category(&unknown)
+        .expect("read should succeed")
+        .is_empty());
+}
+
+/// Runs every conformance check against the given store
+pub fn run_all<S: EventStore>(store: &S) {
+    append_returns_cloud_event(store);
+    reads_preserve_append_order(store);
+    streams_are_isolated(store);
+    unknown_stream_is_empty(store);
+    timestamps_are_monotonic(store);
+    range_reads_filter_by_time(store);
+    payloads_round_trip(store);
+    rejects_empty_stream_name(store);
+    batches_are_appended_at_the_expected_version(store);
+    soft_deleted_streams_can_be_recreated(store);
+    soft_deleted_streams_stay_deleted_when_given_metadata(store);
+    hard_deleted_streams_are_tombstoned(store);
+    deleting_unknown_stream_fails(store);
+    truncation_hides_earlier_events(store);
+    numbered_reads_keep_positions(store);
+    metadata_round_trips(store);
+    max_count_limits_reads(store);
+    max_age_limits_reads(store);
+    read_all_orders_events_globally(store);
+    category_reads_span_streams(store);
+}
+
+/// Expands to one `#[test]` per conformance check, each running against the store
+/// created by the given expression.
+#[macro_export]
+macro_rules! eventstore_conformance_tests {
+    ($store:expr) => {
+        $crate::eventstore_conformance_tests!(@tests $store;
+            append_returns_cloud_event,
+            reads_preserve_append_order,
+            streams_are_isolated,
+            unknown_stream_is_empty,
+            timestamps_are_monotonic,
+            range_reads_filter_by_time,
+            payloads_round_trip,
+            rejects_empty_stream_name,
+            batches_are_appended_at_the_expected_version,
+            soft_deleted_streams_can_be_recreated,
+            soft_deleted_streams_stay_deleted_when_given_metadata,
+            hard_deleted_streams_are_tombstoned,
+            deleting_unknown_stream_fails,
+            truncation_hides_earlier_events,
+            numbered_reads_keep_positions,
+            metadata_round_trips,
+            max_count_limits_reads,
+            max_age_limits_reads,
+            read_all_orders_events_globally,
+            category_reads_span_streams
+        );
+    };
+    (@tests $store:expr; $($check:ident),*) => {
+        $(
+            #[test]
+            fn $check() {
+                let store = $store;
+                $crate::testing::conformance::$check(&store);
+            }
+        )*
+    };
+}
@@ -0,0 +1,197 @@
+//! Test utilities for eventsourcing applications
+//!
+//! This module is only available with the `test-util` feature enabled. It contains a
+//! conformance suite for event store implementations, an in-process fake of the
+//! eventstore.org HTTP API and a Given/When/Then harness for unit testing aggregates
+//! in isolation:
+//!
+//! ```rust
+//!# extern crate serde;
+//!# #[macro_use] extern crate serde_derive;
+//!# extern crate eventsourcing;
+//!# extern crate serde_json;
+//!# #[macro_use] extern crate eventsourcing_derive;
+//!# use eventsourcing::{prelude::*, testing::AggregateTest, Error, Kind, Result};
+//!# #[derive(Serialize, Deserialize, Debug, Clone, Event)]
+//!# #[event_type_version(DOMAIN_VERSION)]
+//!# #[event_source("events://github.com/pholactery/eventsourcing/samples/bank")]
+//!# enum BankEvent {
+//!#     FundsDeposited(u32),
+//!#     FundsWithdrawn(u32),
+//!# }
+//!# const DOMAIN_VERSION: &str = "1.0";
+//!# enum BankCommand {
+//!#     WithdrawFunds(u32),
+//!# }
+//!# #[derive(Debug, Clone, Default)]
+//!# struct AccountData {
+//!#     balance: u32,
+//!#     generation: u64,
+//!# }
+//!# impl AggregateState for AccountData {
+//!#     fn generation(&self) -> u64 {
+//!#         self.generation
+//!#     }
+//!# }
+//!# struct Account;
+//!# impl Aggregate for Account {
+//!#     type Event = BankEvent;
+//!#     type Command = BankCommand;
+//!#     type State = AccountData;
+//!#     fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+//!#         let balance = match *evt {
+//!#             BankEvent::FundsDeposited(amt) => state.balance + amt,
+//!#             BankEvent::FundsWithdrawn(amt) => state.balance - amt,
+//!#         };
+//!#         Ok(AccountData { balance, generation: state.generation + 1 })
+//!#     }
+//!#     fn handle_command(state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+//!#         match *cmd {
+//!#             BankCommand::WithdrawFunds(amt) if amt <= state.balance => {
+//!#                 Ok(vec![BankEvent::FundsWithdrawn(amt)])
+//!#             }
+//!#             BankCommand::WithdrawFunds(_) => Err(Error {
+//!#                 kind: Kind::CommandFailure("Insufficient funds".to_owned()),
+//!#             }),
+//!#         }
+//!#     }
+//!# }
+//!# fn main() {
+//!AggregateTest::<Account>::given(vec![BankEvent::FundsDeposited(100)])
+//!    .when(BankCommand::WithdrawFunds(40))
+//!    .then_expect_events(vec![BankEvent::FundsWithdrawn(40)]);
+//!
+//!AggregateTest::<Account>::given(vec![BankEvent::FundsDeposited(100)])
+//!    .when(BankCommand::WithdrawFunds(140))
+//!    .then_expect_error(Kind::CommandFailure("Insufficient funds".to_owned()));
+//!# }
+//! ```
+use super::{Aggregate, Event, Kind, Result};
+use serde_json::Value;
+use std::fmt::Write;
+
+#[cfg(feature = "eventstore")]
+pub mod conformance;
+#[cfg(feature = "orgeventstore")]
+pub mod orgmock;
+
+/// The _given_ stage of an aggregate test: the state built from the events that
+/// have already happened.
+pub struct AggregateTest<A: Aggregate> {
+    state: A::State,
+}
+
+/// The _then_ stage of an aggregate test: the state the command was handled against
+/// and the outcome of handling it.
+pub struct AggregateTestOutcome<A: Aggregate> {
+    state: A::State,
+    outcome: Result<Vec<A::Event>>,
+}
+
+impl<A> AggregateTest<A>
+where
+    A: Aggregate,
+    A::State: Default,
+{
+    /// Starts a test from the default state with the given history applied to it
+    pub fn given(events: Vec<A::Event>) -> Self {
+        AggregateTest::given_state(A::State::default(), events)
+    }
+}
+
+impl<A: Aggregate> AggregateTest<A> {
+    /// Starts a test from an explicit initial state with the given history applied to it
+    pub fn given_state(initial: A::State, events: Vec<A::Event>) -> Self {
+        match A::apply_all(&initial, &events) {
+            Ok(state) => AggregateTest { state },
+            Err(e) => panic!("Failed to apply the given events: {}", e),
+        }
+    }
+
+    /// Handles a command against the state built from the given events
+    pub fn when(self, cmd: A::Command) -> AggregateTestOutcome<A> {
+        let outcome = A::handle_command(&self.state, &cmd);
+        AggregateTestOutcome {
+            state: self.state,
+            outcome,
+        }
+    }
+}
+
+impl<A: Aggregate> AggregateTestOutcome<A> {
+    /// Asserts that the command produced exactly the expected events, compared by event type
+    /// and JSON payload. On success, returns the state after applying the produced events.
+    pub fn then_expect_events(self, expected: Vec<A::Event>) -> A::State {
+        let actual = match self.outcome {
+            Ok(evts) => evts,
+            Err(e) => panic!(
+                "Expected {} event(s) but the command failed: {:?}",
+                expected.len(),
+                e.kind
+            ),
+        };
+        if let Some(diff) = diff_events(&expected, &actual) {
+            panic!(
+                "Produced events did not match the expected events:\n{}",
+                diff
+            );
+        }
+        match A::apply_all(&self.state, &actual) {
+            Ok(state) => state,
+            Err(e) => panic!("Failed to apply the produced events: {}", e),
+        }
+    }
+
+    /// Asserts that the command was rejected with the expected kind of error
+    pub fn then_expect_error(self, expected: Kind) {
+        match self.outcome {
+            Ok(evts) => panic!(
+                "Expected error {:?} but the command produced {} event(s):\n{}",
+                expected,
+                evts.len(),
+                evts.iter()
+                    .map(|evt| format!("  {}", describe(evt)))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+            Err(e) => assert!(
+                e.kind == expected,
+                "Expected error {:?} but got {:?}",
+                expected,
+                e.kind
+            ),
+        }
+    }
+}
+
+fn describe<E: Event>(evt: &E) -> String {
+    let payload = serde_json::to_value(evt).unwrap_or(Value::Null);
+    format!("{} {}", evt.event_type(), payload)
+}
+
+/// Renders a line-by-line diff of two event lists, or `None` if they are equivalent
+fn diff_events<E: Event>(expected: &[E], actual: &[E]) -> Option<String> {
+    let expected: Vec<String> = expected.iter().map(describe).collect();
+    let actual: Vec<String> = actual.iter().map(describe).collect();
+    if expected == actual {
+        return None;
+    }
+
+    let mut diff = String::new();
+    for idx in 0..expected.len().max(actual.len()) {
+        match (expected.get(idx), actual.get(idx)) {
+            (Some(e), Some(a)) if e == a => {
+                let _ = writeln!(diff, "    [{}] {}", idx, e);
+            }
+            (e, a) => {
+                if let Some(e) = e {
+                    let _ = writeln!(diff, "  - [{}] {}", idx, e);
+                }
+                if let Some(a) = a {
+                    let _ = writeln!(diff, "  + [{}] {}", idx, a);
+                }
+            }
+        }
+    }
+    Some(diff)
+}
@@ -0,0 +1,765 @@
+//! In-process fake of the eventstore.org HTTP API
+//!
+//! `MockEventStoreServer` listens on a local port and serves the subset of the
+//! [eventstore.org HTTP API](https://eventstore.org/docs/http-api/) used by
+//! `OrgEventStore`. Streams are kept in memory, so tests can exercise `OrgEventStore`,
+//! including its error paths, without an external service. The fake supports:
+//!
+//! * posting events to a stream, honoring the `ES-ExpectedVersion` header
+//! * reading a stream's Atom feed, forwards or backwards, with embedded bodies
+//! * soft and hard stream deletion, answering `404 Not Found` and `410 Gone` respectively
+//! * reading and writing stream metadata, honoring the `$tb` (truncate before), `$maxCount`
+//!   and `$maxAge` settings
+//! * reading the `$all` feed forwards from a log position, for admin users only, with the
+//!   position of the next page in the `previous` link as the only position given
+//! * reading `$ce-{category}` streams, as if the `$by_category` projection were running
+//! * injected failures, so that a test can make the next requests fail with a given status
+//!
+//! ```rust,no_run
+//!# extern crate eventsourcing;
+//!# use eventsourcing::testing::orgmock::MockEventStoreServer;
+//!# use eventsourcing::prelude::*;
+//!let server = MockEventStoreServer::start();
+//!let store = server.store();
+//!server.fail_requests(503, 1);
+//!assert!(store.read_stream("ogre").is_err());
+//!assert!(store.read_stream("ogre").unwrap().is_empty());
+//! ```
+use super::super::eventstore::OrgEventStore;
+use chrono::prelude::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The default admin user of the real server
+const ADMIN: &str = "admin";
+const ADMIN_PASSWORD: &str = "changeit";
+
+/// An event as recorded by the mock server
+#[derive(Debug, Clone)]
+pub struct MockEvent {
+    pub event_id: String,
+    pub event_type: String,
+    pub event_number: u64,
+    /// The index of the event in the server's log, which counts every event written to any
+    /// stream
+    pub position: u64,
+    pub data: Value,
+    pub metadata: Value,
+    pub updated: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct MockStream {
+    events: Vec<MockEvent>,
+    next_number: u64,
+    soft_deleted: bool,
+    hard_deleted: bool,
+    metadata: Value,
+}
+
+impl MockStream {
+    /// The number of the last event written to the stream, or -1 if it has none
+    fn current_version(&self) -> i64 {
+        self.next_number as i64 - 1
+    }
+
+    /// The number of the first event that isn't truncated
+    fn truncated_before(&self) -> u64 {
+        self.metadata["$tb"].as_u64().unwrap_or(0)
+    }
+
+    /// The events that are neither truncated nor past the `$maxCount` and `$maxAge`
+    /// retention rules
+    fn visible_events(&self) -> impl DoubleEndedIterator<Item = &MockEvent> {
+        let truncated_before = self.truncated_before();
+        let max_count = self.metadata["$maxCount"].as_u64();
+        let max_age = self.metadata["$maxAge"]
+            .as_i64()
+            .map(chrono::Duration::seconds);
+        let next_number = self.next_number;
+        let now = Utc::now();
+        self.events.iter().filter(move |evt| {
+            evt.event_number >= truncated_before
+                && max_count
+                    .into_iter()
+                    .all(|count| evt.event_number + count >= next_number)
+                && max_age.into_iter().all(|age| evt.updated + age >= now)
+        })
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    streams: HashMap<String, MockStream>,
+    next_position: u64,
+    failures: Vec<u16>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    reason: Option<&'static str>,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16) -> Response {
+        Response {
+            status,
+            reason: None,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    fn json(status: u16, body: &Value) -> Response {
+        Response {
+            status,
+            reason: None,
+            headers: vec![(
+                "Content-Type".to_owned(),
+                "application/vnd.eventstore.atom+json; charset=utf-8".to_owned(),
+            )],
+            body: body.to_string(),
+        }
+    }
+
+    fn with_reason(mut self, reason: &'static str) -> Response {
+        self.reason = Some(reason);
+        self
+    }
+
+    fn with_header(mut self, name: &str, value: String) -> Response {
+        self.headers.push((name.to_owned(), value));
+        self
+    }
+}
+
+/// A fake eventstore.org server running on a background thread. The server lives
+/// until the end of the process.
+pub struct MockEventStoreServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockEventStoreServer {
+    /// Starts a new server on a free port on the loopback interface
+    pub fn start() -> MockEventStoreServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock server");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = server_state.clone();
+                thread::spawn(move || {
+                    let _ = serve_connection(stream, &state);
+                });
+            }
+        });
+        MockEventStoreServer { addr, state }
+    }
+
+    /// The host name clients should connect to
+    pub fn host(&self) -> String {
+        self.addr.ip().to_string()
+    }
+
+    /// The port the server is listening on
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Creates an event store client pointing at this server, with the default admin
+    /// credentials
+    pub fn store(&self) -> OrgEventStore {
+        OrgEventStore::new(&self.host(), self.port()).with_credentials(ADMIN, ADMIN_PASSWORD)
+    }
+
+    /// Returns a copy of the events recorded in the given stream
+    pub fn events(&self, stream: &str) -> Vec<MockEvent> {
+        let state = self.state.lock().unwrap();
+        state
+            .streams
+            .get(stream)
+            .map(|s| s.events.clone())
+            .unwrap_or_default()
+    }
+
+    /// Makes the next `times` requests, of any kind, fail with the given HTTP status
+    pub fn fail_requests(&self, status: u16, times: usize) {
+        let mut state = self.state.lock().unwrap();
+        let failures = state.failures.len() + times;
+        state.failures.resize(failures, status);
+    }
+
+    /// Deletes a stream the way `DELETE /streams/{stream}` would. Soft deleted streams
+    /// answer `404 Not Found` until they are written to again, hard deleted streams
+    /// answer `410 Gone` forever.
+    pub fn delete_stream(&self, stream: &str, hard: bool) {
+        let mut state = self.state.lock().unwrap();
+        delete(&mut state, stream, hard);
+    }
+}
+
+fn serve_connection(stream: TcpStream, state: &Mutex<MockState>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    loop {
+        let request = match read_request(&mut reader)? {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+        let close = request
+            .headers
+            .get("connection")
+            .map(|v| v.eq_ignore_ascii_case("close"))
+            .unwrap_or(false);
+        let response = {
+            let mut state = state.lock().unwrap();
+            route(&mut state, &request, &base_url(&request))
+        };
+        write_response(&mut writer, &response)?;
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_owned();
+    let target = parts.next().unwrap_or("/");
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(idx) = line.find(':') {
+            headers.insert(
+                line[..idx].trim().to_ascii_lowercase(),
+                line[idx + 1..].trim().to_owned(),
+            );
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let mut target_parts = target.splitn(2, '?');
+    let path = target_parts.next().unwrap_or("/").to_owned();
+    let query = target_parts
+        .next()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut kv = pair.splitn(2, '=');
+            (
+                kv.next().unwrap_or("").to_owned(),
+                kv.next().unwrap_or("").to_owned(),
+            )
+        })
+        .collect();
+
+    Ok(Some(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    }))
+}
+
+fn write_response<W: Write>(writer: &mut W, response: &Response) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\n",
+        response.status,
+        response.reason.unwrap_or_else(|| reason(response.status)),
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(response.body.as_bytes())?;
+    writer.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        410 => "Deleted",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn base_url(request: &Request) -> String {
+    format!(
+        "http://{}",
+        request
+            .headers
+            .get("host")
+            .map(String::as_str)
+            .unwrap_or("localhost")
+    )
+}
+
+fn route(state: &mut MockState, request: &Request, base: &str) -> Response {
+    if !state.failures.is_empty() {
+        let status = state.failures.remove(0);
+        return Response::new(status);
+    }
+
+    let path = request.path.replace("%24", "$");
+    let segments: Vec<&str> = path
+        .trim_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["streams", stream]) => post_events(state, stream, request),
+        ("POST", ["streams", stream, "metadata"]) => post_metadata(state, stream, request),
+        ("GET", ["streams", stream, "metadata"]) => match state.streams.get(*stream) {
+            Some(s) if s.hard_deleted => Response::new(410),
+            Some(s) if s.metadata.is_object() => Response::json(200, &s.metadata),
+            _ => Response::json(200, &json!({})),
+        },
+        ("DELETE", ["streams", stream]) => {
+            let hard = header_is_true(request, "es-harddelete");
+            match state.streams.get(*stream) {
+                Some(s) if s.hard_deleted => Response::new(410),
+                Some(s) if !s.soft_deleted => {
+                    delete(state, stream, hard);
+                    Response::new(204)
+                }
+                _ => Response::new(404),
+            }
+        }
+        ("GET", ["streams", stream]) | ("GET", ["streams", stream, "head", "backward", _]) => {
+            let count = segments
+                .get(4)
+                .and_then(|c| c.parse::<u64>().ok())
+                .unwrap_or(PAGE_SIZE);
+            let head = state
+                .streams
+                .get(*stream)
+                .map(|s| s.next_number)
+                .unwrap_or(0);
+            let start = head.saturating_sub(count);
+            read_page(state, stream, start, count, request, base)
+        }
+        ("GET", ["streams", "$all", ..]) if !is_admin(request) => Response::new(401),
+        ("GET", ["streams", "$all", position, "forward", count]) => {
+            match (parse_position(position), count.parse::<u64>()) {
+                (Some(position), Ok(count)) => read_all_page(state, position, count, base),
+                _ => Response::new(400),
+            }
+        }
+        ("GET", ["streams", stream, start, "forward", count]) => {
+            match (start.parse::<u64>(), count.parse::<u64>()) {
+                (Ok(start), Ok(count)) => read_page(state, stream, start, count, request, base),
+                _ => Response::new(400),
+            }
+        }
+        ("GET", ["streams", stream, start, "backward", count]) => {
+            match (start.parse::<u64>(), count.parse::<u64>()) {
+                (Ok(start), Ok(count)) => {
+                    let first = (start + 1).saturating_sub(count);
+                    read_page(state, stream, first, start + 1 - first, request, base)
+                }
+                _ => Response::new(400),
+            }
+        }
+        ("GET", ["streams", stream, number]) => match number.parse::<u64>() {
+            Ok(number) => read_event(state, stream, number, base),
+            Err(_) => Response::new(400),
+        },
+        (_, ["streams", ..]) => Response::new(405),
+        _ => Response::new(404),
+    }
+}
+
+const PAGE_SIZE: u64 = 20;
+
+/// Whether the request carries the default admin credentials
+fn is_admin(request: &Request) -> bool {
+    let credentials = format!("{}:{}", ADMIN, ADMIN_PASSWORD);
+    request.headers.get("authorization") == Some(&format!("Basic {}", base64::encode(credentials)))
+}
+
+fn header_is_true(request: &Request, name: &str) -> bool {
+    request
+        .headers
+        .get(name)
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+fn delete(state: &mut MockState, stream: &str, hard: bool) {
+    let s = state.streams.entry(stream.to_owned()).or_default();
+    s.events.clear();
+    if hard {
+        s.hard_deleted = true;
+    } else {
+        s.soft_deleted = true;
+    }
+}
+
+/// Checks the `ES-ExpectedVersion` header: -2 means any version, -1 that the stream must
+/// not exist, -4 that it must exist, and any other number the exact current version.
+fn version_matches(expected: i64, stream: Option<&MockStream>) -> bool {
+    let exists = stream
+        .map(|s| !s.soft_deleted && s.next_number > 0)
+        .unwrap_or(false);
+    let current = stream.map(MockStream::current_version).unwrap_or(-1);
+    match expected {
+        -2 => true,
+        -1 => !exists,
+        -4 => exists,
+        n => exists && n == current,
+    }
+}
+
+fn post_events(state: &mut MockState, stream: &str, request: &Request) -> Response {
+    if state
+        .streams
+        .get(stream)
+        .map(|s| s.hard_deleted)
+        .unwrap_or(false)
+    {
+        return Response::new(410);
+    }
+    let posted: Vec<Value> = match serde_json::from_slice(&request.body) {
+        Ok(posted) => posted,
+        Err(_) => return Response::new(400),
+    };
+    if let Some(expected) = request.headers.get("es-expectedversion") {
+        let expected = match expected.parse::<i64>() {
+            Ok(expected) => expected,
+            Err(_) => return Response::new(400),
+        };
+        let existing = state.streams.get(stream);
+        if !version_matches(expected, existing) {
+            let current = existing.map(MockStream::current_version).unwrap_or(-1);
+            return Response::new(400)
+                .with_reason("Wrong expected EventNumber")
+                .with_header("ES-CurrentVersion", current.to_string());
+        }
+    }
+
+    if posted
+        .iter()
+        .any(|item| item["eventId"].as_str().is_none() || item["eventType"].as_str().is_none())
+    {
+        return Response::new(400);
+    }
+
+    let s = state.streams.entry(stream.to_owned()).or_default();
+    s.soft_deleted = false;
+    let first = s.next_number;
+    for item in posted {
+        let event_id = item["eventId"].as_str().unwrap_or_default().to_owned();
+        let event_type = item["eventType"].as_str().unwrap_or_default().to_owned();
+        s.events.push(MockEvent {
+            event_id,
+            event_type,
+            event_number: s.next_number,
+            position: state.next_position,
+            data: item["data"].clone(),
+            metadata: item["metadata"].clone(),
+            updated: Utc::now(),
+        });
+        s.next_number += 1;
+        state.next_position += 1;
+    }
+    Response::new(201).with_header("Location", format!("/streams/{}/{}", stream, first))
+}
+
+/// Replaces a stream's metadata with the data of the last posted metadata event
+fn post_metadata(state: &mut MockState, stream: &str, request: &Request) -> Response {
+    let posted: Vec<Value> = match serde_json::from_slice(&request.body) {
+        Ok(posted) => posted,
+        Err(_) => return Response::new(400),
+    };
+    let metadata = match posted.last() {
+        Some(item) if item["data"].is_object() => item["data"].clone(),
+        _ => return Response::new(400),
+    };
+    let s = state.streams.entry(stream.to_owned()).or_default();
+    if s.hard_deleted {
+        return Response::new(410);
+    }
+    s.metadata = metadata;
+    Response::new(201)
+}
+
+/// Looks up a readable stream, or the response explaining why it can't be read
+fn readable<'a>(
+    state: &'a MockState,
+    stream: &str,
+) -> std::result::Result<&'a MockStream, Response> {
+    match state.streams.get(stream) {
+        Some(s) if s.hard_deleted => Err(Response::new(410)),
+        Some(s) if !s.soft_deleted => Ok(s),
+        _ => Err(Response::new(404)),
+    }
+}
+
+fn read_event(state: &MockState, stream: &str, number: u64, base: &str) -> Response {
+    let s = match readable(state, stream) {
+        Ok(s) => s,
+        Err(response) => return response,
+    };
+    let stream_url = format!("{}/streams/{}", base, stream);
+    match s.visible_events().find(|evt| evt.event_number == number) {
+        Some(evt) => Response::json(
+            200,
+            &atom_entry(evt, stream, &stream_url, Some("tryharder")),
+        ),
+        None => Response::new(404),
+    }
+}
+
+fn read_page(
+    state: &MockState,
+    stream: &str,
+    start: u64,
+    count: u64,
+    request: &Request,
+    base: &str,
+) -> Response {
+    if let Some(category) = stream.strip_prefix("$ce-") {
+        return read_category_page(state, category, start, count, request, base);
+    }
+    let s = match readable(state, stream) {
+        Ok(s) => s,
+        Err(response) => return response,
+    };
+    let embed = request.query.get("embed").map(String::as_str);
+    let stream_url = format!("{}/streams/{}", base, stream);
+    let end = start.saturating_add(count);
+    let entries: Vec<Value> = s
+        .visible_events()
+        .filter(|evt| evt.event_number >= start && evt.event_number < end)
+        .rev()
+        .map(|evt| atom_entry(evt, stream, &stream_url, embed))
+        .collect();
+
+    let feed = json!({
+        "title": format!("Event stream '{}'", stream),
+        "id": stream_url,
+        "updated": Utc::now().to_rfc3339(),
+        "streamId": stream,
+        "headOfStream": end >= s.next_number,
+        "links": [
+            { "uri": stream_url, "relation": "self" },
+            { "uri": format!("{}/head/backward/{}", stream_url, count), "relation": "first" },
+            { "uri": format!("{}/0/forward/{}", stream_url, count), "relation": "last" },
+            { "uri": format!("{}/{}/forward/{}", stream_url, end, count), "relation": "previous" }
+        ],
+        "entries": entries,
+    });
+    Response::json(200, &feed)
+}
+
+/// Reads a page of the `$ce-` stream linking to the visible events of every live stream
+/// named `{category}-{id}`, numbered in the order in which they were written
+fn read_category_page(
+    state: &MockState,
+    category: &str,
+    start: u64,
+    count: u64,
+    request: &Request,
+    base: &str,
+) -> Response {
+    let category_stream = format!("$ce-{}", category);
+    let mut events: Vec<(&str, &MockEvent)> = state
+        .streams
+        .iter()
+        .filter(|(name, s)| {
+            !s.hard_deleted
+                && !s.soft_deleted
+                && name.split_once('-').is_some_and(|(c, _)| c == category)
+        })
+        .flat_map(|(name, s)| s.visible_events().map(move |evt| (name.as_str(), evt)))
+        .collect();
+    if events.is_empty() {
+        return Response::new(404);
+    }
+    events.sort_by_key(|(_, evt)| evt.position);
+
+    let embed = request.query.get("embed").map(String::as_str);
+    let category_url = format!("{}/streams/{}", base, category_stream);
+    let end = start.saturating_add(count);
+    let entries: Vec<Value> = events
+        .iter()
+        .enumerate()
+        .filter(|(number, _)| *number as u64 >= start && (*number as u64) < end)
+        .rev()
+        .map(|(number, (stream, evt))| {
+            let stream_url = format!("{}/streams/{}", base, stream);
+            let mut entry = atom_entry(evt, stream, &stream_url, embed);
+            entry["positionEventNumber"] = json!(number);
+            entry["positionStreamId"] = json!(category_stream);
+            entry
+        })
+        .collect();
+
+    let feed = json!({
+        "title": format!("Event stream '{}'", category_stream),
+        "id": category_url,
+        "updated": Utc::now().to_rfc3339(),
+        "streamId": category_stream,
+        "headOfStream": end >= events.len() as u64,
+        "links": [
+            { "uri": category_url, "relation": "self" },
+            {
+                "uri": format!("{}/{}/forward/{}", category_url, end, count),
+                "relation": "previous"
+            }
+        ],
+        "entries": entries,
+    });
+    Response::json(200, &feed)
+}
+
+/// The distance between the log positions of consecutive events
+const LOG_RECORD_SIZE: u64 = 128;
+
+/// The commit and prepare positions of the event with the given index in the log. The
+/// prepare record of an event comes before its commit record, as with the real server.
+fn log_position(index: u64) -> (u64, u64) {
+    let prepare = index * LOG_RECORD_SIZE;
+    (prepare + LOG_RECORD_SIZE / 2, prepare)
+}
+
+/// Parses a `$all` position into its commit and prepare positions
+fn parse_position(position: &str) -> Option<(u64, u64)> {
+    if position.len() != 32 || !position.is_ascii() {
+        return None;
+    }
+    let commit = u64::from_str_radix(&position[..16], 16).ok()?;
+    let prepare = u64::from_str_radix(&position[16..], 16).ok()?;
+    Some((commit, prepare))
+}
+
+fn format_position((commit, prepare): (u64, u64)) -> String {
+    format!("{:016X}{:016X}", commit, prepare)
+}
+
+/// Reads the visible events of every live stream from the given log position on. As with
+/// the real server, entries don't give their positions; the `previous` link reads on from
+/// the position after the page.
+fn read_all_page(state: &MockState, from: (u64, u64), count: u64, base: &str) -> Response {
+    let mut events: Vec<(&str, &MockEvent)> = state
+        .streams
+        .iter()
+        .filter(|(_, s)| !s.hard_deleted && !s.soft_deleted)
+        .flat_map(|(name, s)| s.visible_events().map(move |evt| (name.as_str(), evt)))
+        .filter(|(_, evt)| log_position(evt.position) >= from)
+        .collect();
+    events.sort_by_key(|(_, evt)| evt.position);
+    let head = events.len() as u64 <= count;
+    events.truncate(count as usize);
+    let next = events
+        .last()
+        .map(|(_, evt)| log_position(evt.position + 1))
+        .unwrap_or(from);
+
+    let all_url = format!("{}/streams/%24all", base);
+    let entries: Vec<Value> = events
+        .iter()
+        .rev()
+        .map(|(stream, evt)| {
+            let stream_url = format!("{}/streams/{}", base, stream);
+            atom_entry(evt, stream, &stream_url, Some("tryharder"))
+        })
+        .collect();
+
+    let feed = json!({
+        "title": "All events",
+        "id": all_url,
+        "updated": Utc::now().to_rfc3339(),
+        "headOfStream": head,
+        "links": [
+            { "uri": all_url, "relation": "self" },
+            {
+                "uri": format!("{}/{}/forward/{}", all_url, format_position(next), count),
+                "relation": "previous"
+            }
+        ],
+        "entries": entries,
+    });
+    Response::json(200, &feed)
+}
+
+fn atom_entry(evt: &MockEvent, stream: &str, stream_url: &str, embed: Option<&str>) -> Value {
+    let uri = format!("{}/{}", stream_url, evt.event_number);
+    let mut entry = json!({
+        "title": format!("{}@{}", evt.event_number, stream),
+        "id": uri,
+        "updated": evt.updated.to_rfc3339(),
+        "summary": evt.event_type,
+        "eventId": evt.event_id,
+        "eventType": evt.event_type,
+        "eventNumber": evt.event_number,
+        "streamId": stream,
+        "isJson": true,
+        "isMetaData": false,
+        "positionEventNumber": evt.event_number,
+        "positionStreamId": stream,
+        "links": [
+            { "uri": uri, "relation": "edit" },
+            { "uri": uri, "relation": "alternate" }
+        ],
+    });
+    match embed {
+        Some("tryharder") => {
+            entry["data"] = evt.data.clone();
+            entry["metaData"] = evt.metadata.clone();
+        }
+        Some("body") => {
+            entry["data"] = Value::String(evt.data.to_string());
+            entry["metaData"] = Value::String(evt.metadata.to_string());
+        }
+        _ => {}
+    }
+    entry
+}
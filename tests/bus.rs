@@ -0,0 +1,213 @@
+#![cfg(feature = "eventstore")]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::bus::{CommandBus, Middleware};
+use eventsourcing::cloudevents::CloudEvent;
+use eventsourcing::eventstore::{EventStore, MemoryEventStore};
+use eventsourcing::{prelude::*, Error, Result};
+use std::sync::{Arc, Mutex};
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Command)]
+enum AccountCommand {
+    Deposit(#[aggregate_id] String, u32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/bus")]
+enum AccountEvent {
+    FundsDeposited(u32),
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct AccountState {
+    balance: u32,
+    generation: u64,
+}
+
+struct Account;
+
+impl Aggregate for Account {
+    type Event = AccountEvent;
+    type Command = AccountCommand;
+    type State = AccountState;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        let AccountEvent::FundsDeposited(amount) = *evt;
+        Ok(AccountState {
+            balance: state.balance + amount,
+            ..state.clone()
+        })
+    }
+
+    fn handle_command(state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        let AccountCommand::Deposit(_, amount) = *cmd;
+        if state.balance + amount > 1000 {
+            return Err(Error {
+                kind: Kind::CommandFailure("Deposit limit exceeded".to_owned()),
+            });
+        }
+        Ok(vec![AccountEvent::FundsDeposited(amount)])
+    }
+}
+
+#[derive(Command)]
+#[command_type("customer.register")]
+struct RegisterCustomer {
+    #[aggregate_id]
+    customer: String,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/bus")]
+struct CustomerRegistered {
+    name: String,
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct CustomerState {
+    generation: u64,
+}
+
+struct Customer;
+
+impl Aggregate for Customer {
+    type Event = CustomerRegistered;
+    type Command = RegisterCustomer;
+    type State = CustomerState;
+
+    fn apply_event(state: &Self::State, _evt: &Self::Event) -> Result<Self::State> {
+        Ok(state.clone())
+    }
+
+    fn handle_command(_state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        Ok(vec![CustomerRegistered {
+            name: cmd.name.clone(),
+        }])
+    }
+}
+
+#[derive(Command)]
+struct Unrouted {
+    #[aggregate_id]
+    id: u32,
+}
+
+/// Records every command and its outcome
+#[derive(Clone, Default)]
+struct AuditLog(Arc<Mutex<Vec<String>>>);
+
+impl Middleware for AuditLog {
+    fn after(&self, cmd: &dyn Command, outcome: &Result<Vec<CloudEvent>>) {
+        let outcome = match outcome {
+            Ok(evts) => format!("{} event(s)", evts.len()),
+            Err(e) => e.to_string(),
+        };
+        self.0.lock().unwrap().push(format!(
+            "{} {}: {}",
+            cmd.command_type(),
+            cmd.aggregate_id(),
+            outcome
+        ));
+    }
+}
+
+fn bus(accounts: Arc<MemoryEventStore>, customers: Arc<MemoryEventStore>) -> CommandBus {
+    let mut bus = CommandBus::new();
+    bus.register::<Account, _>(accounts)
+        .register::<Customer, _>(customers);
+    bus
+}
+
+fn deposit(account: &str, amount: u32) -> AccountCommand {
+    AccountCommand::Deposit(account.to_owned(), amount)
+}
+
+#[test]
+fn commands_are_routed_by_type() {
+    let accounts = Arc::new(MemoryEventStore::new());
+    let customers = Arc::new(MemoryEventStore::new());
+    let bus = bus(accounts.clone(), customers.clone());
+
+    bus.send(&deposit("acct-1", 100)).unwrap();
+    bus.send(&deposit("acct-1", 50)).unwrap();
+    let stored = bus
+        .send(&RegisterCustomer {
+            customer: "cust-1".to_owned(),
+            name: "Alice".to_owned(),
+        })
+        .unwrap();
+    assert_eq!(stored[0].event_type, "customerregistered");
+
+    assert_eq!(accounts.read_stream("acct-1").unwrap().len(), 2);
+    assert!(accounts.read_stream("cust-1").unwrap().is_empty());
+    assert_eq!(customers.read_stream("cust-1").unwrap().len(), 1);
+
+    // the state is loaded before every command
+    let err = bus.send(&deposit("acct-1", 900)).unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::CommandFailure("Deposit limit exceeded".to_owned())
+    );
+}
+
+#[test]
+fn unregistered_commands_fail() {
+    let bus = CommandBus::new();
+    let err = bus.send(&Unrouted { id: 1 }).unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::CommandFailure("No aggregate is registered for command unrouted".to_owned())
+    );
+}
+
+#[test]
+fn middleware_can_reject_and_observe_commands() {
+    let accounts = Arc::new(MemoryEventStore::new());
+    let mut bus = bus(accounts.clone(), Arc::new(MemoryEventStore::new()));
+    let log = AuditLog::default();
+    bus.add_middleware(|cmd: &dyn Command| {
+        if cmd.aggregate_id().starts_with("acct-") || cmd.aggregate_id().starts_with("cust-") {
+            Ok(())
+        } else {
+            Err(Error {
+                kind: Kind::ValidationFailure("Malformed aggregate ID".to_owned()),
+            })
+        }
+    })
+    .add_middleware(|cmd: &dyn Command| {
+        if cmd.aggregate_id() == "acct-frozen" {
+            Err(Error {
+                kind: Kind::CommandFailure("Not authorized".to_owned()),
+            })
+        } else {
+            Ok(())
+        }
+    })
+    .add_middleware(log.clone());
+
+    bus.send(&deposit("acct-1", 10)).unwrap();
+    assert!(bus.send(&deposit("savings", 10)).is_err());
+    assert!(bus.send(&deposit("acct-frozen", 10)).is_err());
+
+    assert!(accounts.read_stream("savings").unwrap().is_empty());
+    assert!(accounts.read_stream("acct-frozen").unwrap().is_empty());
+    assert_eq!(
+        *log.0.lock().unwrap(),
+        vec![
+            "accountcommand.deposit acct-1: 1 event(s)",
+            "accountcommand.deposit savings: Malformed aggregate ID",
+            "accountcommand.deposit acct-frozen: Not authorized",
+        ]
+    );
+}
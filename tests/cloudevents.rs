@@ -1,3 +1,4 @@
+#![cfg(feature = "eventstore")]
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -8,8 +9,11 @@ extern crate eventsourcing_derive;
 extern crate chrono;
 
 use chrono::prelude::*;
+use eventsourcing::cloudevents::TimeDimension;
+use eventsourcing::eventstore::MemoryEventStore;
 #[cfg(feature = "eventstore")]
 use eventsourcing::prelude::*;
+use std::convert::TryFrom;
 
 const DOMAIN_VERSION: &str = "1.0";
 
@@ -38,16 +42,19 @@ fn cloud_event_roundtrip() {
         event_type_version: "1.0".to_owned(),
         source: "events://test/source".to_owned(),
         event_id: "abc12345-1111".to_owned(),
-        event_time: Utc::now(),
+        event_time: Some(Utc::now()),
         content_type: "application/json".to_owned(),
+        data_schema: None,
+        subject: None,
         data: serde_json::from_str(&serde_json::to_string(&se).unwrap()).unwrap(),
+        extensions: Default::default(),
     };
 
     let s = serde_json::to_string(&ce).unwrap();
     let round_trip: CloudEvent = serde_json::from_str(&s).unwrap();
     let evtype = round_trip.event_type.clone();
     let evtype_ver = round_trip.event_type_version.clone();
-    let event2: TestEvent = round_trip.into();
+    let event2 = TestEvent::try_from(round_trip).unwrap();
 
     assert_eq!(evtype, "testevent.sample");
     assert_eq!(evtype_ver, DOMAIN_VERSION);
@@ -62,3 +69,253 @@ fn cloud_event_roundtrip() {
     assert_eq!(val2, tval2);
     assert_eq!(val3, tval3);
 }
+
+fn sample_event() -> CloudEvent {
+    CloudEvent::from(TestEvent::Sample {
+        val1: 1,
+        val2: 2,
+        val3: "hello".to_owned(),
+    })
+}
+
+fn assert_invalid(ce: &CloudEvent, fragment: &str) {
+    match ce.validate() {
+        Err(eventsourcing::Error {
+            kind: Kind::ValidationFailure(msg),
+        }) => assert!(msg.contains(fragment), "unexpected message: {}", msg),
+        other => panic!("expected validation failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn generated_events_are_valid() {
+    let ce = sample_event();
+    assert_eq!(ce.cloud_events_version, "1.0");
+    assert!(ce.validate().is_ok());
+}
+
+#[test]
+fn validate_rejects_bad_attributes() {
+    let mut ce = sample_event();
+    ce.cloud_events_version = "0.1".to_owned();
+    assert_invalid(&ce, "spec version");
+
+    let mut ce = sample_event();
+    ce.event_id = "".to_owned();
+    assert_invalid(&ce, "'id'");
+
+    let mut ce = sample_event();
+    ce.source = "not a uri".to_owned();
+    assert_invalid(&ce, "'source'");
+
+    let mut ce = sample_event();
+    ce.source = "1http://example.com".to_owned();
+    assert_invalid(&ce, "'source'");
+
+    let mut ce = sample_event();
+    ce.content_type = "json".to_owned();
+    assert_invalid(&ce, "'datacontenttype'");
+
+    let mut ce = sample_event();
+    ce.extensions
+        .insert("Bad_Name".to_owned(), serde_json::Value::Bool(true));
+    assert_invalid(&ce, "Bad_Name");
+
+    for name in &["id", "type", "data", "subject"] {
+        let mut ce = sample_event();
+        ce.extensions
+            .insert((*name).to_owned(), serde_json::Value::Bool(true));
+        assert_invalid(&ce, "context attribute");
+    }
+}
+
+#[test]
+fn validate_accepts_relative_sources_and_parameters() {
+    let mut ce = sample_event();
+    ce.source = "/sensors/tn-1234567/alerts".to_owned();
+    ce.content_type = "application/json; charset=utf-8".to_owned();
+    ce.data_schema = Some("https://example.com/schemas/sample.json".to_owned());
+    assert!(ce.validate().is_ok());
+}
+
+#[test]
+fn reads_v1_json_with_extensions() {
+    let json = r#"{
+        "specversion": "1.0",
+        "type": "com.example.someevent",
+        "source": "/mycontext",
+        "id": "A234-1234-1234",
+        "time": "2018-04-05T17:31:00Z",
+        "comexampleextension1": "value",
+        "datacontenttype": "application/json",
+        "data": { "appinfoA": "abc" }
+    }"#;
+    let ce = CloudEvent::from_json(json).unwrap();
+    assert_eq!(ce.event_type, "com.example.someevent");
+    assert_eq!(ce.event_type_version, "");
+    assert_eq!(ce.data["appinfoA"], "abc");
+    assert_eq!(ce.extensions["comexampleextension1"], "value");
+
+    let round_trip = serde_json::to_value(&ce).unwrap();
+    assert_eq!(round_trip["comexampleextension1"], "value");
+}
+
+#[test]
+fn reads_v03_json_and_normalizes() {
+    let json = r#"{
+        "specversion": "0.3",
+        "type": "testevent.sample",
+        "source": "events://test/source",
+        "id": "abc12345-1111",
+        "time": "2018-04-05T17:31:00+02:00",
+        "schemaurl": "https://example.com/schemas/sample.json",
+        "datacontenttype": "application/json",
+        "datacontentencoding": "base64",
+        "data": "eyJTYW1wbGUiOnsidmFsMSI6MSwidmFsMiI6MiwidmFsMyI6ImhlbGxvIn19"
+    }"#;
+    let ce = CloudEvent::from_json(json).unwrap();
+    assert_eq!(ce.cloud_events_version, "1.0");
+    assert_eq!(
+        ce.data_schema.as_deref(),
+        Some("https://example.com/schemas/sample.json")
+    );
+    assert!(ce.extensions.is_empty());
+    assert_eq!(ce.data["Sample"]["val3"], "hello");
+    assert_eq!(ce.event_time.map(|time| time.hour()), Some(15));
+}
+
+#[test]
+fn reads_events_without_a_time() {
+    let untimed = r#"{"specversion": "1.0", "type": "t", "source": "/s", "id": "1"}"#;
+    let ce = CloudEvent::from_json(untimed).unwrap();
+    assert_eq!(ce.event_time, None);
+    assert_eq!(ce.time(TimeDimension::Effective), None);
+
+    // reading the same document twice yields the same event
+    let json = serde_json::to_value(&ce).unwrap();
+    assert!(json.get("time").is_none());
+    assert_eq!(
+        serde_json::to_value(CloudEvent::from_json(untimed).unwrap()).unwrap(),
+        json
+    );
+}
+
+#[test]
+fn readers_reject_invalid_json() {
+    let unsupported = r#"{"specversion": "0.1", "type": "t", "source": "/s", "id": "1", "time": "2018-04-05T17:31:00Z"}"#;
+    assert!(CloudEvent::from_json(unsupported).is_err());
+
+    let missing_id =
+        r#"{"specversion": "1.0", "type": "t", "source": "/s", "time": "2018-04-05T17:31:00Z"}"#;
+    assert!(CloudEvent::from_json(missing_id).is_err());
+
+    let bad_time =
+        r#"{"specversion": "1.0", "type": "t", "source": "/s", "id": "1", "time": "yesterday"}"#;
+    match CloudEvent::from_json(bad_time) {
+        Err(e) => assert!(e.to_string().contains("RFC 3339")),
+        Ok(_) => panic!("expected an invalid time to be rejected"),
+    }
+
+    let binary = r#"{"specversion": "1.0", "type": "t", "source": "/s", "id": "1", "time": "2018-04-05T17:31:00Z", "datacontenttype": "image/png", "data_base64": "AAAA"}"#;
+    assert!(CloudEvent::from_json(binary).is_err());
+}
+
+#[derive(Serialize, Deserialize, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/integration")]
+enum LedgerEvent {
+    EntryCorrected {
+        amount: i64,
+        #[effective_time]
+        effective: DateTime<Utc>,
+    },
+    EntryRecorded {
+        amount: i64,
+    },
+}
+
+#[test]
+fn effective_time_is_an_extension() {
+    let march = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+    let ce: CloudEvent = LedgerEvent::EntryCorrected {
+        amount: -5,
+        effective: march,
+    }
+    .into();
+    assert_eq!(ce.extensions["effectivetime"], "2025-03-01T00:00:00Z");
+    assert_eq!(ce.effective_time(), Some(march));
+    assert_eq!(ce.time(TimeDimension::Effective), Some(march));
+    assert_eq!(ce.time(TimeDimension::Recorded), ce.event_time);
+    assert!(ce.validate().is_ok());
+
+    let recorded: CloudEvent = LedgerEvent::EntryRecorded { amount: 1 }.into();
+    assert_eq!(recorded.effective_time(), None);
+    assert_eq!(recorded.time(TimeDimension::Effective), recorded.event_time);
+    let backdated = recorded.with_effective_time(march);
+    assert_eq!(backdated.effective_time(), Some(march));
+
+    let read = CloudEvent::from_json(&serde_json::to_string(&backdated).unwrap()).unwrap();
+    assert_eq!(read.effective_time(), Some(march));
+
+    let mut invalid = backdated;
+    invalid
+        .extensions
+        .insert("effectivetime".to_owned(), "last week".into());
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn stores_filter_ranges_by_either_time() {
+    let store = MemoryEventStore::new();
+    let march = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+    let before = Utc::now();
+    store
+        .append(LedgerEvent::EntryRecorded { amount: 1 }, "ledger")
+        .unwrap();
+    store
+        .append(
+            LedgerEvent::EntryCorrected {
+                amount: -5,
+                effective: march,
+            },
+            "ledger",
+        )
+        .unwrap();
+    let after = Utc::now();
+
+    let amounts = |evts: Vec<CloudEvent>| -> Vec<i64> {
+        evts.iter()
+            .map(|evt| {
+                evt.data
+                    .as_object()
+                    .and_then(|variant| variant.values().next())
+                    .and_then(|fields| fields["amount"].as_i64())
+                    .unwrap()
+            })
+            .collect()
+    };
+    let recorded = store.read_stream_range("ledger", before, after).unwrap();
+    assert_eq!(amounts(recorded), vec![1, -5]);
+    let effective_today = store
+        .read_stream_range_by("ledger", TimeDimension::Effective, before, after)
+        .unwrap();
+    assert_eq!(amounts(effective_today), vec![1]);
+    let effective_in_march = store
+        .read_stream_range_by(
+            "ledger",
+            TimeDimension::Effective,
+            march,
+            march + chrono::Duration::days(31),
+        )
+        .unwrap();
+    assert_eq!(amounts(effective_in_march), vec![-5]);
+    let corrections = store
+        .get_range_by(
+            "ledgerevent.entrycorrected",
+            TimeDimension::Effective,
+            march,
+            march,
+        )
+        .unwrap();
+    assert_eq!(amounts(corrections), vec![-5]);
+}
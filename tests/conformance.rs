@@ -0,0 +1,21 @@
+#![cfg(all(feature = "test-util", feature = "eventstore"))]
+#[macro_use]
+extern crate eventsourcing;
+
+mod memory {
+    use eventsourcing::eventstore::MemoryEventStore;
+
+    eventstore_conformance_tests!(MemoryEventStore::new());
+
+    #[test]
+    fn run_all_against_one_store() {
+        eventsourcing::testing::conformance::run_all(&MemoryEventStore::new());
+    }
+}
+
+#[cfg(feature = "orgeventstore")]
+mod orgeventstore {
+    use eventsourcing::testing::orgmock::MockEventStoreServer;
+
+    eventstore_conformance_tests!(MockEventStoreServer::start().store());
+}
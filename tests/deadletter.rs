@@ -0,0 +1,198 @@
+#![cfg(feature = "eventstore")]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::deadletter::{
+    DeadLetter, DeadLetterHandler, DeadLetterStore, Delivery, EventHandler, MemoryDeadLetterStore,
+};
+use eventsourcing::retry::RetryPolicy;
+use eventsourcing::{prelude::*, CloudEvent, Error, Result};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/deadletter")]
+enum OrderEvent {
+    OrderPlaced { total: u32 },
+}
+
+/// A projection of the total of all orders, which can be broken on purpose
+#[derive(Clone, Default)]
+struct Revenue {
+    total: Arc<AtomicU32>,
+    broken: Arc<AtomicBool>,
+}
+
+impl EventHandler for Revenue {
+    fn handle(&self, ce: &CloudEvent) -> Result<()> {
+        if self.broken.load(Ordering::SeqCst) {
+            return Err(Error {
+                kind: Kind::ApplicationFailure("Projection database unavailable".to_owned()),
+            });
+        }
+        let OrderEvent::OrderPlaced { total } =
+            OrderEvent::deserialize_by_type(&ce.event_type, &ce.data)?;
+        self.total.fetch_add(total, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn placed(total: u32) -> CloudEvent {
+    OrderEvent::OrderPlaced { total }.into()
+}
+
+fn handler(revenue: &Revenue) -> DeadLetterHandler<Revenue, MemoryDeadLetterStore> {
+    DeadLetterHandler::new(revenue.clone(), MemoryDeadLetterStore::new())
+        .with_retry_policy(RetryPolicy::immediate(2))
+}
+
+#[test]
+fn poison_events_are_dead_lettered() {
+    let revenue = Revenue::default();
+    let handler = handler(&revenue);
+    let mut poison = placed(0);
+    poison.data = json!({ "OrderPlaced": { "total": "lots" } });
+
+    assert_eq!(handler.deliver(&placed(10)).unwrap(), Delivery::Handled);
+    assert_eq!(handler.deliver(&poison).unwrap(), Delivery::DeadLettered);
+    handler.handle(&placed(5)).unwrap();
+
+    assert_eq!(revenue.total.load(Ordering::SeqCst), 15);
+    let letters = handler.dead_letters().list().unwrap();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].event.event_id, poison.event_id);
+    assert_eq!(letters[0].attempts, 2);
+    assert!(letters[0]
+        .error
+        .starts_with("Data for event type orderevent.orderplaced could not be deserialized"));
+}
+
+#[test]
+fn transient_failures_are_retried() {
+    let calls = AtomicU32::new(0);
+    let flaky = |_: &CloudEvent| {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            Err(Error {
+                kind: Kind::ApplicationFailure("Timed out".to_owned()),
+            })
+        } else {
+            Ok(())
+        }
+    };
+    let handler = DeadLetterHandler::new(flaky, MemoryDeadLetterStore::new())
+        .with_retry_policy(RetryPolicy::immediate(3));
+
+    assert_eq!(handler.deliver(&placed(1)).unwrap(), Delivery::Handled);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(handler.dead_letters().list().unwrap().is_empty());
+}
+
+#[test]
+fn dead_letters_can_be_replayed_once_fixed() {
+    let revenue = Revenue::default();
+    let handler = handler(&revenue);
+    revenue.broken.store(true, Ordering::SeqCst);
+    let first = placed(10);
+    let second = placed(20);
+    handler.deliver(&first).unwrap();
+    handler.deliver(&second).unwrap();
+
+    assert_eq!(
+        handler.replay(&first.event_id).unwrap(),
+        Delivery::DeadLettered
+    );
+    let letter = handler
+        .dead_letters()
+        .get(&first.event_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(letter.attempts, 4);
+    assert_eq!(letter.error, "Projection database unavailable");
+
+    revenue.broken.store(false, Ordering::SeqCst);
+    let replayed = handler.replay_all().unwrap();
+    assert_eq!(replayed.len(), 2);
+    assert_eq!(replayed[0].event_id, second.event_id);
+    assert!(replayed
+        .iter()
+        .all(|replay| *replay.outcome.as_ref().unwrap() == Delivery::Handled));
+    assert_eq!(revenue.total.load(Ordering::SeqCst), 30);
+    assert!(handler.dead_letters().list().unwrap().is_empty());
+}
+
+#[test]
+fn dead_letters_can_be_skipped() {
+    let revenue = Revenue::default();
+    let handler = handler(&revenue);
+    revenue.broken.store(true, Ordering::SeqCst);
+    let evt = placed(10);
+    handler.deliver(&evt).unwrap();
+
+    let skipped = handler.skip(&evt.event_id).unwrap();
+    assert_eq!(skipped.event.event_id, evt.event_id);
+    assert!(handler.dead_letters().list().unwrap().is_empty());
+    assert_eq!(revenue.total.load(Ordering::SeqCst), 0);
+
+    let err = handler.skip(&evt.event_id).unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::ValidationFailure(format!("No dead-lettered event with ID {}", evt.event_id))
+    );
+}
+
+/// A dead-letter store that can be made to fail to add letters
+#[derive(Default)]
+struct FlakyDeadLetterStore {
+    letters: MemoryDeadLetterStore,
+    failing: AtomicBool,
+}
+
+impl DeadLetterStore for FlakyDeadLetterStore {
+    fn add(&self, letter: DeadLetter) -> Result<()> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(Error {
+                kind: Kind::StoreFailure("Dead-letter store unavailable".to_owned()),
+            });
+        }
+        self.letters.add(letter)
+    }
+
+    fn list(&self) -> Result<Vec<DeadLetter>> {
+        self.letters.list()
+    }
+
+    fn remove(&self, event_id: &str) -> Result<Option<DeadLetter>> {
+        self.letters.remove(event_id)
+    }
+}
+
+#[test]
+fn failed_replays_keep_their_dead_letters() {
+    let revenue = Revenue::default();
+    let handler = DeadLetterHandler::new(revenue.clone(), FlakyDeadLetterStore::default())
+        .with_retry_policy(RetryPolicy::immediate(1));
+    revenue.broken.store(true, Ordering::SeqCst);
+    let first = placed(10);
+    handler.deliver(&first).unwrap();
+    handler.deliver(&placed(20)).unwrap();
+
+    handler.dead_letters().failing.store(true, Ordering::SeqCst);
+    assert!(handler.replay(&first.event_id).is_err());
+    let replayed = handler.replay_all().unwrap();
+    assert_eq!(replayed.len(), 2);
+    assert!(replayed.iter().all(|replay| replay.outcome.is_err()));
+    assert_eq!(handler.dead_letters().list().unwrap().len(), 2);
+
+    revenue.broken.store(false, Ordering::SeqCst);
+    assert_eq!(handler.replay(&first.event_id).unwrap(), Delivery::Handled);
+    assert_eq!(handler.dead_letters().list().unwrap().len(), 1);
+}
@@ -0,0 +1,708 @@
+extern crate chrono;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use chrono::prelude::*;
+use eventsourcing::{prelude::*, Result};
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+enum CounterEvent {
+    Incremented(u32),
+    Reset,
+}
+
+enum CounterCommand {
+    Increment(u32),
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct CounterState {
+    total: u32,
+    #[generation]
+    version: u64,
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct ConventionalState {
+    generation: u64,
+}
+
+struct Counter;
+
+impl Aggregate for Counter {
+    type Event = CounterEvent;
+    type Command = CounterCommand;
+    type State = CounterState;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        let total = match *evt {
+            CounterEvent::Incremented(n) => state.total + n,
+            CounterEvent::Reset => 0,
+        };
+        Ok(CounterState {
+            total,
+            ..state.clone()
+        })
+    }
+
+    fn handle_command(_state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        let CounterCommand::Increment(n) = *cmd;
+        Ok(vec![CounterEvent::Incremented(n)])
+    }
+}
+
+#[test]
+fn derived_state_exposes_marked_generation() {
+    let mut state = CounterState {
+        total: 0,
+        version: 4,
+    };
+    assert_eq!(state.generation(), 4);
+    state.set_generation(9);
+    assert_eq!(state.version, 9);
+}
+
+#[test]
+fn derived_state_defaults_to_generation_field() {
+    let mut state = ConventionalState::default();
+    state.set_generation(3);
+    assert_eq!(state.generation(), 3);
+}
+
+#[test]
+fn apply_bumps_generation() {
+    let state = Counter::apply(&CounterState::default(), &CounterEvent::Incremented(5)).unwrap();
+    assert_eq!(state.total, 5);
+    assert_eq!(state.generation(), 1);
+
+    // apply_event on its own leaves the generation alone
+    let untouched = Counter::apply_event(&state, &CounterEvent::Reset).unwrap();
+    assert_eq!(untouched.generation(), 1);
+}
+
+#[test]
+fn apply_all_bumps_generation_per_event() {
+    let events = Counter::handle_command(&CounterState::default(), &CounterCommand::Increment(2))
+        .unwrap()
+        .into_iter()
+        .chain(vec![CounterEvent::Reset, CounterEvent::Incremented(7)])
+        .collect::<Vec<_>>();
+    let state = Counter::apply_all(&CounterState::default(), &events).unwrap();
+    assert_eq!(state.total, 7);
+    assert_eq!(state.generation(), 3);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+#[event_naming("kebab-case")]
+enum AccountEvent {
+    FundsDeposited {
+        amount: u32,
+    },
+    #[event_type("com.acme.bank.overdrawn")]
+    #[event_type_version("2.0")]
+    #[event_source("events://github.com/pholactery/eventsourcing/tests/derive/overdraft")]
+    AccountOverdrawn,
+    #[event_alias("accountevent.fundsremoved")]
+    #[event_alias("account-event.funds-taken")]
+    FundsWithdrawn(u32),
+    HTTPNotified,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+#[event_type_prefix("com.acme.bank")]
+#[event_naming("snake_case")]
+enum LedgerEvent {
+    EntryPosted(u32),
+}
+
+#[test]
+fn naming_strategy_applies_to_enum_and_variant() {
+    let evt = AccountEvent::FundsDeposited { amount: 5 };
+    assert_eq!(evt.event_type(), "account-event.funds-deposited");
+    assert_eq!(evt.event_type_version(), "1.0");
+    assert_eq!(
+        AccountEvent::HTTPNotified.event_type(),
+        "account-event.http-notified"
+    );
+}
+
+#[test]
+fn variant_overrides_take_precedence() {
+    let evt = AccountEvent::AccountOverdrawn;
+    assert_eq!(evt.event_type(), "com.acme.bank.overdrawn");
+    assert_eq!(evt.event_type_version(), "2.0");
+    assert_eq!(
+        evt.event_source(),
+        "events://github.com/pholactery/eventsourcing/tests/derive/overdraft"
+    );
+    assert_eq!(
+        AccountEvent::FundsWithdrawn(1).event_source(),
+        "events://github.com/pholactery/eventsourcing/tests/derive"
+    );
+}
+
+#[test]
+fn prefix_replaces_enum_name() {
+    assert_eq!(
+        LedgerEvent::EntryPosted(1).event_type(),
+        "com.acme.bank.entry_posted"
+    );
+}
+
+#[cfg(feature = "orgeventstore")]
+#[test]
+fn aliases_are_accepted_when_reading_events() {
+    use std::convert::TryFrom;
+
+    let mut ce: CloudEvent = AccountEvent::FundsWithdrawn(12).into();
+    assert_eq!(ce.event_type, "account-event.funds-withdrawn");
+    assert_eq!(
+        AccountEvent::try_from(ce.clone()).unwrap(),
+        AccountEvent::FundsWithdrawn(12)
+    );
+
+    // an event persisted before the variant was renamed from FundsRemoved
+    ce.event_type = "accountevent.fundsremoved".to_owned();
+    ce.data = serde_json::json!({ "FundsRemoved": 7 });
+    assert_eq!(
+        AccountEvent::try_from(ce.clone()).unwrap(),
+        AccountEvent::FundsWithdrawn(7)
+    );
+
+    ce.event_type = "account-event.funds-taken".to_owned();
+    ce.data = serde_json::json!({ "FundsTaken": 3 });
+    assert_eq!(
+        AccountEvent::try_from(ce).unwrap(),
+        AccountEvent::FundsWithdrawn(3)
+    );
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+#[event_naming("kebab-case")]
+struct FundsDeposited {
+    amount: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version("2.0")]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive/withdrawals")]
+#[event_type("com.acme.bank.funds-withdrawn")]
+struct FundsWithdrawn(u32);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_delegate]
+enum LedgerEntry {
+    Deposited(FundsDeposited),
+    Withdrawn(FundsWithdrawn),
+}
+
+/// Events that are only ever written, never read back, need not deserialize
+#[derive(Serialize, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+#[event_no_catalog]
+enum AuditEvent {
+    Exported { rows: u32 },
+}
+
+#[derive(Serialize, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+#[event_no_catalog]
+struct AuditPurged;
+
+#[test]
+fn structs_are_single_type_events() {
+    let deposited = FundsDeposited { amount: 5 };
+    assert_eq!(deposited.event_type(), "funds-deposited");
+    assert_eq!(deposited.event_type_version(), "1.0");
+
+    let withdrawn = FundsWithdrawn(3);
+    assert_eq!(withdrawn.event_type(), "com.acme.bank.funds-withdrawn");
+    assert_eq!(withdrawn.event_type_version(), "2.0");
+    assert_eq!(
+        withdrawn.event_source(),
+        "events://github.com/pholactery/eventsourcing/tests/derive/withdrawals"
+    );
+}
+
+#[test]
+fn delegating_enum_reports_inner_event_metadata() {
+    let evt = LedgerEntry::Withdrawn(FundsWithdrawn(3));
+    assert_eq!(evt.event_type(), "com.acme.bank.funds-withdrawn");
+    assert_eq!(evt.event_type_version(), "2.0");
+
+    let evt = LedgerEntry::Deposited(FundsDeposited { amount: 5 });
+    assert_eq!(evt.event_type(), "funds-deposited");
+    assert_eq!(
+        evt.event_source(),
+        "events://github.com/pholactery/eventsourcing/tests/derive"
+    );
+}
+
+#[cfg(feature = "orgeventstore")]
+#[test]
+fn struct_events_round_trip_through_cloud_events() {
+    use std::convert::TryFrom;
+
+    let ce: CloudEvent = FundsDeposited { amount: 9 }.into();
+    assert_eq!(ce.event_type, "funds-deposited");
+    assert_eq!(
+        FundsDeposited::try_from(ce).unwrap(),
+        FundsDeposited { amount: 9 }
+    );
+
+    let ce: CloudEvent = LedgerEntry::Withdrawn(FundsWithdrawn(4)).into();
+    assert_eq!(ce.event_type, "com.acme.bank.funds-withdrawn");
+    assert_eq!(
+        LedgerEntry::try_from(ce).unwrap(),
+        LedgerEntry::Withdrawn(FundsWithdrawn(4))
+    );
+}
+
+#[test]
+fn catalog_lists_event_types() {
+    assert_eq!(
+        CounterEvent::EVENT_TYPES,
+        &["counterevent.incremented", "counterevent.reset"]
+    );
+    assert_eq!(
+        AccountEvent::EVENT_TYPES,
+        &[
+            "account-event.funds-deposited",
+            "com.acme.bank.overdrawn",
+            "account-event.funds-withdrawn",
+            "account-event.http-notified"
+        ]
+    );
+    assert_eq!(
+        FundsWithdrawn::EVENT_TYPES,
+        &["com.acme.bank.funds-withdrawn"]
+    );
+    assert_eq!(
+        LedgerEntry::EVENT_TYPES,
+        &["funds-deposited", "com.acme.bank.funds-withdrawn"]
+    );
+}
+
+#[test]
+fn catalog_recognizes_types_and_aliases() {
+    assert!(AccountEvent::is_known_type("com.acme.bank.overdrawn"));
+    assert!(AccountEvent::is_known_type("accountevent.fundsremoved"));
+    assert!(!AccountEvent::is_known_type(
+        "accountevent.accountoverdrawn"
+    ));
+    assert!(LedgerEntry::is_known_type("funds-deposited"));
+    assert!(!LedgerEntry::is_known_type("counterevent.reset"));
+}
+
+#[test]
+fn catalog_deserializes_by_type() {
+    let evt = AccountEvent::deserialize_by_type(
+        "account-event.funds-deposited",
+        &serde_json::json!({ "FundsDeposited": { "amount": 4 } }),
+    )
+    .unwrap();
+    assert_eq!(evt, AccountEvent::FundsDeposited { amount: 4 });
+
+    // the event type picks the variant, even for data persisted under an old name
+    let evt = AccountEvent::deserialize_by_type(
+        "accountevent.fundsremoved",
+        &serde_json::json!({ "FundsRemoved": 2 }),
+    )
+    .unwrap();
+    assert_eq!(evt, AccountEvent::FundsWithdrawn(2));
+
+    let evt = AccountEvent::deserialize_by_type(
+        "com.acme.bank.overdrawn",
+        &serde_json::json!("AccountOverdrawn"),
+    )
+    .unwrap();
+    assert_eq!(evt, AccountEvent::AccountOverdrawn);
+
+    // delegating enums accept both the inner struct's data and their own tagged data
+    let evt =
+        LedgerEntry::deserialize_by_type("com.acme.bank.funds-withdrawn", &serde_json::json!(6))
+            .unwrap();
+    assert_eq!(evt, LedgerEntry::Withdrawn(FundsWithdrawn(6)));
+    let evt = LedgerEntry::deserialize_by_type(
+        "funds-deposited",
+        &serde_json::json!({ "Deposited": { "amount": 1 } }),
+    )
+    .unwrap();
+    assert_eq!(evt, LedgerEntry::Deposited(FundsDeposited { amount: 1 }));
+}
+
+#[test]
+fn catalog_rejects_unknown_types_and_bad_data() {
+    let err = AccountEvent::deserialize_by_type("accountevent.closed", &serde_json::json!({}))
+        .unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::ValidationFailure("Unknown event type accountevent.closed".to_owned())
+    );
+
+    let err = FundsWithdrawn::deserialize_by_type(
+        "com.acme.bank.funds-withdrawn",
+        &serde_json::json!("six"),
+    )
+    .unwrap_err();
+    match err.kind {
+        Kind::ValidationFailure(msg) => {
+            assert!(msg.contains("could not be deserialized"), "{}", msg)
+        }
+        other => panic!("expected a validation failure, got {:?}", other),
+    }
+}
+
+// only the aggregate IDs are read
+#[allow(dead_code)]
+#[derive(Command)]
+#[command_naming("kebab-case")]
+enum AccountCommand {
+    OpenAccount {
+        #[aggregate_id]
+        account: String,
+        owner: String,
+    },
+    DepositFunds(#[aggregate_id] u64, u32),
+    #[command_type("com.acme.bank.close")]
+    CloseAccount(u32, #[aggregate_id] String),
+}
+
+#[derive(Command)]
+struct RenameAccount {
+    #[aggregate_id]
+    account: String,
+}
+
+#[test]
+fn commands_name_their_type() {
+    let open = AccountCommand::OpenAccount {
+        account: "acct-1".to_owned(),
+        owner: "alice".to_owned(),
+    };
+    assert_eq!(open.command_type(), "account-command.open-account");
+    assert_eq!(
+        AccountCommand::DepositFunds(7, 1).command_type(),
+        "account-command.deposit-funds"
+    );
+    assert_eq!(
+        AccountCommand::CloseAccount(0, "acct-1".to_owned()).command_type(),
+        "com.acme.bank.close"
+    );
+    let rename = RenameAccount {
+        account: "acct-2".to_owned(),
+    };
+    assert_eq!(rename.command_type(), "renameaccount");
+}
+
+#[test]
+fn commands_name_their_target_aggregate() {
+    let open = AccountCommand::OpenAccount {
+        account: "acct-1".to_owned(),
+        owner: "alice".to_owned(),
+    };
+    assert_eq!(open.aggregate_id(), "acct-1");
+    assert_eq!(AccountCommand::DepositFunds(7, 1).aggregate_id(), "7");
+    assert_eq!(
+        AccountCommand::CloseAccount(0, "acct-3".to_owned()).aggregate_id(),
+        "acct-3"
+    );
+    let rename = RenameAccount {
+        account: "acct-2".to_owned(),
+    };
+    assert_eq!(rename.aggregate_id(), "acct-2");
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+enum CorrectionEvent {
+    EntryCorrected {
+        amount: i64,
+        #[effective_time]
+        effective: DateTime<Utc>,
+    },
+    EntryReversed(u64, #[effective_time] Option<DateTime<Utc>>),
+    Annotated,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+struct InterestAccrued {
+    cents: u64,
+    #[effective_time]
+    accrued_on: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_delegate]
+enum InterestEvent {
+    Accrued(InterestAccrued),
+    Deposited(FundsDeposited),
+}
+
+#[derive(Command)]
+struct CorrectEntry {
+    #[aggregate_id]
+    account: String,
+    #[effective_time]
+    effective: Option<DateTime<Utc>>,
+}
+
+#[test]
+fn effective_time_is_read_from_the_marked_field() {
+    let march = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+    let corrected = CorrectionEvent::EntryCorrected {
+        amount: -5,
+        effective: march,
+    };
+    assert_eq!(corrected.effective_time(), Some(march));
+    assert_eq!(
+        CorrectionEvent::EntryReversed(1, Some(march)).effective_time(),
+        Some(march)
+    );
+    assert_eq!(
+        CorrectionEvent::EntryReversed(1, None).effective_time(),
+        None
+    );
+    assert_eq!(CorrectionEvent::Annotated.effective_time(), None);
+
+    let accrued = InterestAccrued {
+        cents: 3,
+        accrued_on: march,
+    };
+    assert_eq!(accrued.effective_time(), Some(march));
+    assert_eq!(
+        InterestEvent::Accrued(accrued).effective_time(),
+        Some(march)
+    );
+    assert_eq!(
+        InterestEvent::Deposited(FundsDeposited { amount: 1 }).effective_time(),
+        None
+    );
+
+    let correction = CorrectEntry {
+        account: "acct-1".to_owned(),
+        effective: Some(march),
+    };
+    assert_eq!(correction.effective_time(), Some(march));
+    let rename = RenameAccount {
+        account: "acct-2".to_owned(),
+    };
+    assert_eq!(rename.effective_time(), None);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+enum ShipmentEvent {
+    Shipped {
+        #[subject]
+        customer: String,
+    },
+    Returned(u64, #[subject] Option<String>),
+    Lost,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+struct ShipmentDelayed {
+    #[subject]
+    customer: String,
+}
+
+#[test]
+fn subject_is_read_from_the_marked_field() {
+    let shipped = ShipmentEvent::Shipped {
+        customer: "customer-1".to_owned(),
+    };
+    assert_eq!(shipped.subject(), Some("customer-1".to_owned()));
+    assert_eq!(
+        ShipmentEvent::Returned(1, Some("customer-2".to_owned())).subject(),
+        Some("customer-2".to_owned())
+    );
+    assert_eq!(ShipmentEvent::Returned(1, None).subject(), None);
+    assert_eq!(ShipmentEvent::Lost.subject(), None);
+    let delayed = ShipmentDelayed {
+        customer: "customer-3".to_owned(),
+    };
+    assert_eq!(delayed.subject(), Some("customer-3".to_owned()));
+    #[cfg(feature = "eventstore")]
+    {
+        let ce: CloudEvent = delayed.into();
+        assert_eq!(ce.subject, Some("customer-3".to_owned()));
+    }
+    assert_eq!(FundsDeposited { amount: 1 }.subject(), None);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+enum CustomerEvent {
+    CustomerRegistered {
+        id: u64,
+        #[encrypted]
+        name: Option<String>,
+        #[encrypted]
+        email: Option<String>,
+    },
+    AddressChanged(u64, #[encrypted] Option<String>),
+    NicknameChanged(#[encrypted] Option<String>),
+    CustomerDeparted,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+struct PhoneChanged(u64, #[encrypted] Option<String>);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_delegate]
+enum ContactEvent {
+    Phone(PhoneChanged),
+    Deposit(FundsDeposited),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+#[serde(rename_all = "snake_case")]
+enum RenamedCustomerEvent {
+    #[serde(rename_all = "camelCase")]
+    CustomerRegistered {
+        #[encrypted]
+        #[serde(rename = "mail")]
+        email_address: Option<String>,
+        #[encrypted]
+        display_name: Option<String>,
+    },
+    #[serde(rename = "moved/away")]
+    CustomerMoved(u64, #[serde(skip)] u64, #[encrypted] Option<String>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/derive")]
+#[serde(tag = "kind", content = "payload")]
+enum TaggedCustomerEvent {
+    CustomerRenamed {
+        #[encrypted]
+        name: Option<String>,
+    },
+}
+
+#[test]
+fn encrypted_fields_follow_serde_renames() {
+    let registered = RenamedCustomerEvent::CustomerRegistered {
+        email_address: Some("ada@example.com".to_owned()),
+        display_name: Some("Ada".to_owned()),
+    };
+    let moved = RenamedCustomerEvent::CustomerMoved(1, 2, Some("Main St".to_owned()));
+    let renamed = TaggedCustomerEvent::CustomerRenamed {
+        name: Some("Ada".to_owned()),
+    };
+    assert_eq!(
+        registered.encrypted_fields(),
+        Some(vec![
+            "/customer_registered/mail".to_owned(),
+            "/customer_registered/displayName".to_owned()
+        ])
+    );
+    assert_eq!(
+        moved.encrypted_fields(),
+        Some(vec!["/moved~1away/1".to_owned()])
+    );
+    assert_eq!(
+        renamed.encrypted_fields(),
+        Some(vec!["/payload/name".to_owned()])
+    );
+
+    let data = serde_json::to_value(&registered).unwrap();
+    assert_eq!(
+        data.pointer("/customer_registered/mail"),
+        Some(&serde_json::json!("ada@example.com"))
+    );
+    assert_eq!(
+        data.pointer("/customer_registered/displayName"),
+        Some(&serde_json::json!("Ada"))
+    );
+    let data = serde_json::to_value(&moved).unwrap();
+    assert_eq!(
+        data.pointer("/moved~1away/1"),
+        Some(&serde_json::json!("Main St"))
+    );
+    let data = serde_json::to_value(&renamed).unwrap();
+    assert_eq!(
+        data.pointer("/payload/name"),
+        Some(&serde_json::json!("Ada"))
+    );
+}
+
+#[test]
+fn encrypted_fields_are_json_pointers_into_the_data() {
+    let registered = CustomerEvent::CustomerRegistered {
+        id: 1,
+        name: None,
+        email: None,
+    };
+    assert_eq!(
+        registered.encrypted_fields(),
+        Some(vec![
+            "/CustomerRegistered/name".to_owned(),
+            "/CustomerRegistered/email".to_owned()
+        ])
+    );
+    assert_eq!(
+        CustomerEvent::AddressChanged(1, None).encrypted_fields(),
+        Some(vec!["/AddressChanged/1".to_owned()])
+    );
+    assert_eq!(
+        CustomerEvent::NicknameChanged(None).encrypted_fields(),
+        Some(vec!["/NicknameChanged".to_owned()])
+    );
+    assert_eq!(
+        CustomerEvent::CustomerDeparted.encrypted_fields(),
+        Some(vec![])
+    );
+
+    let phone = PhoneChanged(1, Some("555-0100".to_owned()));
+    assert_eq!(phone.encrypted_fields(), Some(vec!["/1".to_owned()]));
+    assert_eq!(
+        ContactEvent::Phone(phone).encrypted_fields(),
+        Some(vec!["/Phone/1".to_owned()])
+    );
+    assert_eq!(
+        ContactEvent::Deposit(FundsDeposited { amount: 1 }).encrypted_fields(),
+        None
+    );
+    assert_eq!(CounterEvent::Reset.encrypted_fields(), None);
+}
+
+#[test]
+fn serialize_only_events_derive_event() {
+    fn event_type<E: Event>(evt: &E) -> &str {
+        evt.event_type()
+    }
+    let exported = AuditEvent::Exported { rows: 3 };
+    assert_eq!(event_type(&exported), "auditevent.exported");
+    assert_eq!(event_type(&AuditPurged), "auditpurged");
+}
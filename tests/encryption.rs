@@ -0,0 +1,350 @@
+#![cfg(feature = "encryption")]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::encryption::{EncryptingStore, KeyStore, MemoryKeyStore, ENCRYPTED};
+use eventsourcing::eventstore::{EventStore, MemoryEventStore, Position};
+use eventsourcing::repository::Repository;
+use eventsourcing::{prelude::*, Error, Kind, Result};
+use serde_json::json;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/encryption")]
+enum PatientEvent {
+    DiagnosisRecorded(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/encryption")]
+enum CustomerEvent {
+    CustomerRegistered {
+        tier: String,
+        #[encrypted]
+        email: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/encryption")]
+#[serde(rename_all = "snake_case")]
+enum RenamedCustomerEvent {
+    CustomerRegistered {
+        #[encrypted]
+        #[serde(rename = "mail")]
+        email: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/encryption")]
+enum OrderEvent {
+    OrderShipped {
+        #[subject]
+        customer: String,
+        #[encrypted]
+        address: Option<String>,
+    },
+}
+
+/// An event whose data happens to use the key encrypted values are stored under
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/encryption")]
+enum NoteEvent {
+    NoteAdded(serde_json::Value),
+}
+
+/// An event pointing at a field its data doesn't have
+#[derive(Serialize)]
+struct MisdirectedEvent {
+    mail: String,
+}
+
+impl Event for MisdirectedEvent {
+    fn event_type_version(&self) -> &str {
+        DOMAIN_VERSION
+    }
+
+    fn event_type(&self) -> &str {
+        "misdirectedevent"
+    }
+
+    fn event_source(&self) -> &str {
+        "events://github.com/pholactery/eventsourcing/tests/encryption"
+    }
+
+    fn encrypted_fields(&self) -> Option<Vec<String>> {
+        Some(vec!["/email".to_owned()])
+    }
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct CustomerState {
+    emails: Vec<Option<String>>,
+    generation: u64,
+}
+
+struct Customer;
+
+impl Aggregate for Customer {
+    type Event = CustomerEvent;
+    type Command = ();
+    type State = CustomerState;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        let CustomerEvent::CustomerRegistered { email, .. } = evt;
+        let mut emails = state.emails.clone();
+        emails.push(email.clone());
+        Ok(CustomerState {
+            emails,
+            ..state.clone()
+        })
+    }
+
+    fn handle_command(_state: &Self::State, _cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        Err(Error {
+            kind: Kind::CommandFailure("Commands need a context".to_owned()),
+        })
+    }
+}
+
+fn registered(email: &str) -> CustomerEvent {
+    CustomerEvent::CustomerRegistered {
+        tier: "gold".to_owned(),
+        email: Some(email.to_owned()),
+    }
+}
+
+fn encrypting() -> EncryptingStore<MemoryEventStore> {
+    EncryptingStore::new(MemoryEventStore::new(), MemoryKeyStore::new())
+}
+
+#[test]
+fn data_is_only_readable_through_the_store() {
+    let store = encrypting();
+    let appended = store
+        .append(
+            PatientEvent::DiagnosisRecorded("flu".to_owned()),
+            "patient-1",
+        )
+        .unwrap();
+    assert_eq!(appended.data, json!({ "DiagnosisRecorded": "flu" }));
+
+    let at_rest = &store.store().read_stream("patient-1").unwrap()[0];
+    assert_eq!(at_rest.data[ENCRYPTED]["v"], 1);
+    assert!(!at_rest.data.to_string().contains("flu"));
+
+    let read = &store.read_stream("patient-1").unwrap()[0];
+    assert_eq!(read.data, json!({ "DiagnosisRecorded": "flu" }));
+    assert!(!read.is_redacted());
+}
+
+#[test]
+fn marked_fields_are_encrypted_on_their_own() {
+    let store = encrypting();
+    store
+        .append(registered("ada@example.com"), "customer-1")
+        .unwrap();
+
+    let at_rest = &store.store().read_stream("customer-1").unwrap()[0];
+    let fields = &at_rest.data["CustomerRegistered"];
+    assert_eq!(fields["tier"], "gold");
+    assert!(fields["email"].get(ENCRYPTED).is_some());
+
+    let repo: Repository<Customer, _> = Repository::new(store);
+    assert_eq!(
+        repo.load("customer-1").unwrap().emails,
+        vec![Some("ada@example.com".to_owned())]
+    );
+}
+
+#[test]
+fn renamed_fields_are_encrypted() {
+    let store = encrypting();
+    let registered = RenamedCustomerEvent::CustomerRegistered {
+        email: Some("ada@example.com".to_owned()),
+    };
+    store.append(registered.clone(), "customer-1").unwrap();
+
+    let at_rest = &store.store().read_stream("customer-1").unwrap()[0];
+    assert!(at_rest.data["customer_registered"]["mail"]
+        .get(ENCRYPTED)
+        .is_some());
+    assert!(!at_rest.data.to_string().contains("ada@example.com"));
+    let read = &store.read_stream("customer-1").unwrap()[0];
+    assert_eq!(
+        RenamedCustomerEvent::deserialize_by_type(&read.event_type, &read.data).unwrap(),
+        registered
+    );
+}
+
+#[test]
+fn fields_missing_from_the_data_fail_the_append() {
+    let store = encrypting();
+    let err = store
+        .append(
+            MisdirectedEvent {
+                mail: "ada@example.com".to_owned(),
+            },
+            "customer-1",
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("/email"), "{}", err);
+    assert!(store.store().read_stream("customer-1").unwrap().is_empty());
+}
+
+#[test]
+fn shredded_subjects_read_as_redacted() {
+    let store = encrypting();
+    store
+        .append(registered("ada@example.com"), "customer-1")
+        .unwrap();
+    store
+        .append(registered("bob@example.com"), "customer-2")
+        .unwrap();
+    store
+        .append(
+            PatientEvent::DiagnosisRecorded("flu".to_owned()),
+            "customer-1",
+        )
+        .unwrap();
+
+    store.shred("customer-1").unwrap();
+    assert!(store.keys().key("customer-1").unwrap().is_none());
+    let read = store.read_stream("customer-1").unwrap();
+    assert!(read.iter().all(|evt| evt.is_redacted()));
+    assert_eq!(
+        read[0].data,
+        json!({ "CustomerRegistered": { "tier": "gold", "email": null } })
+    );
+    assert_eq!(read[1].data, serde_json::Value::Null);
+    assert!(!store.read_stream("customer-2").unwrap()[0].is_redacted());
+
+    store
+        .append(registered("ada@new.example.com"), "customer-1")
+        .unwrap();
+    let erased = store.read_stream("customer-1").unwrap();
+    assert!(!erased[2].is_redacted());
+    assert_eq!(
+        CustomerEvent::deserialize_by_type(&erased[0].event_type, &erased[0].data).unwrap(),
+        CustomerEvent::CustomerRegistered {
+            tier: "gold".to_owned(),
+            email: None,
+        }
+    );
+}
+
+fn shipped(customer: &str, address: &str) -> OrderEvent {
+    OrderEvent::OrderShipped {
+        customer: customer.to_owned(),
+        address: Some(address.to_owned()),
+    }
+}
+
+#[test]
+fn events_are_encrypted_under_their_subject() {
+    let store = encrypting();
+    store
+        .append(shipped("customer-1", "1 Main St"), "order-1")
+        .unwrap();
+    store
+        .append(shipped("customer-1", "2 Side St"), "order-2")
+        .unwrap();
+    store
+        .append(shipped("customer-2", "3 High St"), "order-3")
+        .unwrap();
+    assert_eq!(
+        store.store().read_stream("order-1").unwrap()[0].subject,
+        Some("customer-1".to_owned())
+    );
+    assert!(store.keys().key("order-1").unwrap().is_none());
+
+    store.shred("customer-1").unwrap();
+    for stream in &["order-1", "order-2"] {
+        let read = &store.read_stream(stream).unwrap()[0];
+        assert!(read.is_redacted());
+        assert_eq!(read.data["OrderShipped"]["address"], json!(null));
+    }
+    let kept = &store.read_stream("order-3").unwrap()[0];
+    assert!(!kept.is_redacted());
+    assert_eq!(kept.data["OrderShipped"]["address"], json!("3 High St"));
+
+    let all = store.read_all(Position::START, 10).unwrap();
+    assert!(all[0].event.is_redacted() && all[1].event.is_redacted());
+    assert!(!all[2].event.is_redacted());
+}
+
+#[test]
+fn fully_redacted_events_fail_to_convert() {
+    let store = encrypting();
+    store
+        .append(
+            PatientEvent::DiagnosisRecorded("flu".to_owned()),
+            "patient-1",
+        )
+        .unwrap();
+    store.shred("patient-1").unwrap();
+
+    let read = &store.read_stream("patient-1").unwrap()[0];
+    assert!(read.is_redacted());
+    assert!(read.to_event::<PatientEvent>().is_err());
+}
+
+#[test]
+fn the_feed_of_all_streams_is_decrypted_per_stream() {
+    let store = encrypting();
+    store
+        .append(registered("ada@example.com"), "customer-1")
+        .unwrap();
+    store
+        .append(registered("bob@example.com"), "customer-2")
+        .unwrap();
+    store.shred("customer-1").unwrap();
+
+    let all = store.read_all(Position::START, 10).unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].stream, "customer-1");
+    assert!(all[0].event.is_redacted());
+    assert_eq!(
+        all[0].event.data["CustomerRegistered"]["email"],
+        json!(null)
+    );
+    assert!(!all[1].event.is_redacted());
+    assert_eq!(
+        all[1].event.data["CustomerRegistered"]["email"],
+        json!("bob@example.com")
+    );
+}
+
+#[test]
+fn data_that_only_looks_encrypted_is_left_alone() {
+    let store = encrypting();
+    let lookalikes = [
+        json!({ "$encrypted": "yes" }),
+        json!({ "$encrypted": { "key": "k", "nonce": "n", "ciphertext": "c" } }),
+        json!({ "$encrypted": { "v": 2, "key": "k", "nonce": "n", "ciphertext": "c" } }),
+    ];
+    for note in &lookalikes {
+        store
+            .store()
+            .append(NoteEvent::NoteAdded(note.clone()), "notes")
+            .unwrap();
+    }
+
+    let read = store.read_stream("notes").unwrap();
+    for (evt, note) in read.iter().zip(&lookalikes) {
+        assert_eq!(evt.data, json!({ "NoteAdded": note }));
+        assert!(!evt.is_redacted());
+    }
+}
@@ -0,0 +1,285 @@
+#![cfg(feature = "integrity")]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::eventstore::{Deletion, EventStore, MemoryEventStore, StreamMetadata};
+use eventsourcing::integrity::{
+    canonical_json, chain_hash, Break, BrokenLink, ChainLink, HashChainStore, HashStore,
+    MemoryHashStore,
+};
+use eventsourcing::{prelude::*, CloudEvent, Error, Kind, Result};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/integrity")]
+enum LedgerEvent {
+    FundsDeposited(u32),
+}
+
+/// A store whose events can be tampered with behind the hash chain's back
+#[derive(Default)]
+struct LooseStore {
+    evts: Mutex<Vec<(String, CloudEvent)>>,
+}
+
+impl LooseStore {
+    fn tamper(&self, position: usize, f: impl FnOnce(&mut Vec<(String, CloudEvent)>, usize)) {
+        f(&mut self.evts.lock().unwrap(), position)
+    }
+}
+
+impl EventStore for LooseStore {
+    fn append(&self, evt: impl Event, stream: &str) -> Result<CloudEvent> {
+        let evt: CloudEvent = evt.into();
+        self.evts
+            .lock()
+            .unwrap()
+            .push((stream.to_owned(), evt.clone()));
+        Ok(evt)
+    }
+
+    fn read_stream(&self, stream: &str) -> Result<Vec<CloudEvent>> {
+        Ok(self
+            .evts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| name == stream)
+            .map(|(_, evt)| evt.clone())
+            .collect())
+    }
+}
+
+fn ledger(deposits: u32) -> (HashChainStore<LooseStore>, Vec<String>) {
+    let store = HashChainStore::new(LooseStore::default());
+    let ids = (0..deposits)
+        .map(|n| {
+            store
+                .append(LedgerEvent::FundsDeposited(n), "ledger")
+                .unwrap()
+                .event_id
+        })
+        .collect();
+    (store, ids)
+}
+
+#[test]
+fn appended_events_are_chained() {
+    let (store, ids) = ledger(3);
+    store
+        .append(LedgerEvent::FundsDeposited(9), "other")
+        .unwrap();
+
+    let links = store.hashes().links("ledger").unwrap();
+    let evts = store.read_stream("ledger").unwrap();
+    assert_eq!(links.len(), 3);
+    assert_eq!(links[0].hash, chain_hash(None, &evts[0]).unwrap());
+    assert_eq!(
+        links[1].hash,
+        chain_hash(Some(&links[0].hash), &evts[1]).unwrap()
+    );
+    assert_eq!(links[2].event_id, ids[2]);
+    assert_eq!(store.verify("ledger").unwrap(), None);
+    assert_eq!(store.verify("other").unwrap(), None);
+    assert_eq!(store.verify("empty").unwrap(), None);
+}
+
+#[test]
+fn events_are_hashed_with_sorted_keys() {
+    let mut evt: CloudEvent = LedgerEvent::FundsDeposited(1).into();
+    evt.data = json!({
+        "zeta": 1,
+        "alpha": { "delta": [{ "charlie": 3, "bravo": "x y" }], "beta": null }
+    });
+    let canonical = canonical_json(&evt).unwrap();
+    assert!(canonical.contains(
+        r#""data":{"alpha":{"beta":null,"delta":[{"bravo":"x y","charlie":3}]},"zeta":1}"#
+    ));
+    let position = |key: &str| canonical.find(&format!("\"{}\":", key)).unwrap();
+    assert!(position("data") < position("id") && position("id") < position("type"));
+}
+
+#[test]
+fn altered_events_break_the_chain() {
+    let (store, ids) = ledger(3);
+    store.store().tamper(1, |evts, position| {
+        evts[position].1.data = serde_json::json!({ "FundsDeposited": 1000 });
+    });
+    assert_eq!(
+        store.verify("ledger").unwrap(),
+        Some(BrokenLink {
+            position: 1,
+            event_id: ids[1].clone(),
+            kind: Break::Altered,
+        })
+    );
+}
+
+#[test]
+fn removed_events_break_the_chain() {
+    let (store, ids) = ledger(3);
+    store.store().tamper(1, |evts, position| {
+        evts.remove(position);
+    });
+    assert_eq!(
+        store.verify("ledger").unwrap(),
+        Some(BrokenLink {
+            position: 1,
+            event_id: ids[2].clone(),
+            kind: Break::Missing,
+        })
+    );
+
+    store.store().tamper(1, |evts, position| {
+        evts.truncate(position);
+    });
+    assert_eq!(
+        store.verify("ledger").unwrap(),
+        Some(BrokenLink {
+            position: 1,
+            event_id: ids[1].clone(),
+            kind: Break::Missing,
+        })
+    );
+}
+
+#[test]
+fn events_bypassing_the_chain_are_reported() {
+    let (store, _) = ledger(2);
+    let bypassed = store
+        .store()
+        .append(LedgerEvent::FundsDeposited(7), "ledger")
+        .unwrap();
+    assert_eq!(
+        store.verify("ledger").unwrap(),
+        Some(BrokenLink {
+            position: 2,
+            event_id: bypassed.event_id,
+            kind: Break::Unchained,
+        })
+    );
+}
+
+#[test]
+fn events_hidden_by_the_store_are_skipped() {
+    let store = HashChainStore::new(MemoryEventStore::new());
+    for n in 0..4 {
+        store
+            .append(LedgerEvent::FundsDeposited(n), "ledger")
+            .unwrap();
+    }
+    store.store().truncate_before("ledger", 1).unwrap();
+    assert_eq!(store.verify("ledger").unwrap(), None);
+
+    let metadata = StreamMetadata::default().with_max_count(2);
+    store
+        .store()
+        .set_stream_metadata("ledger", metadata)
+        .unwrap();
+    assert_eq!(store.read_stream("ledger").unwrap().len(), 2);
+    assert_eq!(store.verify("ledger").unwrap(), None);
+
+    store.store().truncate_before("ledger", 4).unwrap();
+    assert_eq!(store.verify("ledger").unwrap(), None);
+}
+
+/// A hash store whose links can be made to fail to be added
+#[derive(Default)]
+struct FlakyHashStore {
+    hashes: MemoryHashStore,
+    failing: AtomicBool,
+}
+
+impl HashStore for FlakyHashStore {
+    fn append_link(&self, stream: &str, link: ChainLink) -> Result<()> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(Error {
+                kind: Kind::StoreFailure("Hash store unavailable".to_owned()),
+            });
+        }
+        self.hashes.append_link(stream, link)
+    }
+
+    fn links(&self, stream: &str) -> Result<Vec<ChainLink>> {
+        self.hashes.links(stream)
+    }
+}
+
+#[test]
+fn events_left_unlinked_are_relinked() {
+    let store = HashChainStore::with_hash_store(LooseStore::default(), FlakyHashStore::default());
+    store
+        .append(LedgerEvent::FundsDeposited(1), "ledger")
+        .unwrap();
+    store.hashes().failing.store(true, Ordering::SeqCst);
+    assert!(store
+        .append(LedgerEvent::FundsDeposited(2), "ledger")
+        .is_err());
+    store.hashes().failing.store(false, Ordering::SeqCst);
+
+    let unlinked = store.read_stream("ledger").unwrap()[1].event_id.clone();
+    assert_eq!(
+        store.verify("ledger").unwrap(),
+        Some(BrokenLink {
+            position: 1,
+            event_id: unlinked,
+            kind: Break::Unchained,
+        })
+    );
+    assert!(store
+        .append(LedgerEvent::FundsDeposited(3), "ledger")
+        .is_err());
+    assert_eq!(store.read_stream("ledger").unwrap().len(), 2);
+
+    assert_eq!(store.relink("ledger").unwrap(), 1);
+    assert_eq!(store.verify("ledger").unwrap(), None);
+    assert_eq!(store.relink("ledger").unwrap(), 0);
+    store
+        .append(LedgerEvent::FundsDeposited(3), "ledger")
+        .unwrap();
+    assert_eq!(store.verify("ledger").unwrap(), None);
+}
+
+#[test]
+fn broken_chains_are_not_relinked() {
+    let (store, _) = ledger(2);
+    store.store().tamper(0, |evts, position| {
+        evts[position].1.data = serde_json::json!({ "FundsDeposited": 1000 });
+    });
+    store
+        .store()
+        .append(LedgerEvent::FundsDeposited(7), "ledger")
+        .unwrap();
+    assert!(store.relink("ledger").is_err());
+    assert_eq!(store.hashes().links("ledger").unwrap().len(), 2);
+}
+
+#[test]
+fn chained_streams_cant_be_deleted_or_truncated() {
+    let store = HashChainStore::new(MemoryEventStore::new());
+    store
+        .append(LedgerEvent::FundsDeposited(1), "ledger")
+        .unwrap();
+
+    assert!(store.delete_stream("ledger", Deletion::Soft).is_err());
+    let err = store.truncate_before("ledger", 1).unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::StoreFailure("Stream ledger can't be truncated by this event store".to_owned())
+    );
+    assert!(store
+        .set_stream_metadata("ledger", StreamMetadata::default().with_max_count(1))
+        .is_err());
+    assert_eq!(store.read_stream("ledger").unwrap().len(), 1);
+    assert_eq!(store.verify("ledger").unwrap(), None);
+}
@@ -0,0 +1,288 @@
+#![cfg(all(feature = "test-util", feature = "orgeventstore"))]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::eventstore::{ExpectedVersion, OrgEventStore, Position, RecordedEvent};
+use eventsourcing::testing::orgmock::MockEventStoreServer;
+use eventsourcing::{prelude::*, Result};
+use std::convert::TryFrom;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Debug)]
+enum CombatCommand {
+    Attack(String, u32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/orgeventstore")]
+enum CombatEvent {
+    EntityAttacked(String, u32),
+}
+
+#[derive(Debug, Clone)]
+struct CombatState {
+    hitpoints: u32,
+    generation: u64,
+}
+
+impl AggregateState for CombatState {
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+struct Combat;
+
+impl Aggregate for Combat {
+    type Event = CombatEvent;
+    type Command = CombatCommand;
+    type State = CombatState;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        let CombatEvent::EntityAttacked(_, pts) = *evt;
+        Ok(CombatState {
+            hitpoints: state.hitpoints.saturating_sub(pts),
+            generation: state.generation + 1,
+        })
+    }
+
+    fn handle_command(_state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        let CombatCommand::Attack(ref entity_id, pts) = *cmd;
+        Ok(vec![CombatEvent::EntityAttacked(entity_id.clone(), pts)])
+    }
+}
+
+/// Context that multiplies the damage of every attack
+struct DamageMultiplier(u32);
+
+impl CommandHandler<DamageMultiplier> for Combat {
+    fn handle_command_with(
+        _state: &Self::State,
+        cmd: &Self::Command,
+        ctx: &DamageMultiplier,
+    ) -> Result<Vec<Self::Event>> {
+        let CombatCommand::Attack(ref entity_id, pts) = *cmd;
+        Ok(vec![CombatEvent::EntityAttacked(
+            entity_id.clone(),
+            pts * ctx.0,
+        )])
+    }
+}
+
+#[derive(Dispatcher)]
+#[aggregate(Combat)]
+struct CombatDispatcher;
+
+fn attack(pts: u32) -> CombatEvent {
+    CombatEvent::EntityAttacked("ogre".to_owned(), pts)
+}
+
+fn store_failure<T: std::fmt::Debug>(res: Result<T>) -> String {
+    match res {
+        Err(e) => match e.kind {
+            Kind::StoreFailure(msg) => msg,
+            other => panic!("expected a store failure, got {:?}", other),
+        },
+        Ok(value) => panic!("expected a store failure, got {:?}", value),
+    }
+}
+
+#[test]
+fn dispatcher_appends_through_http() {
+    let server = MockEventStoreServer::start();
+    let store = server.store();
+    let state = CombatState {
+        hitpoints: 900,
+        generation: 0,
+    };
+
+    let res = CombatDispatcher::dispatch(
+        &state,
+        &CombatCommand::Attack("ogre".to_owned(), 150),
+        &store,
+        "ogre",
+    );
+    assert_eq!(res.len(), 1);
+    assert!(res[0].is_ok());
+
+    let recorded = server.events("ogre");
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].event_type, "combatevent.entityattacked");
+    assert_eq!(
+        recorded[0].metadata["source"],
+        "events://github.com/pholactery/eventsourcing/tests/orgeventstore"
+    );
+
+    let events = store.read_stream("ogre").unwrap();
+    let replayed: Vec<CombatEvent> = events
+        .into_iter()
+        .map(CombatEvent::try_from)
+        .collect::<Result<_>>()
+        .unwrap();
+    let state = Combat::apply_all(&state, &replayed).unwrap();
+    assert_eq!(state.hitpoints, 750);
+}
+
+#[test]
+fn dispatcher_threads_the_context() {
+    let server = MockEventStoreServer::start();
+    let store = server.store();
+    let state = CombatState {
+        hitpoints: 900,
+        generation: 0,
+    };
+
+    let res = CombatDispatcher::dispatch_with(
+        &state,
+        &CombatCommand::Attack("ogre".to_owned(), 150),
+        &DamageMultiplier(3),
+        &store,
+        "ogre",
+    );
+    assert!(res[0].is_ok());
+    let recorded = server.events("ogre");
+    assert_eq!(
+        recorded[0].data,
+        serde_json::json!({ "EntityAttacked": ["ogre", 450] })
+    );
+}
+
+#[test]
+fn expected_version_is_enforced() {
+    let server = MockEventStoreServer::start();
+    let store = server.store();
+
+    store
+        .append_with_version(attack(1), "ogre", ExpectedVersion::NoStream)
+        .unwrap();
+    store
+        .append_with_version(attack(2), "ogre", ExpectedVersion::Exact(0))
+        .unwrap();
+    store
+        .append_with_version(attack(3), "ogre", ExpectedVersion::StreamExists)
+        .unwrap();
+
+    let msg =
+        store_failure(store.append_with_version(attack(4), "ogre", ExpectedVersion::Exact(0)));
+    assert!(msg.contains("current version is 2"), "{}", msg);
+    store_failure(store.append_with_version(attack(4), "ogre", ExpectedVersion::NoStream));
+    store_failure(store.append_with_version(attack(4), "goblin", ExpectedVersion::StreamExists));
+
+    store
+        .append_with_version(attack(4), "ogre", ExpectedVersion::Any)
+        .unwrap();
+    assert_eq!(store.read_stream("ogre").unwrap().len(), 4);
+    assert!(store.read_stream("goblin").unwrap().is_empty());
+}
+
+#[test]
+fn deleted_streams_are_not_found_or_gone() {
+    let server = MockEventStoreServer::start();
+    let store = server.store();
+    store.append(attack(1), "soft").unwrap();
+    store.append(attack(1), "hard").unwrap();
+
+    server.delete_stream("soft", false);
+    server.delete_stream("hard", true);
+
+    assert!(store.read_stream("soft").unwrap().is_empty());
+    let msg = match store.read_stream("hard") {
+        Err(e) => e.to_string(),
+        Ok(evts) => panic!("expected a deleted stream error, got {:?}", evts),
+    };
+    assert!(msg.contains("deleted"), "{}", msg);
+
+    // soft deleted streams can be recreated, hard deleted ones can't
+    store.append(attack(2), "soft").unwrap();
+    assert_eq!(store.read_stream("soft").unwrap().len(), 1);
+    assert_eq!(server.events("soft")[0].event_number, 1);
+    let msg = store_failure(store.append(attack(2), "hard"));
+    assert!(msg.contains("deleted"), "{}", msg);
+}
+
+#[test]
+fn injected_failures_surface_as_store_failures() {
+    let server = MockEventStoreServer::start();
+    let store = server.store();
+
+    server.fail_requests(500, 2);
+    let msg = store_failure(store.append(attack(1), "ogre"));
+    assert!(msg.contains("500"), "{}", msg);
+    assert!(store.read_stream("ogre").is_err());
+
+    store.append(attack(1), "ogre").unwrap();
+    assert_eq!(store.read_stream("ogre").unwrap().len(), 1);
+}
+
+#[test]
+fn unreachable_server_is_a_store_failure() {
+    let store = OrgEventStore::new("127.0.0.1", 1);
+    store_failure(store.append(attack(1), "ogre"));
+    assert!(store.read_stream("ogre").is_err());
+}
+
+#[test]
+fn reads_page_through_long_streams() {
+    let server = MockEventStoreServer::start();
+    let store = server.store();
+    for n in 0..45 {
+        store.append(attack(n), "ogre").unwrap();
+    }
+    let events = store.read_stream("ogre").unwrap();
+    let points: Vec<u64> = events
+        .iter()
+        .map(|evt| evt.data["EntityAttacked"][1].as_u64().unwrap())
+        .collect();
+    assert_eq!(points, (0..45).collect::<Vec<u64>>());
+}
+
+#[test]
+fn reads_of_all_streams_follow_the_feed_links() {
+    let server = MockEventStoreServer::start();
+    let store = server.store();
+    for n in 0..45 {
+        let stream = if n % 2 == 0 { "ogre" } else { "troll" };
+        store.append(attack(n), stream).unwrap();
+    }
+    let points = |recorded: &[RecordedEvent]| -> Vec<u64> {
+        recorded
+            .iter()
+            .map(|r| r.event.data["EntityAttacked"][1].as_u64().unwrap())
+            .collect()
+    };
+    let all = store.read_all(Position::START, usize::MAX).unwrap();
+    assert_eq!(points(&all), (0..45).collect::<Vec<u64>>());
+    assert!(all
+        .windows(2)
+        .all(|pair| pair[0].position <= pair[1].position));
+
+    let first = store.read_all(Position::START, 30).unwrap();
+    assert_eq!(points(&first), (0..30).collect::<Vec<u64>>());
+    let rest = store
+        .read_all(first.last().unwrap().position, usize::MAX)
+        .unwrap();
+    assert_eq!(points(&rest), (30..45).collect::<Vec<u64>>());
+    assert!(store
+        .read_all(all.last().unwrap().position, usize::MAX)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn reading_all_streams_needs_admin_credentials() {
+    let server = MockEventStoreServer::start();
+    server.store().append(attack(1), "ogre").unwrap();
+    let anonymous = OrgEventStore::new(&server.host(), server.port());
+    let msg = store_failure(anonymous.read_all(Position::START, 10));
+    assert!(msg.contains("admin"), "{}", msg);
+
+    let wrong = anonymous.with_credentials("admin", "guess");
+    assert!(wrong.read_all(Position::START, 10).is_err());
+}
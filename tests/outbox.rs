@@ -0,0 +1,249 @@
+#![cfg(feature = "eventstore")]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::eventstore::{EventStore, MemoryEventStore};
+use eventsourcing::outbox::{Outbox, OutboxRelay, OutboxStore, Publisher};
+use eventsourcing::retry::RetryPolicy;
+use eventsourcing::{prelude::*, CloudEvent, Error, Result};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/outbox")]
+enum OrderEvent {
+    OrderPlaced(u32),
+}
+
+fn placed(stream: &OutboxStore<Arc<MemoryEventStore>>, orders: u32) -> Vec<String> {
+    (0..orders)
+        .map(|n| {
+            stream
+                .append(OrderEvent::OrderPlaced(n), "orders")
+                .unwrap()
+                .event_id
+        })
+        .collect()
+}
+
+fn ids(evts: &[CloudEvent]) -> Vec<String> {
+    evts.iter().map(|evt| evt.event_id.clone()).collect()
+}
+
+/// A publisher recording the events it was given, failing on the given calls
+#[derive(Clone, Default)]
+struct Broker {
+    published: Arc<Mutex<Vec<String>>>,
+    calls: Arc<Mutex<u32>>,
+    failing: Vec<u32>,
+}
+
+impl Broker {
+    fn failing(calls: Vec<u32>) -> Broker {
+        Broker {
+            failing: calls,
+            ..Broker::default()
+        }
+    }
+
+    fn published(&self) -> Vec<String> {
+        self.published.lock().unwrap().clone()
+    }
+}
+
+impl Publisher for Broker {
+    fn publish(&self, evt: &CloudEvent) -> Result<()> {
+        let mut calls = self.calls.lock().unwrap();
+        *calls += 1;
+        if self.failing.contains(&calls) {
+            return Err(Error {
+                kind: Kind::ApplicationFailure("Broker unavailable".to_owned()),
+            });
+        }
+        self.published.lock().unwrap().push(evt.event_id.clone());
+        Ok(())
+    }
+}
+
+#[test]
+fn appends_through_the_outbox_are_queued() {
+    let store = Arc::new(MemoryEventStore::new());
+    let outbox = OutboxStore::new(store.clone());
+
+    let queued = placed(&outbox, 2);
+    store.append(OrderEvent::OrderPlaced(9), "orders").unwrap();
+
+    assert_eq!(outbox.read_stream("orders").unwrap().len(), 3);
+    assert_eq!(ids(&store.pending_publications().unwrap()), queued);
+}
+
+#[test]
+fn relay_publishes_pending_events_once() {
+    let store = Arc::new(MemoryEventStore::new());
+    let queued = placed(&OutboxStore::new(store.clone()), 3);
+    let broker = Broker::default();
+    let relay = OutboxRelay::new(store, broker.clone());
+
+    assert_eq!(relay.relay().unwrap(), 3);
+    assert_eq!(broker.published(), queued);
+    assert!(relay.store().pending_publications().unwrap().is_empty());
+
+    assert_eq!(relay.relay().unwrap(), 0);
+    assert_eq!(broker.published().len(), 3);
+}
+
+#[test]
+fn relay_retries_failed_deliveries() {
+    let store = Arc::new(MemoryEventStore::new());
+    let queued = placed(&OutboxStore::new(store.clone()), 1);
+    let broker = Broker::failing(vec![1, 2]);
+    let relay =
+        OutboxRelay::new(store, broker.clone()).with_retry_policy(RetryPolicy::immediate(3));
+
+    assert_eq!(relay.relay().unwrap(), 1);
+    assert_eq!(broker.published(), queued);
+}
+
+#[test]
+fn undeliverable_events_stay_pending() {
+    let store = Arc::new(MemoryEventStore::new());
+    let queued = placed(&OutboxStore::new(store.clone()), 3);
+    let broker = Broker::failing(vec![2, 3]);
+    let relay = OutboxRelay::new(store.clone(), broker.clone())
+        .with_retry_policy(RetryPolicy::immediate(2));
+
+    let err = relay.relay().unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::ApplicationFailure("Broker unavailable".to_owned())
+    );
+    assert_eq!(broker.published(), queued[..1]);
+    assert_eq!(ids(&store.pending_publications().unwrap()), queued[1..]);
+
+    assert_eq!(relay.relay().unwrap(), 2);
+    assert_eq!(broker.published(), queued);
+}
+
+#[test]
+fn retry_delays_grow_exponentially() {
+    let policy = RetryPolicy::new(4, Duration::from_millis(100));
+    assert_eq!(policy.delay(1), Duration::from_millis(100));
+    assert_eq!(policy.delay(2), Duration::from_millis(200));
+    assert_eq!(policy.delay(3), Duration::from_millis(400));
+    assert_eq!(
+        policy.with_multiplier(3).delay(3),
+        Duration::from_millis(900)
+    );
+
+    let mut attempts = Vec::new();
+    let outcome: Result<()> = RetryPolicy::immediate(3).run(|attempt| {
+        attempts.push(attempt);
+        Err(Error {
+            kind: Kind::ApplicationFailure(format!("attempt {}", attempt)),
+        })
+    });
+    assert_eq!(attempts, vec![1, 2, 3]);
+    assert_eq!(
+        outcome.unwrap_err().kind,
+        Kind::ApplicationFailure("attempt 3".to_owned())
+    );
+}
+
+#[cfg(feature = "orgeventstore")]
+mod webhook {
+    use super::*;
+    use eventsourcing::outbox::{WebhookPublisher, CLOUDEVENTS_JSON};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A request received by the local webhook: its content type and body
+    type Received = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Starts a webhook on a free local port answering with the given statuses in turn,
+    /// then with 200
+    fn start_webhook(statuses: Vec<u16>) -> (String, Received) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let received = Received::default();
+        let log = received.clone();
+        thread::spawn(move || {
+            let mut statuses = statuses.into_iter();
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_type = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_at(line.find(':').unwrap_or(0));
+                    let value = value.trim_start_matches(':').trim();
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-type" => content_type = value.to_owned(),
+                        "content-length" => length = value.parse().unwrap(),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                log.lock()
+                    .unwrap()
+                    .push((content_type, String::from_utf8(body).unwrap()));
+                let status = statuses.next().unwrap_or(200);
+                let mut writer = stream;
+                write!(
+                    writer,
+                    "HTTP/1.1 {} Webhook\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    #[test]
+    fn webhook_receives_structured_cloud_events() {
+        let (url, received) = start_webhook(vec![503]);
+        let store = Arc::new(MemoryEventStore::new());
+        let queued = placed(&OutboxStore::new(store.clone()), 1);
+        let relay = OutboxRelay::new(store, WebhookPublisher::new(&url))
+            .with_retry_policy(RetryPolicy::immediate(2));
+
+        assert_eq!(relay.relay().unwrap(), 1);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (content_type, body) = &received[1];
+        assert_eq!(content_type, CLOUDEVENTS_JSON);
+        let evt = CloudEvent::from_json(body).unwrap();
+        assert_eq!(evt.event_id, queued[0]);
+        assert_eq!(evt.event_type, "orderevent.orderplaced");
+    }
+
+    #[test]
+    fn webhook_failures_are_reported() {
+        let (url, _) = start_webhook(vec![500]);
+        let evt: CloudEvent = OrderEvent::OrderPlaced(1).into();
+
+        let err = WebhookPublisher::new(&url).publish(&evt).unwrap_err();
+        assert_eq!(
+            err.kind,
+            Kind::ApplicationFailure(
+                "Failed to post to webhook (500 Internal Server Error)".to_owned()
+            )
+        );
+    }
+}
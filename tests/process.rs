@@ -0,0 +1,480 @@
+#![cfg(feature = "eventstore")]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::bus::CommandBus;
+use eventsourcing::eventstore::{EventStore, MemoryEventStore};
+use eventsourcing::process::{ProcessManager, ProcessRunner, Reaction};
+use eventsourcing::repository::Repository;
+use eventsourcing::{prelude::*, Error, Result};
+use std::sync::Arc;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/process")]
+enum AccountEvent {
+    AccountOpened {
+        account: String,
+    },
+    FundsDeposited {
+        account: String,
+        amount: u32,
+        transfer: Option<String>,
+    },
+    FundsWithdrawn {
+        account: String,
+        amount: u32,
+        transfer: Option<String>,
+    },
+    TransferRequested {
+        transfer: String,
+        from: String,
+        to: String,
+        amount: u32,
+    },
+}
+
+#[derive(Command)]
+enum AccountCommand {
+    Open {
+        #[aggregate_id]
+        account: String,
+    },
+    Deposit {
+        #[aggregate_id]
+        account: String,
+        amount: u32,
+        transfer: Option<String>,
+    },
+    Withdraw {
+        #[aggregate_id]
+        account: String,
+        amount: u32,
+        transfer: Option<String>,
+    },
+    Transfer {
+        #[aggregate_id]
+        from: String,
+        to: String,
+        amount: u32,
+        transfer: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct AccountState {
+    open: bool,
+    balance: u32,
+    generation: u64,
+}
+
+struct Account;
+
+impl Aggregate for Account {
+    type Event = AccountEvent;
+    type Command = AccountCommand;
+    type State = AccountState;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        let mut state = state.clone();
+        match evt {
+            AccountEvent::AccountOpened { .. } => state.open = true,
+            AccountEvent::FundsDeposited { amount, .. } => state.balance += amount,
+            AccountEvent::FundsWithdrawn { amount, .. } => state.balance -= amount,
+            AccountEvent::TransferRequested { .. } => {}
+        }
+        Ok(state)
+    }
+
+    fn handle_command(state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        if let AccountCommand::Open { account } = cmd {
+            return Ok(vec![AccountEvent::AccountOpened {
+                account: account.clone(),
+            }]);
+        }
+        if !state.open {
+            return Err(failure(format!(
+                "Account {} is not open",
+                cmd.aggregate_id()
+            )));
+        }
+        let evt = match cmd {
+            AccountCommand::Deposit {
+                account,
+                amount,
+                transfer,
+            } => AccountEvent::FundsDeposited {
+                account: account.clone(),
+                amount: *amount,
+                transfer: transfer.clone(),
+            },
+            AccountCommand::Withdraw { amount, .. } | AccountCommand::Transfer { amount, .. }
+                if *amount > state.balance =>
+            {
+                return Err(failure("Insufficient funds".to_owned()))
+            }
+            AccountCommand::Withdraw {
+                account,
+                amount,
+                transfer,
+            } => AccountEvent::FundsWithdrawn {
+                account: account.clone(),
+                amount: *amount,
+                transfer: transfer.clone(),
+            },
+            AccountCommand::Transfer {
+                from,
+                to,
+                amount,
+                transfer,
+            } => AccountEvent::TransferRequested {
+                transfer: transfer.clone(),
+                from: from.clone(),
+                to: to.clone(),
+                amount: *amount,
+            },
+            AccountCommand::Open { .. } => unreachable!(),
+        };
+        Ok(vec![evt])
+    }
+}
+
+fn failure(reason: String) -> Error {
+    Error {
+        kind: Kind::CommandFailure(reason),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/process")]
+enum TransferEvent {
+    TransferStarted {
+        from: String,
+        to: String,
+        amount: u32,
+    },
+    SourceDebited,
+    TargetCredited,
+    TransferFailed {
+        reason: String,
+    },
+    SourceRefunded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum Status {
+    #[default]
+    NotStarted,
+    Debiting,
+    Crediting,
+    Completed,
+    Refunding,
+    Refunded,
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct TransferState {
+    from: String,
+    to: String,
+    amount: u32,
+    status: Status,
+    generation: u64,
+}
+
+/// Moves funds between two accounts: debits the source, then credits the target,
+/// refunding the source if the target can't be credited
+struct Transfer;
+
+impl ProcessManager for Transfer {
+    type Event = AccountEvent;
+    type ProcessEvent = TransferEvent;
+    type State = TransferState;
+    type Command = AccountCommand;
+
+    const PROCESS_TYPE: &'static str = "transfer";
+
+    fn correlation_id(evt: &Self::Event) -> Option<String> {
+        match evt {
+            AccountEvent::TransferRequested { transfer, .. } => Some(transfer.clone()),
+            AccountEvent::FundsDeposited { transfer, .. }
+            | AccountEvent::FundsWithdrawn { transfer, .. } => transfer.clone(),
+            AccountEvent::AccountOpened { .. } => None,
+        }
+    }
+
+    fn apply_event(state: &Self::State, evt: &Self::ProcessEvent) -> Result<Self::State> {
+        let mut state = state.clone();
+        match evt {
+            TransferEvent::TransferStarted { from, to, amount } => {
+                state.from = from.clone();
+                state.to = to.clone();
+                state.amount = *amount;
+                state.status = Status::Debiting;
+            }
+            TransferEvent::SourceDebited => state.status = Status::Crediting,
+            TransferEvent::TargetCredited => state.status = Status::Completed,
+            TransferEvent::TransferFailed { .. } => state.status = Status::Refunding,
+            TransferEvent::SourceRefunded => state.status = Status::Refunded,
+        }
+        Ok(state)
+    }
+
+    fn handle_event(
+        state: &Self::State,
+        evt: &Self::Event,
+    ) -> Result<Reaction<Self::ProcessEvent, Self::Command>> {
+        let reaction = match (state.status, evt) {
+            (
+                Status::NotStarted,
+                AccountEvent::TransferRequested {
+                    transfer,
+                    from,
+                    to,
+                    amount,
+                },
+            ) => Reaction::new()
+                .record(TransferEvent::TransferStarted {
+                    from: from.clone(),
+                    to: to.clone(),
+                    amount: *amount,
+                })
+                .send(AccountCommand::Withdraw {
+                    account: from.clone(),
+                    amount: *amount,
+                    transfer: Some(transfer.clone()),
+                }),
+            (Status::Debiting, AccountEvent::FundsWithdrawn { transfer, .. }) => Reaction::new()
+                .record(TransferEvent::SourceDebited)
+                .send(AccountCommand::Deposit {
+                    account: state.to.clone(),
+                    amount: state.amount,
+                    transfer: transfer.clone(),
+                }),
+            (Status::Crediting, AccountEvent::FundsDeposited { .. }) => {
+                Reaction::new().record(TransferEvent::TargetCredited)
+            }
+            (Status::Refunding, AccountEvent::FundsDeposited { .. }) => {
+                Reaction::new().record(TransferEvent::SourceRefunded)
+            }
+            _ => Reaction::new(),
+        };
+        Ok(reaction)
+    }
+
+    fn compensate(
+        state: &Self::State,
+        cmd: &Self::Command,
+        error: &Error,
+    ) -> Result<Reaction<Self::ProcessEvent, Self::Command>> {
+        match cmd {
+            AccountCommand::Deposit { transfer, .. } if state.status == Status::Crediting => {
+                Ok(Reaction::new()
+                    .record(TransferEvent::TransferFailed {
+                        reason: error.to_string(),
+                    })
+                    .send(AccountCommand::Deposit {
+                        account: state.from.clone(),
+                        amount: state.amount,
+                        transfer: transfer.clone(),
+                    }))
+            }
+            _ => Err(Error {
+                kind: error.kind.clone(),
+            }),
+        }
+    }
+}
+
+struct Bank {
+    accounts: Repository<Account, Arc<MemoryEventStore>>,
+    bus: CommandBus,
+}
+
+fn bank() -> Bank {
+    let store = Arc::new(MemoryEventStore::new());
+    let mut bus = CommandBus::new();
+    bus.register::<Account, _>(store.clone());
+    let bank = Bank {
+        accounts: Repository::new(store),
+        bus,
+    };
+    bank.bus
+        .send(&AccountCommand::Open {
+            account: "checking".to_owned(),
+        })
+        .unwrap();
+    bank.bus
+        .send(&AccountCommand::Deposit {
+            account: "checking".to_owned(),
+            amount: 100,
+            transfer: None,
+        })
+        .unwrap();
+    bank
+}
+
+fn transfer(bank: &Bank, to: &str, amount: u32) -> Vec<CloudEvent> {
+    bank.bus
+        .send(&AccountCommand::Transfer {
+            from: "checking".to_owned(),
+            to: to.to_owned(),
+            amount,
+            transfer: "t-1".to_owned(),
+        })
+        .unwrap()
+}
+
+fn process_events(store: &MemoryEventStore) -> Vec<TransferEvent> {
+    store
+        .read_stream("transfer-t-1")
+        .unwrap()
+        .into_iter()
+        .map(|ce| TransferEvent::deserialize_by_type(&ce.event_type, &ce.data).unwrap())
+        .collect()
+}
+
+#[test]
+fn transfer_moves_funds_between_accounts() {
+    let bank = bank();
+    bank.bus
+        .send(&AccountCommand::Open {
+            account: "savings".to_owned(),
+        })
+        .unwrap();
+    let runner: ProcessRunner<Transfer, _> = ProcessRunner::new(MemoryEventStore::new());
+
+    runner
+        .handle_all(&bank.bus, transfer(&bank, "savings", 30))
+        .unwrap();
+
+    assert_eq!(bank.accounts.load("checking").unwrap().balance, 70);
+    assert_eq!(bank.accounts.load("savings").unwrap().balance, 30);
+    let state = runner.load("t-1").unwrap();
+    assert_eq!(state.status, Status::Completed);
+    assert_eq!(state.generation(), 3);
+    assert_eq!(
+        process_events(runner.store()),
+        vec![
+            TransferEvent::TransferStarted {
+                from: "checking".to_owned(),
+                to: "savings".to_owned(),
+                amount: 30,
+            },
+            TransferEvent::SourceDebited,
+            TransferEvent::TargetCredited,
+        ]
+    );
+}
+
+#[test]
+fn failed_steps_are_compensated() {
+    let bank = bank();
+    let runner: ProcessRunner<Transfer, _> = ProcessRunner::new(MemoryEventStore::new());
+
+    runner
+        .handle_all(&bank.bus, transfer(&bank, "closed", 30))
+        .unwrap();
+
+    assert_eq!(bank.accounts.load("checking").unwrap().balance, 100);
+    assert!(bank
+        .accounts
+        .store()
+        .read_stream("closed")
+        .unwrap()
+        .is_empty());
+    assert_eq!(runner.load("t-1").unwrap().status, Status::Refunded);
+    assert_eq!(
+        process_events(runner.store())[1..],
+        [
+            TransferEvent::SourceDebited,
+            TransferEvent::TransferFailed {
+                reason: "Account closed is not open".to_owned(),
+            },
+            TransferEvent::SourceRefunded,
+        ]
+    );
+}
+
+#[test]
+fn uncompensated_failures_record_nothing() {
+    let bank = bank();
+    let runner: ProcessRunner<Transfer, _> = ProcessRunner::new(MemoryEventStore::new());
+    let requested = AccountEvent::TransferRequested {
+        transfer: "t-1".to_owned(),
+        from: "savings".to_owned(),
+        to: "checking".to_owned(),
+        amount: 30,
+    };
+
+    let err = runner.react(&bank.bus, &requested).unwrap_err();
+    assert_eq!(err.to_string(), "Account savings is not open");
+    assert!(process_events(runner.store()).is_empty());
+    assert_eq!(runner.load("t-1").unwrap().status, Status::NotStarted);
+
+    bank.bus
+        .send(&AccountCommand::Open {
+            account: "savings".to_owned(),
+        })
+        .unwrap();
+    bank.bus
+        .send(&AccountCommand::Deposit {
+            account: "savings".to_owned(),
+            amount: 50,
+            transfer: None,
+        })
+        .unwrap();
+    let emitted = runner.react(&bank.bus, &requested).unwrap();
+    assert_eq!(emitted[0].event_type, "accountevent.fundswithdrawn");
+    assert_eq!(runner.load("t-1").unwrap().status, Status::Debiting);
+}
+
+#[test]
+fn process_state_survives_a_restart() {
+    let bank = bank();
+    bank.bus
+        .send(&AccountCommand::Open {
+            account: "savings".to_owned(),
+        })
+        .unwrap();
+    let store = Arc::new(MemoryEventStore::new());
+
+    let emitted = {
+        let runner: ProcessRunner<Transfer, _> = ProcessRunner::new(store.clone());
+        let requested = transfer(&bank, "savings", 30);
+        runner.handle(&bank.bus, &requested[0]).unwrap()
+    };
+    assert_eq!(emitted[0].event_type, "accountevent.fundswithdrawn");
+
+    let runner: ProcessRunner<Transfer, _> = ProcessRunner::new(store);
+    assert_eq!(runner.load("t-1").unwrap().status, Status::Debiting);
+    runner.handle_all(&bank.bus, emitted).unwrap();
+    assert_eq!(runner.load("t-1").unwrap().status, Status::Completed);
+    assert_eq!(bank.accounts.load("savings").unwrap().balance, 30);
+}
+
+#[test]
+fn uncorrelated_events_are_ignored() {
+    let bank = bank();
+    let runner: ProcessRunner<Transfer, _> = ProcessRunner::new(MemoryEventStore::new());
+    let opened = bank
+        .bus
+        .send(&AccountCommand::Open {
+            account: "savings".to_owned(),
+        })
+        .unwrap();
+
+    assert!(runner.handle(&bank.bus, &opened[0]).unwrap().is_empty());
+    assert!(runner
+        .store()
+        .get_all("transferevent.transferstarted")
+        .unwrap()
+        .is_empty());
+}
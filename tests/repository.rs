@@ -0,0 +1,478 @@
+#![cfg(feature = "eventstore")]
+extern crate chrono;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use eventsourcing::clock::{Clock, ManualClock};
+use eventsourcing::cloudevents::TimeDimension;
+use eventsourcing::eventstore::{EventStore, ExpectedVersion, MemoryEventStore};
+use eventsourcing::repository::Repository;
+use eventsourcing::snapshot::{MemorySnapshotStore, Snapshot, SnapshotStore};
+use eventsourcing::{prelude::*, Error, Result};
+use std::sync::Arc;
+use std::thread;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/repository")]
+enum AccountEvent {
+    FundsDeposited { amount: u32, at: DateTime<Utc> },
+    FundsConverted { cents: u32 },
+}
+
+enum AccountCommand {
+    Deposit(u32),
+    Convert { amount: u32, currency: String },
+}
+
+#[derive(Command)]
+struct Deposit {
+    #[aggregate_id]
+    account: String,
+    amount: u32,
+    #[effective_time]
+    effective: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/repository")]
+struct Deposited {
+    amount: u32,
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct LedgerState {
+    total: u32,
+    generation: u64,
+}
+
+struct Ledger;
+
+impl Aggregate for Ledger {
+    type Event = Deposited;
+    type Command = Deposit;
+    type State = LedgerState;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        Ok(LedgerState {
+            total: state.total + evt.amount,
+            ..state.clone()
+        })
+    }
+
+    fn handle_command(_state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        Ok(vec![Deposited { amount: cmd.amount }])
+    }
+}
+
+/// A store that another writer appends a deposit to right after every read of a stream
+struct RacingStore {
+    store: MemoryEventStore,
+}
+
+impl EventStore for RacingStore {
+    fn append(&self, evt: impl Event, stream: &str) -> Result<CloudEvent> {
+        self.store.append(evt, stream)
+    }
+
+    fn append_batch<E: Event>(
+        &self,
+        evts: Vec<E>,
+        stream: &str,
+        expected: ExpectedVersion,
+    ) -> Result<Vec<CloudEvent>> {
+        self.store.append_batch(evts, stream, expected)
+    }
+
+    fn read_stream(&self, stream: &str) -> Result<Vec<CloudEvent>> {
+        self.store.read_stream(stream)
+    }
+
+    fn read_stream_numbered(&self, stream: &str) -> Result<Vec<(u64, CloudEvent)>> {
+        let evts = self.store.read_stream_numbered(stream)?;
+        self.store.append(Deposited { amount: 1 }, stream)?;
+        Ok(evts)
+    }
+}
+
+#[cfg(feature = "orgeventstore")]
+#[derive(Dispatcher)]
+#[aggregate(Ledger)]
+struct LedgerDispatcher;
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct AccountState {
+    balance: u32,
+    last_deposit: Option<DateTime<Utc>>,
+    generation: u64,
+}
+
+/// Dependencies of the account's command handling
+struct BankContext<'a> {
+    clock: &'a dyn Clock,
+    euro_cents_per_dollar: u32,
+}
+
+struct Account;
+
+impl Aggregate for Account {
+    type Event = AccountEvent;
+    type Command = AccountCommand;
+    type State = AccountState;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        Ok(match *evt {
+            AccountEvent::FundsDeposited { amount, at } => AccountState {
+                balance: state.balance + amount,
+                last_deposit: Some(at),
+                ..state.clone()
+            },
+            AccountEvent::FundsConverted { cents } => AccountState {
+                balance: state.balance + cents,
+                ..state.clone()
+            },
+        })
+    }
+
+    fn handle_command(_state: &Self::State, _cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        Err(Error {
+            kind: Kind::CommandFailure("Commands need a context".to_owned()),
+        })
+    }
+}
+
+impl<'a> CommandHandler<BankContext<'a>> for Account {
+    fn handle_command_with(
+        _state: &Self::State,
+        cmd: &Self::Command,
+        ctx: &BankContext<'a>,
+    ) -> Result<Vec<Self::Event>> {
+        match *cmd {
+            AccountCommand::Deposit(amount) => Ok(vec![AccountEvent::FundsDeposited {
+                amount,
+                at: ctx.clock.now(),
+            }]),
+            AccountCommand::Convert {
+                amount,
+                ref currency,
+            } if currency == "EUR" => Ok(vec![AccountEvent::FundsConverted {
+                cents: amount * ctx.euro_cents_per_dollar,
+            }]),
+            AccountCommand::Convert { .. } => Err(Error {
+                kind: Kind::CommandFailure("Unknown currency".to_owned()),
+            }),
+        }
+    }
+}
+
+fn context(clock: &ManualClock) -> BankContext<'_> {
+    BankContext {
+        clock,
+        euro_cents_per_dollar: 92,
+    }
+}
+
+#[test]
+fn context_is_threaded_through_the_repository() {
+    let clock = ManualClock::new(Utc.with_ymd_and_hms(2020, 3, 1, 9, 0, 0).unwrap());
+    let repo: Repository<Account, _> = Repository::new(MemoryEventStore::new());
+
+    repo.execute_with("acct-1", &AccountCommand::Deposit(100), &context(&clock))
+        .unwrap();
+    clock.advance(Duration::hours(2));
+    let stored = repo
+        .execute_with(
+            "acct-1",
+            &AccountCommand::Convert {
+                amount: 2,
+                currency: "EUR".to_owned(),
+            },
+            &context(&clock),
+        )
+        .unwrap();
+    assert_eq!(stored[0].event_type, "accountevent.fundsconverted");
+    repo.execute_with("acct-1", &AccountCommand::Deposit(5), &context(&clock))
+        .unwrap();
+
+    let state = repo.load("acct-1").unwrap();
+    assert_eq!(state.balance, 289);
+    assert_eq!(state.generation, 3);
+    assert_eq!(
+        state.last_deposit,
+        Some(Utc.with_ymd_and_hms(2020, 3, 1, 11, 0, 0).unwrap())
+    );
+    assert_eq!(repo.store().read_stream("acct-1").unwrap().len(), 3);
+}
+
+#[test]
+fn command_failures_append_nothing() {
+    let clock = ManualClock::default();
+    let repo: Repository<Account, _> = Repository::new(MemoryEventStore::new());
+    let err = repo
+        .execute_with(
+            "acct-1",
+            &AccountCommand::Convert {
+                amount: 2,
+                currency: "GBP".to_owned(),
+            },
+            &context(&clock),
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::CommandFailure("Unknown currency".to_owned())
+    );
+    assert!(repo.store().read_stream("acct-1").unwrap().is_empty());
+}
+
+#[test]
+fn aggregates_needing_a_context_reject_commands_without_one() {
+    let repo: Repository<Account, _> = Repository::new(MemoryEventStore::new());
+    let err = repo
+        .execute("acct-1", &AccountCommand::Deposit(1))
+        .unwrap_err();
+    match err.kind {
+        Kind::CommandFailure(_) => {}
+        other => panic!("expected a command failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn commands_choose_their_stream() {
+    let repo: Repository<Ledger, _> = Repository::new(MemoryEventStore::new());
+    for (account, amount) in &[("acct-1", 5), ("acct-2", 7), ("acct-1", 3)] {
+        repo.execute_command(&Deposit {
+            account: account.to_string(),
+            amount: *amount,
+            effective: None,
+        })
+        .unwrap();
+    }
+    assert_eq!(repo.load("acct-1").unwrap().total, 8);
+    assert_eq!(repo.load("acct-2").unwrap().total, 7);
+}
+
+fn ledger(amounts: &[u32]) -> (Repository<Ledger, MemoryEventStore>, Vec<CloudEvent>) {
+    let repo: Repository<Ledger, _> = Repository::new(MemoryEventStore::new());
+    let mut stored = Vec::new();
+    for amount in amounts {
+        // keep the event times of consecutive deposits apart
+        thread::sleep(std::time::Duration::from_millis(2));
+        stored.extend(
+            repo.execute_command(&Deposit {
+                account: "acct-1".to_owned(),
+                amount: *amount,
+                effective: None,
+            })
+            .unwrap(),
+        );
+    }
+    (repo, stored)
+}
+
+#[test]
+fn state_can_be_loaded_as_of_a_time() {
+    let (repo, stored) = ledger(&[5, 7, 3]);
+
+    let as_of = |time| repo.load_as_of("acct-1", time).unwrap();
+    assert_eq!(
+        as_of(stored[0].event_time.unwrap() - Duration::seconds(1)).total,
+        0
+    );
+    assert_eq!(as_of(stored[0].event_time.unwrap()).total, 5);
+    assert_eq!(as_of(stored[1].event_time.unwrap()).generation(), 2);
+    assert_eq!(
+        as_of(stored[2].event_time.unwrap() - Duration::microseconds(1)).total,
+        12
+    );
+    assert_eq!(as_of(Utc::now()).total, 15);
+}
+
+#[test]
+fn state_can_be_loaded_at_a_generation() {
+    let (repo, _) = ledger(&[5, 7, 3]);
+
+    assert_eq!(repo.load_at_generation("acct-1", 0).unwrap().total, 0);
+    assert_eq!(repo.load_at_generation("acct-1", 2).unwrap().total, 12);
+    assert_eq!(
+        repo.load_at_generation("acct-1", 3).unwrap().generation(),
+        3
+    );
+    let err = repo.load_at_generation("acct-1", 4).unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::ValidationFailure("Stream acct-1 has not reached generation 4".to_owned())
+    );
+}
+
+#[test]
+fn loads_start_from_suitable_snapshots() {
+    let (repo, stored) = ledger(&[5, 7, 3]);
+    let repo = repo.with_snapshots(MemorySnapshotStore::new());
+    assert_eq!(repo.snapshot("acct-1").unwrap().total, 15);
+    assert_eq!(
+        repo.snapshots().snapshots("acct-1").unwrap()[0].generation,
+        3
+    );
+
+    // a doctored snapshot shows which loads start from it
+    repo.snapshots()
+        .save(
+            "acct-1",
+            Snapshot {
+                state: LedgerState {
+                    total: 1000,
+                    generation: 2,
+                },
+                generation: 2,
+                event_time: stored[1].event_time,
+            },
+        )
+        .unwrap();
+    repo.snapshots()
+        .save(
+            "acct-1",
+            Snapshot {
+                state: LedgerState::default(),
+                generation: 3,
+                event_time: stored[2].event_time,
+            },
+        )
+        .unwrap();
+    repo.execute_command(&Deposit {
+        account: "acct-1".to_owned(),
+        amount: 1,
+        effective: None,
+    })
+    .unwrap();
+
+    assert_eq!(repo.load_at_generation("acct-1", 1).unwrap().total, 5);
+    assert_eq!(repo.load_at_generation("acct-1", 2).unwrap().total, 1000);
+    assert_eq!(
+        repo.load_as_of(
+            "acct-1",
+            stored[2].event_time.unwrap() - Duration::microseconds(1)
+        )
+        .unwrap()
+        .total,
+        1000
+    );
+    assert_eq!(repo.load("acct-1").unwrap().total, 1);
+}
+
+#[test]
+fn truncated_streams_load_from_the_snapshot_covering_them() {
+    let store = Arc::new(MemoryEventStore::new());
+    let repo: Repository<Ledger, _, _> =
+        Repository::new(store.clone()).with_snapshots(MemorySnapshotStore::new());
+    let deposit = |amount| {
+        repo.execute_command(&Deposit {
+            account: "acct-1".to_owned(),
+            amount,
+            effective: None,
+        })
+        .unwrap()
+    };
+    for amount in &[5, 7, 3, 2] {
+        deposit(*amount);
+    }
+    repo.snapshot("acct-1").unwrap();
+    deposit(1);
+    store.truncate_before("acct-1", 4).unwrap();
+    assert_eq!(store.read_stream("acct-1").unwrap().len(), 1);
+
+    let state = repo.load("acct-1").unwrap();
+    assert_eq!(state.total, 18);
+    assert_eq!(state.generation, 5);
+    assert_eq!(repo.load_at_generation("acct-1", 4).unwrap().total, 17);
+    assert!(repo.load_at_generation("acct-1", 3).is_err());
+
+    let unsnapshotted: Repository<Ledger, _> = Repository::new(store);
+    let err = unsnapshotted.load("acct-1").unwrap_err();
+    assert!(err.to_string().contains("position 4"), "{}", err);
+}
+
+#[test]
+fn commands_can_backdate_their_events() {
+    let repo: Repository<Ledger, _> = Repository::new(MemoryEventStore::new());
+    let march = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+    let stored = repo
+        .execute_command(&Deposit {
+            account: "acct-1".to_owned(),
+            amount: 5,
+            effective: Some(march),
+        })
+        .unwrap();
+    assert_eq!(stored[0].effective_time(), Some(march));
+    assert!(stored[0].event_time.unwrap() > march);
+
+    let in_march = repo
+        .store()
+        .read_stream_range_by(
+            "acct-1",
+            TimeDimension::Effective,
+            march,
+            march + Duration::days(31),
+        )
+        .unwrap();
+    assert_eq!(in_march.len(), 1);
+    let recorded_in_march = repo
+        .store()
+        .read_stream_range("acct-1", march, march + Duration::days(31))
+        .unwrap();
+    assert!(recorded_in_march.is_empty());
+}
+
+#[test]
+fn commands_fail_when_the_stream_moved_on_since_loading() {
+    let repo: Repository<Ledger, _> = Repository::new(RacingStore {
+        store: MemoryEventStore::new(),
+    });
+    let deposit = |amount| Deposit {
+        account: "acct-1".to_owned(),
+        amount,
+        effective: None,
+    };
+    for _ in 0..2 {
+        let err = repo.execute_command(&deposit(5)).unwrap_err();
+        assert!(
+            err.to_string().contains("Wrong expected version"),
+            "{}",
+            err
+        );
+    }
+    let stored = repo.store().store.read_stream("acct-1").unwrap();
+    assert_eq!(stored.len(), 2);
+    assert!(stored.iter().all(|evt| evt.data["amount"] == 1));
+}
+
+#[cfg(feature = "orgeventstore")]
+#[test]
+fn dispatched_events_take_the_command_effective_time() {
+    let store = MemoryEventStore::new();
+    let march = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+    let deposit = Deposit {
+        account: "acct-1".to_owned(),
+        amount: 5,
+        effective: Some(march),
+    };
+    let state = LedgerState::default();
+
+    let dispatched = LedgerDispatcher::dispatch(&state, &deposit, &store, "acct-1");
+    let with_context = LedgerDispatcher::dispatch_with(&state, &deposit, &(), &store, "acct-1");
+    let by_command = LedgerDispatcher::dispatch_command(&state, &deposit, &store);
+    for res in dispatched.iter().chain(&with_context).chain(&by_command) {
+        assert_eq!(res.as_ref().unwrap().effective_time(), Some(march));
+    }
+    assert_eq!(store.read_stream("acct-1").unwrap().len(), 3);
+}
@@ -0,0 +1,193 @@
+#![cfg(feature = "eventstore")]
+extern crate chrono;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use chrono::Duration;
+use eventsourcing::bus::CommandBus;
+use eventsourcing::clock::ManualClock;
+use eventsourcing::eventstore::MemoryEventStore;
+use eventsourcing::repository::Repository;
+use eventsourcing::scheduler::Scheduler;
+use eventsourcing::{prelude::*, Result};
+use std::sync::Arc;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Command)]
+enum OrderCommand {
+    PlaceOrder(#[aggregate_id] String),
+    CancelOrder(#[aggregate_id] String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/scheduler")]
+enum OrderEvent {
+    OrderPlaced,
+    OrderCancelled,
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct OrderState {
+    cancelled: bool,
+    generation: u64,
+}
+
+struct Order;
+
+impl Aggregate for Order {
+    type Event = OrderEvent;
+    type Command = OrderCommand;
+    type State = OrderState;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        Ok(OrderState {
+            cancelled: matches!(evt, OrderEvent::OrderCancelled),
+            ..state.clone()
+        })
+    }
+
+    fn handle_command(_state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        Ok(match cmd {
+            OrderCommand::PlaceOrder(_) => vec![OrderEvent::OrderPlaced],
+            OrderCommand::CancelOrder(_) => vec![OrderEvent::OrderCancelled],
+        })
+    }
+}
+
+fn cancel(order: &str) -> OrderCommand {
+    OrderCommand::CancelOrder(order.to_owned())
+}
+
+fn bus(orders: Arc<MemoryEventStore>) -> CommandBus {
+    let mut bus = CommandBus::new();
+    bus.register::<Order, _>(orders);
+    bus
+}
+
+#[test]
+fn commands_fire_once_they_are_due() {
+    let orders = Arc::new(MemoryEventStore::new());
+    let bus = bus(orders.clone());
+    let clock = ManualClock::default();
+    let scheduler = Scheduler::new(MemoryEventStore::new(), &clock);
+    bus.send(&OrderCommand::PlaceOrder("order-1".to_owned()))
+        .unwrap();
+    let id = scheduler
+        .schedule_in(&cancel("order-1"), Duration::days(3))
+        .unwrap();
+
+    clock.advance(Duration::days(2));
+    assert!(scheduler.fire_due(|cmd| bus.send(cmd)).unwrap().is_empty());
+    assert_eq!(scheduler.pending().unwrap()[0].id, id);
+
+    clock.advance(Duration::days(1));
+    let fired = scheduler.fire_due(|cmd| bus.send(cmd)).unwrap();
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].id, id);
+    assert_eq!(
+        fired[0].outcome.as_ref().unwrap()[0].event_type,
+        "orderevent.ordercancelled"
+    );
+    let order: Repository<Order, _> = Repository::new(orders);
+    assert!(order.load("order-1").unwrap().cancelled);
+
+    clock.advance(Duration::days(1));
+    assert!(scheduler.fire_due(|cmd| bus.send(cmd)).unwrap().is_empty());
+    assert!(scheduler.pending().unwrap().is_empty());
+}
+
+#[test]
+fn cancelled_commands_never_fire() {
+    let bus = bus(Arc::new(MemoryEventStore::new()));
+    let clock = ManualClock::default();
+    let scheduler = Scheduler::new(MemoryEventStore::new(), &clock);
+    let id = scheduler
+        .schedule_in(&cancel("order-1"), Duration::days(3))
+        .unwrap();
+
+    scheduler.cancel(&id).unwrap();
+    clock.advance(Duration::days(3));
+    assert!(scheduler.fire_due(|cmd| bus.send(cmd)).unwrap().is_empty());
+
+    let err = scheduler.cancel(&id).unwrap_err();
+    assert_eq!(
+        err.kind,
+        Kind::ValidationFailure(format!("No command with ID {} is pending", id))
+    );
+}
+
+#[test]
+fn scheduled_commands_are_persisted_in_due_order() {
+    let store = Arc::new(MemoryEventStore::new());
+    let clock = ManualClock::default();
+    {
+        let scheduler: Scheduler<OrderCommand, _, _> = Scheduler::new(store.clone(), &clock);
+        scheduler
+            .schedule_in(&cancel("late"), Duration::hours(5))
+            .unwrap();
+        scheduler
+            .schedule_in(&cancel("early"), Duration::hours(1))
+            .unwrap();
+    }
+
+    let scheduler: Scheduler<OrderCommand, _, _> = Scheduler::new(store, &clock);
+    let pending: Vec<OrderCommand> = scheduler
+        .pending()
+        .unwrap()
+        .into_iter()
+        .map(|scheduled| scheduled.command)
+        .collect();
+    assert_eq!(pending, vec![cancel("early"), cancel("late")]);
+}
+
+#[test]
+fn failed_commands_are_not_retried() {
+    let clock = ManualClock::default();
+    let scheduler = Scheduler::new(MemoryEventStore::new(), &clock);
+    scheduler
+        .schedule_in(&cancel("order-1"), Duration::minutes(1))
+        .unwrap();
+
+    clock.advance(Duration::minutes(1));
+    let bus = CommandBus::new();
+    let fired = scheduler.fire_due(|cmd| bus.send(cmd)).unwrap();
+    assert_eq!(
+        fired[0].outcome.as_ref().unwrap_err().kind,
+        Kind::CommandFailure(
+            "No aggregate is registered for command ordercommand.cancelorder".to_owned()
+        )
+    );
+    assert!(scheduler.pending().unwrap().is_empty());
+    assert_eq!(
+        scheduler
+            .store()
+            .get_all("schedule.commandfailed")
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn commands_fire_through_a_repository() {
+    let orders: Repository<Order, _> = Repository::new(MemoryEventStore::new());
+    let clock = ManualClock::default();
+    let scheduler = Scheduler::new(MemoryEventStore::new(), &clock);
+    scheduler
+        .schedule_in(&cancel("order-1"), Duration::hours(1))
+        .unwrap();
+
+    clock.advance(Duration::hours(1));
+    let fired = scheduler
+        .fire_due(|cmd| orders.execute_command(cmd))
+        .unwrap();
+    assert_eq!(fired.len(), 1);
+    assert!(orders.load("order-1").unwrap().cancelled);
+}
@@ -0,0 +1,205 @@
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::schema::{self, EventSchema};
+use eventsourcing::{prelude::*, Result};
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event, EventSchema)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/schema")]
+enum AccountEvent {
+    Opened {
+        owner: String,
+        overdraft: Option<u32>,
+    },
+    FundsDeposited(u32),
+    FundsMoved(String, String, u32),
+    #[event_type_version("2.0")]
+    #[event_source("events://github.com/pholactery/eventsourcing/tests/schema/closures")]
+    Closed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event, EventSchema)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/schema")]
+struct Audited {
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event, EventSchema)]
+#[event_delegate]
+enum LedgerEvent {
+    Audit(Audited),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event, EventSchema)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/schema")]
+#[serde(rename_all = "snake_case")]
+enum CustomerEvent {
+    #[serde(rename_all = "camelCase")]
+    CustomerRegistered {
+        #[serde(rename = "mail")]
+        email_address: String,
+        display_name: String,
+        #[serde(skip)]
+        session: Option<String>,
+        #[serde(default)]
+        tier: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        referrer: Option<String>,
+    },
+    #[serde(rename = "left")]
+    CustomerLeft,
+}
+
+#[derive(Debug, Clone, Default, AggregateState)]
+struct AccountState {
+    generation: u64,
+}
+
+struct Account;
+
+impl Aggregate for Account {
+    type Event = AccountEvent;
+    type Command = ();
+    type State = AccountState;
+
+    fn apply_event(state: &Self::State, _evt: &Self::Event) -> Result<Self::State> {
+        Ok(state.clone())
+    }
+
+    fn handle_command(_state: &Self::State, _cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        Ok(vec![AccountEvent::Closed])
+    }
+}
+
+#[test]
+fn schemas_follow_the_event_catalog() {
+    let types: Vec<String> = AccountEvent::event_schemas()
+        .into_iter()
+        .map(|schema| schema.event_type)
+        .collect();
+    assert_eq!(types, AccountEvent::EVENT_TYPES);
+}
+
+#[test]
+fn schema_documents_describe_variant_payloads() {
+    let opened = AccountEvent::event_schema("accountevent.opened").unwrap();
+    assert_eq!(
+        opened.schema_uri(),
+        "events://github.com/pholactery/eventsourcing/tests/schema/schemas/accountevent.opened/1.0.json"
+    );
+    let doc = opened.to_json_schema();
+    assert_eq!(doc["$id"], json!(opened.schema_uri()));
+    assert_eq!(doc["x-event-type-version"], json!("1.0"));
+    assert_eq!(
+        doc["x-event-source"],
+        json!("events://github.com/pholactery/eventsourcing/tests/schema")
+    );
+    assert_eq!(doc["required"], json!(["Opened"]));
+    let payload = &doc["properties"]["Opened"];
+    assert_eq!(payload["properties"]["owner"], json!({ "type": "string" }));
+    assert_eq!(payload["required"], json!(["owner"]));
+
+    let deposited = AccountEvent::event_schema("accountevent.fundsdeposited").unwrap();
+    assert_eq!(
+        deposited.data["properties"]["FundsDeposited"],
+        json!({ "type": "integer", "minimum": 0 })
+    );
+
+    let moved = AccountEvent::event_schema("accountevent.fundsmoved").unwrap();
+    assert_eq!(moved.data["properties"]["FundsMoved"]["maxItems"], json!(3));
+
+    let closed = AccountEvent::event_schema("accountevent.closed").unwrap();
+    assert_eq!(closed.event_type_version, "2.0");
+    assert_eq!(closed.data, json!({ "const": "Closed" }));
+}
+
+#[test]
+fn struct_and_delegate_schemas() {
+    let audited = Audited::event_schemas().remove(0);
+    assert_eq!(audited.event_type, "audited");
+    assert_eq!(
+        audited.data["properties"]["tags"],
+        json!({ "type": "array", "items": { "type": "string" } })
+    );
+
+    let ledger = LedgerEvent::event_schemas().remove(0);
+    assert_eq!(ledger.event_type, "audited");
+    assert_eq!(ledger.data["properties"]["Audit"], audited.data);
+}
+
+#[test]
+fn schemas_follow_serde_renames_and_skips() {
+    let registered = CustomerEvent::CustomerRegistered {
+        email_address: "ada@example.com".to_owned(),
+        display_name: "Ada".to_owned(),
+        session: Some("s-1".to_owned()),
+        tier: 2,
+        referrer: None,
+    };
+    let data = serde_json::to_value(&registered).unwrap();
+    let schema = CustomerEvent::event_schema("customerevent.customerregistered").unwrap();
+    assert_eq!(schema.data["required"], json!(["customer_registered"]));
+
+    let payload = &schema.data["properties"]["customer_registered"];
+    let serialized = data["customer_registered"].as_object().unwrap();
+    let mut properties: Vec<&String> = payload["properties"].as_object().unwrap().keys().collect();
+    properties.sort();
+    assert_eq!(properties, vec!["displayName", "mail", "referrer", "tier"]);
+    assert!(serialized.keys().all(|key| properties.contains(&key)));
+    assert_eq!(payload["required"], json!(["mail", "displayName"]));
+    for required in payload["required"].as_array().unwrap() {
+        assert!(serialized.contains_key(required.as_str().unwrap()));
+    }
+
+    let left = CustomerEvent::event_schema("customerevent.customerleft").unwrap();
+    assert_eq!(left.data, json!({ "const": "left" }));
+    assert_eq!(
+        serde_json::to_value(CustomerEvent::CustomerLeft).unwrap(),
+        json!("left")
+    );
+}
+
+#[test]
+fn async_api_catalog_lists_aggregate_events() {
+    let catalog = schema::async_api::<Account>("Accounts", "1.2.0");
+    assert_eq!(catalog["asyncapi"], json!(schema::ASYNC_API_VERSION));
+    assert_eq!(catalog["info"]["version"], json!("1.2.0"));
+    let channel = &catalog["channels"]["events://github.com/pholactery/eventsourcing/tests/schema"];
+    assert_eq!(
+        channel["subscribe"]["message"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .len(),
+        3
+    );
+    let message = &catalog["components"]["messages"]["accountevent.closed"];
+    assert_eq!(message["x-event-type-version"], json!("2.0"));
+    assert_eq!(
+        message["payload"]["$id"],
+        json!("events://github.com/pholactery/eventsourcing/tests/schema/closures/schemas/accountevent.closed/2.0.json")
+    );
+}
+
+#[cfg(feature = "eventstore")]
+#[test]
+fn cloud_events_reference_their_schema() {
+    let ce = schema::cloud_event(AccountEvent::FundsDeposited(10));
+    assert_eq!(
+        ce.data_schema.as_ref().unwrap(),
+        &AccountEvent::event_schema("accountevent.fundsdeposited")
+            .unwrap()
+            .schema_uri()
+    );
+    ce.validate().unwrap();
+}
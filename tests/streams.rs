@@ -0,0 +1,139 @@
+#![cfg(feature = "eventstore")]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::eventstore::{Deletion, EventStore, MemoryEventStore, Position, StreamName};
+use eventsourcing::{CloudEvent, Event, Kind, Result};
+use std::sync::mpsc::TryRecvError;
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/streams")]
+enum AccountEvent {
+    FundsDeposited(u32),
+}
+
+#[test]
+fn stream_names_split_at_the_first_separator() {
+    let name: StreamName = "account-123".parse().unwrap();
+    assert_eq!(name.category(), "account");
+    assert_eq!(name.id(), Some("123"));
+
+    let name = StreamName::parse("account-7f3c-42").unwrap();
+    assert_eq!(name.category(), "account");
+    assert_eq!(name.id(), Some("7f3c-42"));
+    assert_eq!(name.to_string(), "account-7f3c-42");
+
+    let category = StreamName::parse("account").unwrap();
+    assert_eq!(category, StreamName::for_category("account").unwrap());
+    assert_eq!(category.id(), None);
+    assert_eq!(category.to_string(), "account");
+
+    assert_eq!(
+        StreamName::new("account", "123").unwrap().to_string(),
+        "account-123"
+    );
+}
+
+#[test]
+fn malformed_stream_names_are_rejected() {
+    for name in &["", "-123", "account-"] {
+        match StreamName::parse(name) {
+            Err(e) => match e.kind {
+                Kind::ValidationFailure(_) => {}
+                other => panic!("unexpected error kind {:?}", other),
+            },
+            Ok(parsed) => panic!("{:?} parsed as {:?}", name, parsed),
+        }
+    }
+    assert!(StreamName::new("savings-account", "1").is_err());
+    assert!(StreamName::new("account", "").is_err());
+    assert!(StreamName::for_category("").is_err());
+}
+
+#[test]
+fn category_reads_skip_hidden_events_and_bare_category_streams() {
+    let store = MemoryEventStore::new();
+    store
+        .append(AccountEvent::FundsDeposited(1), "account-1")
+        .unwrap();
+    store
+        .append(AccountEvent::FundsDeposited(2), "account-2")
+        .unwrap();
+    store
+        .append(AccountEvent::FundsDeposited(3), "account")
+        .unwrap();
+    store.delete_stream("account-1", Deletion::Soft).unwrap();
+
+    let read = store.read_category("account").unwrap();
+    assert_eq!(read.len(), 1);
+    assert_eq!(read[0].stream, "account-2");
+    assert_eq!(
+        read[0].position,
+        store.read_all(Position::START, 1).unwrap()[0].position
+    );
+}
+
+#[test]
+fn category_subscriptions_catch_up_then_follow() {
+    let store = MemoryEventStore::new();
+    store
+        .append(AccountEvent::FundsDeposited(1), "account-1")
+        .unwrap();
+    store
+        .append(AccountEvent::FundsDeposited(2), "account-2")
+        .unwrap();
+
+    let first = store.read_all(Position::START, 1).unwrap()[0].position;
+    let all = store.subscribe_to_category("account", Position::START);
+    let later = store.subscribe_to_category("account", first);
+    store
+        .append(AccountEvent::FundsDeposited(3), "customer-1")
+        .unwrap();
+    store
+        .append(AccountEvent::FundsDeposited(4), "account-1")
+        .unwrap();
+
+    let received: Vec<_> = all.try_iter().collect();
+    let streams: Vec<&str> = received
+        .iter()
+        .map(|recorded| recorded.stream.as_str())
+        .collect();
+    assert_eq!(streams, vec!["account-1", "account-2", "account-1"]);
+    let positions: Vec<Position> = later.try_iter().map(|recorded| recorded.position).collect();
+    assert_eq!(positions, vec![received[1].position, received[2].position]);
+    assert_eq!(all.try_recv().unwrap_err(), TryRecvError::Empty);
+}
+
+/// A store that can only write, as every store could before streams were read back
+struct WriteOnlyStore;
+
+impl EventStore for WriteOnlyStore {
+    fn append(&self, evt: impl Event, _stream: &str) -> Result<CloudEvent> {
+        Ok(evt.into())
+    }
+}
+
+#[test]
+fn write_only_stores_fail_reads() {
+    let store = WriteOnlyStore;
+    store
+        .append(AccountEvent::FundsDeposited(1), "account-1")
+        .unwrap();
+    for outcome in [
+        store.read_stream("account-1").map(|_| ()),
+        store.read_stream_numbered("account-1").map(|_| ()),
+        store.read_category("account").map(|_| ()),
+    ] {
+        match outcome {
+            Err(e) => assert!(matches!(e.kind, Kind::StoreFailure(_)), "{}", e),
+            Ok(()) => panic!("a write-only store read events"),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+#![cfg(feature = "test-util")]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate eventsourcing;
+extern crate serde_json;
+#[macro_use]
+extern crate eventsourcing_derive;
+
+use eventsourcing::{prelude::*, testing::AggregateTest, Error, Result};
+
+const DOMAIN_VERSION: &str = "1.0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Event)]
+#[event_type_version(DOMAIN_VERSION)]
+#[event_source("events://github.com/pholactery/eventsourcing/tests/testing")]
+enum BankEvent {
+    FundsDeposited(String, u32),
+    FundsWithdrawn(String, u32),
+}
+
+enum BankCommand {
+    DepositFunds(String, u32),
+    WithdrawFunds(String, u32),
+}
+
+#[derive(Debug, Clone, Default)]
+struct AccountData {
+    balance: u32,
+    generation: u64,
+}
+
+impl AggregateState for AccountData {
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+struct Account;
+
+impl Aggregate for Account {
+    type Event = BankEvent;
+    type Command = BankCommand;
+    type State = AccountData;
+
+    fn apply_event(state: &Self::State, evt: &Self::Event) -> Result<Self::State> {
+        let balance = match *evt {
+            BankEvent::FundsDeposited(_, amt) => state.balance + amt,
+            BankEvent::FundsWithdrawn(_, amt) if amt <= state.balance => state.balance - amt,
+            BankEvent::FundsWithdrawn(_, _) => {
+                return Err(Error {
+                    kind: Kind::ApplicationFailure("Overdrawn".to_owned()),
+                })
+            }
+        };
+        Ok(AccountData {
+            balance,
+            generation: state.generation + 1,
+        })
+    }
+
+    fn handle_command(state: &Self::State, cmd: &Self::Command) -> Result<Vec<Self::Event>> {
+        match *cmd {
+            BankCommand::DepositFunds(ref acct, amt) => {
+                Ok(vec![BankEvent::FundsDeposited(acct.clone(), amt)])
+            }
+            BankCommand::WithdrawFunds(ref acct, amt) if amt <= state.balance => {
+                Ok(vec![BankEvent::FundsWithdrawn(acct.clone(), amt)])
+            }
+            BankCommand::WithdrawFunds(_, _) => Err(Error {
+                kind: Kind::CommandFailure("Insufficient funds".to_owned()),
+            }),
+        }
+    }
+}
+
+fn deposited(amt: u32) -> BankEvent {
+    BankEvent::FundsDeposited("SAVINGS100".to_owned(), amt)
+}
+
+#[test]
+fn expected_events_return_new_state() {
+    let state = AggregateTest::<Account>::given(vec![deposited(100), deposited(50)])
+        .when(BankCommand::WithdrawFunds("SAVINGS100".to_owned(), 120))
+        .then_expect_events(vec![BankEvent::FundsWithdrawn(
+            "SAVINGS100".to_owned(),
+            120,
+        )]);
+
+    assert_eq!(state.balance, 30);
+    assert_eq!(state.generation, 3);
+}
+
+#[test]
+fn given_state_starts_from_explicit_state() {
+    let initial = AccountData {
+        balance: 10,
+        generation: 7,
+    };
+    let state = AggregateTest::<Account>::given_state(initial, vec![])
+        .when(BankCommand::DepositFunds("SAVINGS100".to_owned(), 5))
+        .then_expect_events(vec![deposited(5)]);
+
+    assert_eq!(state.balance, 15);
+    assert_eq!(state.generation, 8);
+}
+
+#[test]
+fn expected_error() {
+    AggregateTest::<Account>::given(vec![deposited(100)])
+        .when(BankCommand::WithdrawFunds("SAVINGS100".to_owned(), 101))
+        .then_expect_error(Kind::CommandFailure("Insufficient funds".to_owned()));
+}
+
+#[test]
+#[should_panic(expected = r#"- [0] bankevent.fundsdeposited {"FundsDeposited":["SAVINGS100",6]}"#)]
+fn mismatched_payload_is_reported() {
+    AggregateTest::<Account>::given(vec![])
+        .when(BankCommand::DepositFunds("SAVINGS100".to_owned(), 5))
+        .then_expect_events(vec![deposited(6)]);
+}
+
+#[test]
+#[should_panic(expected = "- [1] bankevent.fundsdeposited")]
+fn missing_event_is_reported() {
+    AggregateTest::<Account>::given(vec![])
+        .when(BankCommand::DepositFunds("SAVINGS100".to_owned(), 5))
+        .then_expect_events(vec![deposited(5), deposited(5)]);
+}
+
+#[test]
+#[should_panic(expected = "but the command produced 1 event(s)")]
+fn unexpected_success_is_reported() {
+    AggregateTest::<Account>::given(vec![])
+        .when(BankCommand::DepositFunds("SAVINGS100".to_owned(), 5))
+        .then_expect_error(Kind::CommandFailure("Insufficient funds".to_owned()));
+}
+
+#[test]
+#[should_panic(expected = "Failed to apply the given events: Overdrawn")]
+fn failing_history_is_reported() {
+    AggregateTest::<Account>::given(vec![BankEvent::FundsWithdrawn("SAVINGS100".to_owned(), 1)]);
+}